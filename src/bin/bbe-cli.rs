@@ -0,0 +1,318 @@
+//! Inspects and converts map files without opening a window, e.g. on CI machines.
+
+use building_blocks_editor::{
+    export_meshes, open_map, read_vox, sdf_to_vox_model, vox_model_to_sdf, write_gltf,
+    write_map_with_compression, write_vox, Config, MapFile, MapStats, PaletteAsset,
+    ThreadLocalVoxelCache, VoxFile, VoxelType, EMPTY_VOXEL_TYPE,
+};
+
+use anyhow::{anyhow, bail, Context};
+use building_blocks::{
+    prelude::*,
+    storage::{Lz4, Sd8},
+};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+const CONFIG_FILE: &str = "config.ron";
+const ASSETS_DIR: &str = "assets";
+
+const USAGE: &str = "\
+Usage: bbe-cli <command> [args]
+
+Commands:
+    stats <map>
+        Print the number of chunks, their compressed and decompressed sizes, and the number of voxels of each type.
+    convert <input> <output>
+        Convert between map files, MagicaVoxel .vox models, and .glb or .gltf meshes (output only). The format is chosen
+        by the file extension, and .vox files use the `vox` mapping in config.ron.
+    crop <input> <output> <min_x> <min_y> <min_z> <shape_x> <shape_y> <shape_z>
+        Remove every voxel outside of the given extent.
+    bounds <input> <output> [<min_x> <min_y> <min_z> <shape_x> <shape_y> <shape_z>]
+        Make the map finite by setting its world bounds, or infinite by leaving out the extent. Voxels outside of the
+        bounds are removed.
+    recompress <input> <output> <level>
+        Rewrite a map file with a different LZ4 compression level.
+    validate-palette [palette] [map]
+        Check a palette asset (config.ron's palette by default) and, optionally, that a map file was saved with it.
+";
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let config = read_config()?;
+
+    match args.as_slice() {
+        ["stats", map] => stats(&config, map),
+        ["convert", input, output] => convert(&config, input, output),
+        ["crop", input, output, extent @ ..] if extent.len() == 6 => {
+            let extent = parse_extent(extent)?;
+            let mut map_file = open_map(input).with_context(|| format!("Reading {}", input))?;
+            map_file.crop(&extent);
+            println!("Cropped to {} chunks", map_file.chunks.len());
+            write_map_file(output, map_file, config.chunk_cache.compression())
+        }
+        ["bounds", input, output, extent @ ..] if extent.is_empty() || extent.len() == 6 => {
+            let mut map_file = open_map(input).with_context(|| format!("Reading {}", input))?;
+            if extent.is_empty() {
+                map_file.bounds = None;
+                println!("Removed the world bounds");
+            } else {
+                let extent = parse_extent(extent)?;
+                map_file.crop(&extent);
+                map_file.bounds = Some(extent);
+                println!("Bounded to {} chunks", map_file.chunks.len());
+            }
+            write_map_file(output, map_file, config.chunk_cache.compression())
+        }
+        ["recompress", input, output, level] => {
+            let level = level
+                .parse()
+                .with_context(|| format!("Invalid compression level {}", level))?;
+            let map_file = open_map(input).with_context(|| format!("Reading {}", input))?;
+            let before = map_file.compressed_bytes;
+            write_map_file(output, map_file, Lz4 { level })?;
+            let after = open_map(output)?.compressed_bytes;
+            println!("Compressed chunks: {} -> {} bytes", before, after);
+
+            Ok(())
+        }
+        ["validate-palette"] => validate_palette(&palette_path(&config), None),
+        ["validate-palette", palette] => validate_palette(Path::new(palette), None),
+        ["validate-palette", palette, map] => validate_palette(Path::new(palette), Some(*map)),
+        _ => {
+            eprint!("{}", USAGE);
+            bail!("Invalid arguments");
+        }
+    }
+}
+
+fn read_config() -> anyhow::Result<Config> {
+    if Path::new(CONFIG_FILE).exists() {
+        Ok(Config::read_file(CONFIG_FILE)?)
+    } else {
+        Ok(Config::default())
+    }
+}
+
+/// The palette asset, which the editor loads from the assets directory.
+fn palette_path(config: &Config) -> std::path::PathBuf {
+    Path::new(ASSETS_DIR).join(&config.palette)
+}
+
+fn read_palette_asset(path: &Path) -> anyhow::Result<PaletteAsset> {
+    let reader = File::open(path).with_context(|| format!("Opening {}", path.display()))?;
+    let palette =
+        ron::de::from_reader(reader).with_context(|| format!("Reading {}", path.display()))?;
+
+    Ok(palette)
+}
+
+fn stats(config: &Config, map: &str) -> anyhow::Result<()> {
+    let map_file = open_map(map).with_context(|| format!("Reading {}", map))?;
+    let stats = MapStats::new(&map_file);
+    // Names are nice to have, but the counts don't depend on them.
+    let palette = read_palette_asset(&palette_path(config)).ok();
+
+    println!("Chunk shape: {:?}", map_file.chunk_shape.0);
+    match map_file.bounds {
+        Some(b) => println!("World bounds: {:?} + {:?}", b.minimum.0, b.shape.0),
+        None => println!("World bounds: none"),
+    }
+    println!("Chunks: {}", stats.num_chunks);
+    println!("Compressed bytes: {}", stats.compressed_bytes);
+    println!("Decompressed bytes: {}", stats.decompressed_bytes);
+    println!("Voxels:");
+    for (voxel_type, count) in stats.used_voxel_types() {
+        let name = palette
+            .as_ref()
+            .and_then(|p| p.voxel_types.get(voxel_type.0 as usize))
+            .map_or("?", |t| t.name.as_str());
+        println!("    {:>3} {:<12} {}", voxel_type.0, name, count);
+    }
+
+    Ok(())
+}
+
+enum FileFormat {
+    Map,
+    Vox,
+    Gltf,
+}
+
+fn file_format(path: &str) -> FileFormat {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("vox") => FileFormat::Vox,
+        Some("glb") | Some("gltf") => FileFormat::Gltf,
+        _ => FileFormat::Map,
+    }
+}
+
+fn convert(config: &Config, input: &str, output: &str) -> anyhow::Result<()> {
+    let map_file = match file_format(input) {
+        FileFormat::Map => open_map(input).with_context(|| format!("Reading {}", input))?,
+        FileFormat::Vox => read_vox_as_map(config, input)?,
+        FileFormat::Gltf => bail!("Meshes can't be converted back into voxels"),
+    };
+
+    match file_format(output) {
+        FileFormat::Map => write_map_file(output, map_file, config.chunk_cache.compression()),
+        FileFormat::Vox => write_map_as_vox(config, map_file, output),
+        FileFormat::Gltf => {
+            let map = map_file.into_voxel_map(config.chunk_cache.compression());
+            let meshes = export_meshes(
+                &map,
+                &ThreadLocalVoxelCache::new(),
+                None,
+                config.export.merge_chunks,
+            );
+            write_gltf(output, &meshes).with_context(|| format!("Writing {}", output))?;
+            println!("Exported {} meshes to {}", meshes.len(), output);
+
+            Ok(())
+        }
+    }
+}
+
+fn read_vox_as_map(config: &Config, path: &str) -> anyhow::Result<MapFile> {
+    let palette = read_palette_asset(&palette_path(config))?;
+    let type_map = config.vox.mapping.resolve(&palette)?;
+
+    let file =
+        read_vox(BufReader::new(File::open(path)?)).with_context(|| format!("Reading {}", path))?;
+    let model = file
+        .models
+        .get(config.vox.model_index)
+        .ok_or_else(|| anyhow!("{} has no model {}", path, config.vox.model_index))?;
+    let voxels = vox_model_to_sdf(model, &type_map, PointN([0; 3]));
+
+    Ok(MapFile::from_sdf_array(
+        PointN(config.chunk_shape),
+        palette.voxel_palette()?,
+        &voxels,
+    ))
+}
+
+fn write_map_as_vox(config: &Config, map_file: MapFile, path: &str) -> anyhow::Result<()> {
+    let palette = read_palette_asset(&palette_path(config))?;
+    let type_map = config.vox.mapping.resolve(&palette)?;
+
+    // Finite maps are exported with their bounds, even if they're partly empty.
+    let extent = map_file
+        .bounds
+        .or_else(|| solid_extent(&map_file))
+        .ok_or_else(|| anyhow!("The map is empty"))?;
+    let map = map_file.into_voxel_map(config.chunk_cache.compression());
+    let voxels = map.copy_extent_without_caching(extent);
+    let model = sdf_to_vox_model(&voxels, &map.palette, &type_map)?;
+    println!(
+        "Exporting {:?} as a {:?} model",
+        extent.minimum.0, model.size
+    );
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_vox(
+        &mut writer,
+        &VoxFile {
+            models: vec![model],
+            palette: None,
+        },
+    )?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// The smallest extent containing every solid voxel of the map.
+fn solid_extent(map_file: &MapFile) -> Option<Extent3i> {
+    let mut bounds: Option<([i32; 3], [i32; 3])> = None;
+    for (_, chunk) in map_file.chunks.iter() {
+        chunk.for_each(
+            chunk.extent(),
+            |p: Point3i, (v_type, v_dist): (VoxelType, Sd8)| {
+                if v_type == EMPTY_VOXEL_TYPE || v_dist.0 >= 0 {
+                    return;
+                }
+                let (min, max) = bounds.get_or_insert((p.0, p.0));
+                for ((lo, hi), &x) in min.iter_mut().zip(max.iter_mut()).zip(p.0.iter()) {
+                    *lo = (*lo).min(x);
+                    *hi = (*hi).max(x);
+                }
+            },
+        );
+    }
+
+    bounds.map(|(min, max)| Extent3i::from_min_and_max(PointN(min), PointN(max)))
+}
+
+fn parse_extent(args: &[&str]) -> anyhow::Result<Extent3i> {
+    let mut values = [0; 6];
+    for (value, arg) in values.iter_mut().zip(args.iter()) {
+        *value = arg
+            .parse()
+            .with_context(|| format!("Invalid coordinate {}", arg))?;
+    }
+    let [min_x, min_y, min_z, shape_x, shape_y, shape_z] = values;
+    if shape_x <= 0 || shape_y <= 0 || shape_z <= 0 {
+        bail!("The extent's shape must be positive");
+    }
+
+    Ok(Extent3i::from_min_and_shape(
+        PointN([min_x, min_y, min_z]),
+        PointN([shape_x, shape_y, shape_z]),
+    ))
+}
+
+fn write_map_file(path: &str, map_file: MapFile, compression: Lz4) -> anyhow::Result<()> {
+    let map = map_file.into_voxel_map(compression);
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_map_with_compression(&mut writer, &map, compression)
+        .with_context(|| format!("Writing {}", path))?;
+    writer.flush()?;
+    println!("Wrote {}", path);
+
+    Ok(())
+}
+
+fn validate_palette(path: &Path, map: Option<&str>) -> anyhow::Result<()> {
+    let palette = read_palette_asset(path)?;
+    let voxel_palette = palette
+        .voxel_palette()
+        .with_context(|| format!("Validating {}", path.display()))?;
+    for texture in palette.base_color_textures.iter() {
+        let texture_path = Path::new(ASSETS_DIR).join(texture);
+        if !texture_path.exists() {
+            bail!("Missing base color texture {}", texture_path.display());
+        }
+    }
+    println!(
+        "{}: {} voxel types, {} material layers",
+        path.display(),
+        voxel_palette.infos.len(),
+        palette.material_layers.len()
+    );
+
+    if let Some(map) = map {
+        let map_file = open_map(map).with_context(|| format!("Reading {}", map))?;
+        if map_file.palette != voxel_palette {
+            bail!("{} was saved with a different palette", map);
+        }
+        let stats = MapStats::new(&map_file);
+        if let Some((voxel_type, _)) = stats
+            .used_voxel_types()
+            .find(|(t, _)| t.0 as usize >= voxel_palette.infos.len())
+        {
+            bail!("{} uses undefined voxel type {}", map, voxel_type.0);
+        }
+        println!("{} matches the palette", map);
+    }
+
+    Ok(())
+}
@@ -1,4 +1,6 @@
-use building_blocks_editor::{BevyPlugins, Config, EditorPlugin};
+use building_blocks_editor::{
+    has_recovery_data, BevyPlugins, Config, ConfigError, EditorPlugin, RecoverMap,
+};
 
 use bevy::{
     app::prelude::*,
@@ -7,8 +9,9 @@ use bevy::{
     window::WindowDescriptor,
 };
 
-fn main() -> Result<(), ron::Error> {
+fn main() -> Result<(), ConfigError> {
     let config = Config::read_file("config.ron")?;
+    let recover = has_recovery_data(&config.map_file) && ask_to_recover(&config.map_file);
 
     let window_desc = WindowDescriptor {
         width: 1600.0,
@@ -30,11 +33,25 @@ fn main() -> Result<(), ron::Error> {
             ..Default::default()
         })
         .insert_resource(WireframeConfig { global: true })
-        .insert_resource(config)
-        .add_plugins(BevyPlugins::new(config))
+        .insert_resource(config.clone())
+        .insert_resource(RecoverMap(recover))
+        .add_plugins(BevyPlugins::new(config.clone()))
         // Editor stuff.
-        .add_plugin(EditorPlugin)
+        .add_plugin(EditorPlugin::new(config))
         .run();
 
     Ok(())
 }
+
+fn ask_to_recover(map_file: &str) -> bool {
+    println!(
+        "Found unsaved edits to {} from a previous session. Recover them? [y/N]",
+        map_file
+    );
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
@@ -23,7 +23,7 @@ impl StatePlugin for BVTPlugin {
     }
 
     fn add_update_systems(set: SystemSet) -> SystemSet {
-        set.with_system(octree_generator_system.system())
+        set.with_system(octree_generator_system.system().label("voxel_bvt"))
     }
 }
 
@@ -96,7 +96,7 @@ fn generate_octree_for_each_chunk(
                 let cache_tls = local_caches.get();
                 let reader = map.reader(&cache_tls);
                 let chunk = reader.get_chunk(ChunkKey::new(0, chunk_min)).unwrap();
-                let transform_chunk = TransformMap::new(chunk, map.voxel_info_transform());
+                let transform_chunk = TransformMap::new(&*chunk, map.voxel_info_transform());
 
                 (
                     chunk_min,
@@ -23,7 +23,7 @@ impl Plugin for CursorRayPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.insert_resource(CursorRay::default())
             .insert_resource(CursorRayCalculator::default())
-            .add_system(cursor_ray_system.system());
+            .add_system(cursor_ray_system.system().label("cursor_ray"));
     }
 }
 
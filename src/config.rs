@@ -1,23 +1,289 @@
+use crate::{
+    is_valid_chunk_shape, ChunkCacheConfig, CsgOperation, LodConfig, VoxMapping,
+    DEFAULT_CHUNK_SHAPE,
+};
+
+use building_blocks::core::{Extent3i, PointN};
+
 use serde::Deserialize;
 
 use smooth_bevy_cameras::controllers::{
     orbit::OrbitCameraController, unreal::UnrealCameraController,
 };
 
-#[derive(Clone, Copy, Deserialize, Default)]
+#[derive(Clone, Deserialize)]
 pub struct Config {
     pub wireframes: bool,
     pub camera: CameraConfig,
+    /// The map file that gets opened on startup and written when saving.
+    #[serde(default = "default_map_file")]
+    pub map_file: String,
+    /// The palette asset that defines the map's voxel types and material layers.
+    #[serde(default = "default_palette")]
+    pub palette: String,
+    /// The rules that smart tiling applies to the selection.
+    #[serde(default = "default_smart_tile_rules")]
+    pub smart_tile_rules: String,
+    /// How often the map is autosaved next to `map_file`, which also truncates the edit journal.
+    #[serde(default = "default_autosave_interval_secs")]
+    pub autosave_interval_secs: f64,
+    /// The chunk shape of new maps, which must be a power of two along each axis. Existing maps keep the shape they were saved
+    /// with.
+    #[serde(default = "default_chunk_shape")]
+    pub chunk_shape: [i32; 3],
+    /// The world bounds of new maps. Existing maps keep the bounds they were saved with.
+    #[serde(default)]
+    pub world_bounds: Option<WorldBoundsConfig>,
+    #[serde(default)]
+    pub chunk_cache: ChunkCacheConfig,
+    #[serde(default)]
+    pub lod: LodConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
+    #[serde(default)]
+    pub heightmap: HeightmapConfig,
+    #[serde(default)]
+    pub vox: VoxConfig,
+    #[serde(default)]
+    pub obj: ObjConfig,
+    #[serde(default)]
+    pub session: SessionConfig,
+}
+
+fn default_map_file() -> String {
+    "map.bbmap".to_string()
+}
+
+fn default_palette() -> String {
+    "default.palette.ron".to_string()
+}
+
+fn default_smart_tile_rules() -> String {
+    "smart_tiles.ron".to_string()
+}
+
+fn default_autosave_interval_secs() -> f64 {
+    120.0
+}
+
+fn default_chunk_shape() -> [i32; 3] {
+    DEFAULT_CHUNK_SHAPE.0
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            wireframes: false,
+            camera: Default::default(),
+            map_file: default_map_file(),
+            palette: default_palette(),
+            smart_tile_rules: default_smart_tile_rules(),
+            autosave_interval_secs: default_autosave_interval_secs(),
+            chunk_shape: default_chunk_shape(),
+            world_bounds: None,
+            chunk_cache: Default::default(),
+            lod: Default::default(),
+            export: Default::default(),
+            heightmap: Default::default(),
+            vox: Default::default(),
+            obj: Default::default(),
+            session: Default::default(),
+        }
+    }
 }
 
 impl Config {
-    pub fn read_file(path: &str) -> Result<Self, ron::Error> {
+    pub fn read_file(path: &str) -> Result<Self, ConfigError> {
         let reader = std::fs::File::open(path)?;
+        let config: Self = ron::de::from_reader(reader)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !is_valid_chunk_shape(PointN(self.chunk_shape)) {
+            return Err(ConfigError::BadChunkShape(self.chunk_shape));
+        }
+        if self.lod.num_lods == 0 {
+            return Err(ConfigError::NoLods);
+        }
+        if let Some(bounds) = &self.world_bounds {
+            if bounds.shape.iter().any(|&s| s <= 0) {
+                return Err(ConfigError::BadWorldBounds(bounds.shape));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    BadChunkShape([i32; 3]),
+    NoLods,
+    BadWorldBounds([i32; 3]),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Ron(e) => write!(f, "{}", e),
+            ConfigError::BadChunkShape(s) => write!(
+                f,
+                "Chunk shape {:?} must be a power of two along each axis",
+                s
+            ),
+            ConfigError::NoLods => write!(f, "There must be at least one level of detail"),
+            ConfigError::BadWorldBounds(s) => {
+                write!(f, "World bounds shape {:?} must be positive", s)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
 
-        ron::de::from_reader(reader)
+impl From<ron::Error> for ConfigError {
+    fn from(e: ron::Error) -> Self {
+        ConfigError::Ron(e)
     }
 }
 
+/// A finite extent of the world. Voxels can't be edited outside of it.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct WorldBoundsConfig {
+    pub min: [i32; 3],
+    pub shape: [i32; 3],
+}
+
+impl WorldBoundsConfig {
+    pub fn extent(&self) -> Extent3i {
+        Extent3i::from_min_and_shape(PointN(self.min), PointN(self.shape))
+    }
+}
+
+/// Where and how meshes are exported.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// A `.glb` or `.gltf` file.
+    pub path: String,
+    /// Merge all of the chunk meshes into a single mesh, instead of one mesh per chunk.
+    pub merge_chunks: bool,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            path: "map.glb".to_string(),
+            merge_chunks: false,
+        }
+    }
+}
+
+/// Where and how a heightmap is imported as terrain.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct HeightmapConfig {
+    /// A grayscale 8 or 16-bit PNG.
+    pub path: String,
+    /// The voxel at the minimum corner of the terrain. The lowest possible height is at `origin[1]`.
+    pub origin: [i32; 3],
+    /// Voxels per pixel along X and Z.
+    pub horizontal_scale: f32,
+    /// Voxels from the lowest possible height to the highest.
+    pub vertical_scale: f32,
+}
+
+impl Default for HeightmapConfig {
+    fn default() -> Self {
+        Self {
+            path: "heightmap.png".to_string(),
+            origin: [0, 0, 0],
+            horizontal_scale: 1.0,
+            vertical_scale: 64.0,
+        }
+    }
+}
+
+/// Where MagicaVoxel models are imported from and exported to.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct VoxConfig {
+    pub import_path: String,
+    /// Which model of the imported file to use.
+    pub model_index: usize,
+    /// The voxel at the minimum corner of the imported model.
+    pub origin: [i32; 3],
+    pub export_path: String,
+    /// The minimum corner of the exported extent.
+    pub export_min: [i32; 3],
+    /// The shape of the exported extent. MagicaVoxel supports at most 256 voxels per axis.
+    pub export_shape: [i32; 3],
+    pub mapping: VoxMapping,
+}
+
+impl Default for VoxConfig {
+    fn default() -> Self {
+        Self {
+            import_path: "import.vox".to_string(),
+            model_index: 0,
+            origin: [0, 0, 0],
+            export_path: "export.vox".to_string(),
+            export_min: [0, 0, 0],
+            export_shape: [64, 64, 64],
+            mapping: Default::default(),
+        }
+    }
+}
+
+/// Where and how an OBJ mesh is voxelized into the map.
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ObjConfig {
+    /// A watertight OBJ mesh.
+    pub path: String,
+    /// Where the mesh's origin ends up in the map.
+    pub origin: [i32; 3],
+    /// Voxels per mesh unit.
+    pub scale: f32,
+    /// The name of the palette's voxel type for the inside of the mesh.
+    pub voxel_type: String,
+    pub operation: CsgOperation,
+}
+
+impl Default for ObjConfig {
+    fn default() -> Self {
+        Self {
+            path: "import.obj".to_string(),
+            origin: [0, 0, 0],
+            scale: 1.0,
+            voxel_type: "rock".to_string(),
+            operation: CsgOperation::Union,
+        }
+    }
+}
+
+/// Where editing sessions are recorded to and replayed from.
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Every frame of editing is recorded to this file.
+    pub record_path: Option<String>,
+    /// The edit tools replay this file instead of reading keyboard and mouse input.
+    pub replay_path: Option<String>,
+}
+
 #[derive(Clone, Copy, Deserialize)]
 pub enum CameraConfig {
     Unreal(UnrealCameraController),
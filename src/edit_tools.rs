@@ -1,32 +1,76 @@
+mod clipboard;
 mod drag_face;
 mod edit_timeline;
+mod paint_material;
 mod plugin;
 mod selection;
+mod slope;
+mod smart_tiles;
 mod snapshotting_editor;
 mod terraformer;
+mod tile;
 mod tool_switcher;
 mod undo;
 
+pub use clipboard::{Clipboard, ClipboardEvents, PasteMode};
+pub use drag_face::DragFaceEvents;
+pub use paint_material::MaterialPainterEvents;
 pub use plugin::EditToolsPlugin;
+pub use selection::{SelectionEvents, SelectionState};
+pub use slope::{SlopeEdge, SlopeEvents, SlopeMode};
+pub use smart_tiles::{
+    Prefab, PrefabVoxel, SmartTileError, SmartTileEvents, SmartTileRule, SmartTileRuleSet,
+    SmartTileRules, TileAction, TileMatch, TilePattern,
+};
+pub use terraformer::{BrushShape, TerraformerEvents};
+pub use tile::TileEvents;
+pub use tool_switcher::ToolSwitchEvents;
+pub use undo::UndoEvents;
 
 use drag_face::DragFaceState;
+use slope::SlopeState;
 use snapshotting_editor::SnapshottingVoxelEditor;
+use tile::TileState;
+
+use bevy::ecs::prelude::*;
+use smooth_bevy_cameras::controllers::unreal::UnrealCameraController;
 
 pub enum CurrentTool {
     DragFace(DragFaceState),
     Terraform,
-    PaintMaterial, // TODO
-    Tile,          // TODO: tile the current buffer by dragging; replaces DragFace
-    Slope,         // TODO: select two edges to slope between
+    Paste,
+    PaintMaterial,
+    Tile(TileState),
+    Slope(SlopeState),
 }
 
-// TODO: 3D selection; like the drag face tool, but you drag to size the 3rd dimension of the
-// selection. Move the selection by dragging a face. Allow visibility masking so you can only see
-// the voxels in the selection.
+impl CurrentTool {
+    /// Puts the tool back in the state that it starts in, giving up on any edit that it's in the middle of. Tools turn off the
+    /// mouse camera while they drag, so it's turned back on. The caller must abort the `EditTransaction`, or the tool's preview
+    /// would be committed by whichever tool commits next.
+    fn stop_editing(
+        &mut self,
+        selection_state: &mut SelectionState,
+        mouse_camera_controllers: &mut Query<&mut UnrealCameraController>,
+    ) {
+        match self {
+            CurrentTool::DragFace(state) => {
+                if let DragFaceState::DraggingFace { .. } = state {
+                    *selection_state = SelectionState::SelectingFirstCorner;
+                }
+                *state = DragFaceState::SelectionReady;
+            }
+            CurrentTool::Tile(state) => *state = TileState::Ready,
+            CurrentTool::Slope(state) => *state = SlopeState::default(),
+            CurrentTool::Terraform | CurrentTool::Paste | CurrentTool::PaintMaterial => {}
+        }
+        if let Some(mut controller) = mouse_camera_controllers.iter_mut().next() {
+            controller.enabled = true;
+        }
+    }
+}
 
-// TODO: copy current selection to buffer
+// TODO: move the 3D selection by dragging it. Allow visibility masking so you can only see the
+// voxels in the selection.
 
 // TODO: render SDF
-
-// TODO: smart tools; given some map palette and constraints, you can carve out section of map, and
-// tiles get placed automatically, e.g. walls, floors, doors, and stairs are detected
@@ -0,0 +1,367 @@
+use super::{selection::SelectionState, CurrentTool, DragFaceState, SnapshottingVoxelEditor};
+
+use crate::{
+    ambient_sdf_array,
+    geometry::{offset_transform, point3i_serde},
+    picking::{VoxelCursor, VoxelFace},
+    ImmediateModeTag, SdfArray, SdfVoxelMap, SdfVoxelPalette, VoxelType, EMPTY_SDF_VOXEL,
+};
+
+use bevy::{
+    asset::prelude::*,
+    ecs::prelude::*,
+    input::prelude::*,
+    pbr::prelude::*,
+    prelude::{EventReader, EventWriter},
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        pipeline::PrimitiveTopology,
+        prelude::*,
+    },
+};
+use building_blocks::{mesh::surface_nets::*, prelude::*};
+use serde::{Deserialize, Serialize};
+
+/// Voxels copied out of the `SdfVoxelMap`, waiting to be pasted.
+#[derive(Default)]
+pub struct Clipboard {
+    /// Keeps the extent that the voxels were copied from. Pasting translates them.
+    voxels: Option<SdfArray>,
+    /// The surface of `voxels`, in the coordinates they were copied from.
+    preview_mesh: Option<Handle<Mesh>>,
+    paste_mode: PasteMode,
+}
+
+impl Clipboard {
+    pub fn voxels(&self) -> Option<&SdfArray> {
+        self.voxels.as_ref()
+    }
+
+    pub fn paste_mode(&self) -> PasteMode {
+        self.paste_mode
+    }
+}
+
+/// How pasted voxels are combined with the voxels already in the map.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum PasteMode {
+    /// Every voxel in the pasted extent is overwritten.
+    Replace,
+    /// Each voxel keeps whichever of the pasted and existing voxels has the smaller signed distance.
+    Union,
+    /// Only the non-empty voxels of the clipboard are written.
+    IgnoreEmpty,
+}
+
+impl Default for PasteMode {
+    fn default() -> Self {
+        PasteMode::Replace
+    }
+}
+
+impl PasteMode {
+    fn next(self) -> Self {
+        match self {
+            PasteMode::Replace => PasteMode::Union,
+            PasteMode::Union => PasteMode::IgnoreEmpty,
+            PasteMode::IgnoreEmpty => PasteMode::Replace,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ClipboardEvents {
+    Copy,
+    Cut,
+    StartPaste,
+    ChangePasteMode(PasteMode),
+    /// Paste the clipboard with its minimum at this point.
+    Paste(#[serde(with = "point3i_serde")] Point3i),
+    CancelPaste,
+}
+
+pub fn clipboard_default_input_map(
+    keyboard: Res<Input<KeyCode>>,
+    voxel_cursor: VoxelCursor,
+    current_tool: Res<CurrentTool>,
+    clipboard: Res<Clipboard>,
+    mut events: EventWriter<ClipboardEvents>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    if ctrl {
+        if keyboard.just_pressed(KeyCode::C) {
+            events.send(ClipboardEvents::Copy);
+        } else if keyboard.just_pressed(KeyCode::X) {
+            events.send(ClipboardEvents::Cut);
+        } else if keyboard.just_pressed(KeyCode::V) {
+            events.send(ClipboardEvents::StartPaste);
+        }
+    }
+
+    if let CurrentTool::Paste = *current_tool {
+    } else {
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Tab) {
+        events.send(ClipboardEvents::ChangePasteMode(
+            clipboard.paste_mode.next(),
+        ));
+    }
+    if keyboard.just_pressed(KeyCode::Escape) {
+        events.send(ClipboardEvents::CancelPaste);
+    } else if let Some(face) = voxel_cursor.voxel_just_clicked(MouseButton::Left) {
+        if let Some(voxels) = &clipboard.voxels {
+            events.send(ClipboardEvents::Paste(paste_min(
+                voxels.extent().shape,
+                &face,
+            )));
+        }
+    }
+}
+
+pub fn clipboard_system(
+    mut current_tool: ResMut<CurrentTool>,
+    mut clipboard: ResMut<Clipboard>,
+    mut voxel_editor: SnapshottingVoxelEditor,
+    voxel_map: Res<SdfVoxelMap>,
+    selection_state: Res<SelectionState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut events: EventReader<ClipboardEvents>,
+) {
+    for event in events.iter() {
+        match event {
+            ClipboardEvents::Copy | ClipboardEvents::Cut => {
+                let extent = if let Some(extent) = selection_state.selected_extent() {
+                    voxel_map.clip_to_bounds(extent)
+                } else {
+                    println!("Nothing is selected to copy");
+                    continue;
+                };
+                if extent.is_empty() {
+                    continue;
+                }
+
+                let voxels = voxel_map.copy_extent_without_caching(extent);
+                clipboard.preview_mesh = clipboard_surface_mesh(&voxels).map(|m| meshes.add(m));
+                clipboard.voxels = Some(voxels);
+
+                if let ClipboardEvents::Cut = event {
+                    voxel_editor.edit_extent_and_touch_neighbors(extent, |_p, (v_type, v_dist)| {
+                        *v_type = EMPTY_SDF_VOXEL.0;
+                        *v_dist = EMPTY_SDF_VOXEL.1;
+                    });
+                    voxel_editor.finish_edit();
+                }
+            }
+            ClipboardEvents::StartPaste => {
+                if clipboard.voxels.is_some() {
+                    *current_tool = CurrentTool::Paste;
+                } else {
+                    println!("The clipboard is empty");
+                }
+            }
+            ClipboardEvents::ChangePasteMode(mode) => {
+                println!("Paste mode: {:?}", mode);
+                clipboard.paste_mode = *mode;
+            }
+            ClipboardEvents::Paste(min) => {
+                let voxels = if let Some(v) = &clipboard.voxels {
+                    v
+                } else {
+                    continue;
+                };
+                let offset = *min - voxels.extent().minimum;
+                let paste_extent = Extent3i::from_min_and_shape(*min, voxels.extent().shape);
+                let mode = clipboard.paste_mode;
+                let palette = &voxel_map.palette;
+                voxel_editor.edit_extent_and_touch_neighbors(paste_extent, |p, dst| {
+                    paste_voxel(mode, voxels.get(p - offset), dst, palette);
+                });
+                voxel_editor.finish_edit();
+            }
+            ClipboardEvents::CancelPaste => {
+                if let CurrentTool::Paste = *current_tool {
+                    *current_tool = CurrentTool::DragFace(DragFaceState::SelectionReady);
+                }
+            }
+        }
+    }
+}
+
+/// Where the minimum of a clipboard with `shape` goes when it's pasted onto `face`: centered on the face's voxel, and stacked on
+/// top of the face.
+pub fn paste_min(shape: Point3i, face: &VoxelFace) -> Point3i {
+    let mut min = face.point - PointN([shape.x() / 2, shape.y() / 2, shape.z() / 2]);
+    let axis = face.normal.axis;
+    let outside = face.point.axis_component(axis) + face.normal.sign;
+    *min.axis_component_mut(axis) = if face.normal.sign > 0 {
+        outside
+    } else {
+        outside - shape.axis_component(axis) + 1
+    };
+
+    min
+}
+
+pub(super) fn paste_voxel(
+    mode: PasteMode,
+    src: (VoxelType, Sd8),
+    (v_type, v_dist): (&mut VoxelType, &mut Sd8),
+    palette: &SdfVoxelPalette,
+) {
+    let write = match mode {
+        PasteMode::Replace => true,
+        PasteMode::Union => src.1 .0 < v_dist.0,
+        PasteMode::IgnoreEmpty => !palette.get_voxel_type_info(src.0).is_empty,
+    };
+    if write {
+        *v_type = src.0;
+        *v_dist = src.1;
+    }
+}
+
+pub struct ClipboardPreviewMaterial(pub Handle<StandardMaterial>);
+
+pub fn initialize_clipboard(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(Clipboard::default());
+
+    let mut color = Color::CYAN;
+    color.set_a(0.5);
+    let material = ClipboardPreviewMaterial(materials.add(StandardMaterial::from(color)));
+    commands.insert_resource(material);
+}
+
+/// While pasting, shows the clipboard where it would be pasted.
+pub fn clipboard_preview_system(
+    mut commands: Commands,
+    current_tool: Res<CurrentTool>,
+    clipboard: Res<Clipboard>,
+    material: Res<ClipboardPreviewMaterial>,
+    voxel_cursor: VoxelCursor,
+) {
+    if let CurrentTool::Paste = *current_tool {
+    } else {
+        return;
+    }
+
+    let (voxels, mesh) = match (&clipboard.voxels, &clipboard.preview_mesh) {
+        (Some(voxels), Some(mesh)) => (voxels, mesh.clone()),
+        _ => return,
+    };
+    let face = if let Some(face) = voxel_cursor.voxel_face() {
+        face
+    } else {
+        return;
+    };
+
+    let offset = paste_min(voxels.extent().shape, &face) - voxels.extent().minimum;
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh,
+            material: material.0.clone(),
+            ..Default::default()
+        })
+        .insert(offset_transform(Point3f::from(offset)))
+        .insert(ImmediateModeTag);
+}
+
+/// The surface of `voxels`, closed off where it meets the edges of the array.
+fn clipboard_surface_mesh(voxels: &SdfArray) -> Option<Mesh> {
+    let padded_extent = voxels.extent().padded(1);
+    let mut padded = ambient_sdf_array(padded_extent);
+    copy_extent(voxels.extent(), voxels, &mut padded);
+
+    let mut buffer = SurfaceNetsBuffer::default();
+    let sdf = TransformMap::new(&padded, |(_type, dist): (VoxelType, Sd8)| dist);
+    surface_nets(&sdf, &padded_extent, 1.0, &mut buffer);
+    if buffer.mesh.indices.is_empty() {
+        return None;
+    }
+
+    let mesh = buffer.mesh;
+    let num_vertices = mesh.positions.len();
+    let mut render_mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    render_mesh.set_attribute(
+        "Vertex_Position",
+        VertexAttributeValues::Float3(mesh.positions),
+    );
+    render_mesh.set_attribute("Vertex_Normal", VertexAttributeValues::Float3(mesh.normals));
+    // HACK: We have to provide UVs, even though we don't use them.
+    render_mesh.set_attribute(
+        "Vertex_Uv",
+        VertexAttributeValues::Float2(vec![[0.0; 2]; num_vertices]),
+    );
+    render_mesh.set_indices(Some(Indices::U32(mesh.indices)));
+
+    Some(render_mesh)
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{VoxelMaterial, VoxelTypeInfo};
+
+    use building_blocks::core::SignedAxis3;
+
+    #[test]
+    fn paste_modes_combine_voxels() {
+        let palette = SdfVoxelPalette {
+            infos: vec![
+                VoxelTypeInfo {
+                    is_empty: true,
+                    material: VoxelMaterial::NULL,
+                },
+                VoxelTypeInfo {
+                    is_empty: false,
+                    material: VoxelMaterial(0),
+                },
+            ],
+        };
+        let solid = (VoxelType(1), Sd8::NEG_ONE);
+        let paste = |mode, src: (VoxelType, Sd8), mut dst: (VoxelType, Sd8)| {
+            paste_voxel(mode, src, (&mut dst.0, &mut dst.1), &palette);
+            dst
+        };
+
+        assert_eq!(
+            paste(PasteMode::Replace, EMPTY_SDF_VOXEL, solid),
+            EMPTY_SDF_VOXEL
+        );
+        assert_eq!(paste(PasteMode::Union, EMPTY_SDF_VOXEL, solid), solid);
+        assert_eq!(paste(PasteMode::Union, solid, EMPTY_SDF_VOXEL), solid);
+        assert_eq!(paste(PasteMode::IgnoreEmpty, EMPTY_SDF_VOXEL, solid), solid);
+        assert_eq!(paste(PasteMode::IgnoreEmpty, solid, EMPTY_SDF_VOXEL), solid);
+
+        // Types that aren't in the palette are treated as empty.
+        let undefined = (VoxelType(7), Sd8::NEG_ONE);
+        assert_eq!(paste(PasteMode::IgnoreEmpty, undefined, solid), solid);
+    }
+
+    #[test]
+    fn paste_sits_on_the_clicked_face() {
+        let shape = PointN([3, 2, 3]);
+        let top = VoxelFace {
+            point: PointN([5, 7, 5]),
+            normal: SignedAxis3::from_vector(PointN([0, 1, 0])).unwrap(),
+        };
+        assert_eq!(paste_min(shape, &top), PointN([4, 8, 4]));
+
+        let bottom = VoxelFace {
+            point: PointN([5, 7, 5]),
+            normal: SignedAxis3::from_vector(PointN([0, -1, 0])).unwrap(),
+        };
+        assert_eq!(paste_min(shape, &bottom), PointN([4, 5, 4]));
+    }
+}
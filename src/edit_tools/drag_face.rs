@@ -4,7 +4,7 @@ use super::{selection::SelectionState, CurrentTool, SnapshottingVoxelEditor};
 
 use crate::{
     camera::CursorRay,
-    geometry::{closest_points_on_two_lines, Ray3},
+    geometry::{closest_points_on_two_lines, point3i_serde, Ray3},
     picking::VoxelCursor,
     VoxelType,
 };
@@ -19,6 +19,7 @@ use building_blocks::{
     mesh::OrientedCubeFace,
     storage::Sd8,
 };
+use serde::{Deserialize, Serialize};
 use smooth_bevy_cameras::controllers::unreal::UnrealCameraController;
 
 #[derive(Clone, Copy)]
@@ -31,14 +32,17 @@ pub enum DragFaceState {
     },
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum DragFaceEvents {
     StartDragFace(VoxelFace),
-    UpdateDragFace(Point3i),
+    UpdateDragFace(#[serde(with = "point3i_serde")] Point3i),
     FinishDragFace,
+    CancelDragFace,
 }
 
 pub fn drag_face_default_input_map(
     voxel_cursor: VoxelCursor,
+    keyboard: Res<Input<KeyCode>>,
     mut events: EventWriter<DragFaceEvents>,
     current_tool: Res<CurrentTool>,
     selection_state: Res<SelectionState>,
@@ -51,9 +55,11 @@ pub fn drag_face_default_input_map(
     };
     match state {
         DragFaceState::SelectionReady => {
+            // SHIFT + press gives the selection depth instead.
+            let shift = keyboard.pressed(KeyCode::LShift) || keyboard.pressed(KeyCode::RShift);
             if let SelectionState::SelectionReady { quad_extent, .. } = *selection_state {
                 if let Some(voxel_face) = voxel_cursor.voxel_just_pressed(MouseButton::Left) {
-                    if quad_extent.contains(voxel_face.point) {
+                    if !shift && quad_extent.contains(voxel_face.point) {
                         events.send(DragFaceEvents::StartDragFace(voxel_face))
                     }
                 }
@@ -83,7 +89,9 @@ pub fn drag_face_default_input_map(
                     }
                 }
             }
-            if voxel_cursor.mouse_input.just_released(MouseButton::Left) {
+            if keyboard.just_pressed(KeyCode::Escape) {
+                events.send(DragFaceEvents::CancelDragFace)
+            } else if voxel_cursor.mouse_input.just_released(MouseButton::Left) {
                 events.send(DragFaceEvents::FinishDragFace)
             }
         }
@@ -144,17 +152,14 @@ pub fn drag_face_tool_system(
                             (VoxelType(0), Sd8::ONE)
                         };
 
-                    // Write voxels in the extent between the old and new quad.
+                    // Write voxels in the extent between the old and new quad. They're only previewed until the drag is done.
                     let fill_min = quad_extent.minimum.meet(old_quad_extent.minimum);
                     let fill_max = quad_extent.max().join(old_quad_extent.max());
                     let fill_extent = Extent3i::from_min_and_max(fill_min, fill_max);
-                    voxel_editor.edit_extent_and_touch_neighbors(
-                        fill_extent,
-                        |_p, (v_type, v_dist)| {
-                            *v_type = write_voxel.0;
-                            *v_dist = write_voxel.1;
-                        },
-                    );
+                    voxel_editor.edit_extent_in_transaction(fill_extent, |_p, (v_type, v_dist)| {
+                        *v_type = write_voxel.0;
+                        *v_dist = write_voxel.1;
+                    });
 
                     *state = DragFaceState::DraggingFace {
                         quad_extent,
@@ -163,12 +168,16 @@ pub fn drag_face_tool_system(
                     };
                 }
             }
-            DragFaceEvents::FinishDragFace => {
+            DragFaceEvents::FinishDragFace | DragFaceEvents::CancelDragFace => {
                 // Done dragging.
                 if let Some(mut controller) = mouse_camera_controllers.iter_mut().next() {
                     controller.enabled = true;
                 }
-                voxel_editor.finish_edit();
+                if let DragFaceEvents::FinishDragFace = event {
+                    voxel_editor.commit_transaction();
+                } else {
+                    voxel_editor.abort_transaction();
+                }
                 *state = DragFaceState::SelectionReady;
                 *selection_state = SelectionState::SelectingFirstCorner;
             }
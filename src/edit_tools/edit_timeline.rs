@@ -1,6 +1,5 @@
 use crate::{
-    ambient_sdf_array, empty_sdf_chunk_hash_map, CompressibleSdfChunkMap, SdfChunkHashMap,
-    VoxelEditor, CHUNK_SHAPE,
+    ambient_sdf_array, empty_sdf_chunk_hash_map, SdfChunkHashMap, SdfVoxelMap, VoxelEditor,
 };
 
 use building_blocks::prelude::*;
@@ -15,13 +14,12 @@ pub struct EditTimeline {
 }
 
 impl EditTimeline {
-    pub fn new() -> Self {
+    /// `chunk_shape` must match the `SdfVoxelMap` that gets edited.
+    pub fn new(chunk_shape: Point3i) -> Self {
         Self {
             undo_queue: Default::default(),
             redo_queue: Default::default(),
-            current_edit: Edit {
-                voxels: empty_sdf_chunk_hash_map(CHUNK_SHAPE),
-            },
+            current_edit: Edit::new(chunk_shape),
         }
     }
 
@@ -42,23 +40,18 @@ impl EditTimeline {
         reversible_restore_snapshot(&mut self.redo_queue, &mut self.undo_queue, editor)
     }
 
-    pub fn add_extent_to_current_edit(
-        &mut self,
-        extent: Extent3i,
-        src_map: &CompressibleSdfChunkMap,
-    ) {
-        for chunk_min in src_map.indexer.chunk_mins_for_extent(&extent) {
+    pub fn add_extent_to_current_edit(&mut self, extent: Extent3i, src_map: &SdfVoxelMap) {
+        let indexer = src_map.voxels.indexer;
+        for chunk_min in indexer.chunk_mins_for_extent(&extent) {
             let chunk_key = ChunkKey::new(0, chunk_min);
             self.current_edit
                 .voxels
                 .get_mut_chunk_or_insert_with(chunk_key, || {
+                    // This chunk will eventually get cached after being written by the editor.
                     src_map
-                        .storage()
-                        // This chunk will eventually get cached after being written by the editor.
-                        .copy_without_caching(chunk_key)
-                        .map(|c| c.into_decompressed())
+                        .copy_chunk_without_caching(chunk_key)
                         .unwrap_or_else(|| {
-                            ambient_sdf_array(src_map.indexer.extent_for_chunk_with_min(chunk_min))
+                            ambient_sdf_array(indexer.extent_for_chunk_with_min(chunk_min))
                         })
                 });
         }
@@ -79,10 +72,7 @@ fn reversible_restore_snapshot(
             editor.insert_chunk_and_touch_neighbors(chunk_key.minimum, chunk);
             let old_chunk = editor
                 .map
-                .voxels
-                .storage()
-                .copy_without_caching(chunk_key)
-                .map(|c| c.into_decompressed())
+                .copy_chunk_without_caching(chunk_key)
                 .unwrap_or_else(|| {
                     ambient_sdf_array(indexer.extent_for_chunk_with_min(chunk_key.minimum))
                 });
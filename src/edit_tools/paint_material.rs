@@ -0,0 +1,292 @@
+use super::{terraformer::centered_extent, CurrentTool, SnapshottingVoxelEditor};
+
+use crate::{ImmediateModeTag, SdfArray, SdfVoxelMap, SdfVoxelPalette, VoxelCursor, VoxelType};
+
+use bevy::{
+    asset::prelude::*,
+    ecs::prelude::*,
+    input::prelude::*,
+    pbr::prelude::*,
+    prelude::{EventReader, EventWriter},
+    render::{mesh::shape, prelude::*},
+    transform::prelude::*,
+};
+use building_blocks::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A brush that changes the `VoxelType` of surface voxels, without touching their signed distances. This retextures the surface
+/// without reshaping it.
+pub struct MaterialPainter {
+    brush_radius: u32,
+    voxel_type: VoxelType,
+}
+
+impl Default for MaterialPainter {
+    fn default() -> Self {
+        Self {
+            brush_radius: 4,
+            voxel_type: VoxelType(1),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum MaterialPainterEvents {
+    ChangeBrushRadius(i8),
+    ChangeVoxelType(u8),
+    Paint,
+    FinishPaint,
+}
+
+pub fn material_painter_default_input_map(
+    mut events: EventWriter<MaterialPainterEvents>,
+    keyboard: Res<Input<KeyCode>>,
+    current_tool: Res<CurrentTool>,
+) {
+    if let CurrentTool::PaintMaterial = *current_tool {
+    } else {
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::Up) {
+        events.send(MaterialPainterEvents::ChangeBrushRadius(1))
+    } else if keyboard.just_pressed(KeyCode::Down) {
+        events.send(MaterialPainterEvents::ChangeBrushRadius(-1))
+    }
+
+    if keyboard.just_pressed(KeyCode::Key1) {
+        events.send(MaterialPainterEvents::ChangeVoxelType(1));
+    } else if keyboard.just_pressed(KeyCode::Key2) {
+        events.send(MaterialPainterEvents::ChangeVoxelType(2));
+    } else if keyboard.just_pressed(KeyCode::Key3) {
+        events.send(MaterialPainterEvents::ChangeVoxelType(3));
+    } else if keyboard.just_pressed(KeyCode::Key4) {
+        events.send(MaterialPainterEvents::ChangeVoxelType(4));
+    }
+
+    if keyboard.pressed(KeyCode::Z) {
+        events.send(MaterialPainterEvents::Paint);
+    }
+    if keyboard.just_released(KeyCode::Z) {
+        events.send(MaterialPainterEvents::FinishPaint);
+    }
+}
+
+pub fn material_painter_system(
+    current_tool: Res<CurrentTool>,
+    mut painter: ResMut<MaterialPainter>,
+    mut voxel_editor: SnapshottingVoxelEditor,
+    voxel_map: Res<SdfVoxelMap>,
+    voxel_cursor: VoxelCursor,
+    mut events: EventReader<MaterialPainterEvents>,
+) {
+    if let CurrentTool::PaintMaterial = *current_tool {
+    } else {
+        return;
+    }
+
+    for event in events.iter() {
+        match event {
+            MaterialPainterEvents::Paint => {
+                if let Some(face) = voxel_cursor.voxel_face() {
+                    paint_sphere(
+                        face.point,
+                        painter.brush_radius,
+                        painter.voxel_type,
+                        &*voxel_map,
+                        &mut voxel_editor,
+                    );
+                }
+            }
+            MaterialPainterEvents::FinishPaint => {
+                voxel_editor.finish_edit();
+            }
+            MaterialPainterEvents::ChangeBrushRadius(delta) => {
+                painter.brush_radius =
+                    ((*delta as i32 + painter.brush_radius as i32) as u32).max(1);
+            }
+            MaterialPainterEvents::ChangeVoxelType(voxel_type) => {
+                if *voxel_type as usize >= voxel_map.palette.infos.len() {
+                    println!("Voxel type {} is not in the palette", voxel_type);
+                    continue;
+                }
+                painter.voxel_type = VoxelType(*voxel_type);
+            }
+        }
+    }
+}
+
+fn paint_sphere(
+    center: Point3i,
+    radius: u32,
+    voxel_type: VoxelType,
+    voxel_map: &SdfVoxelMap,
+    voxel_editor: &mut SnapshottingVoxelEditor,
+) {
+    let palette = &voxel_map.palette;
+    let info = if let Some(info) = palette.infos.get(voxel_type.0 as usize) {
+        info
+    } else {
+        // The palette can change when another map is opened.
+        println!("Voxel type {} is not in the palette", voxel_type.0);
+        return;
+    };
+    // Painting an empty type would make solid voxels disappear from the surface without changing its shape.
+    if info.is_empty {
+        return;
+    }
+
+    let extent = centered_extent(center, radius);
+    // The neighbors of the voxels on the edge of the brush decide whether they're on the surface.
+    let neighborhood = voxel_map.copy_extent_without_caching(extent.padded(1));
+    let fradius = radius as f32;
+    voxel_editor.edit_extent_and_touch_neighbors(
+        extent,
+        |p: Point3i, (v_type, _v_dist): (&mut VoxelType, &mut Sd8)| {
+            if (p - center).norm() <= fradius && is_surface_voxel(&neighborhood, p, palette) {
+                *v_type = voxel_type;
+            }
+        },
+    );
+}
+
+/// True if the voxel at `p` is solid and at least one of the voxels that share a face with it is empty. These are the voxels
+/// whose materials get blended into the surface mesh. Voxels with types that aren't in the `palette` are solid if their
+/// distance is negative, like they are for the mesh.
+fn is_surface_voxel(voxels: &SdfArray, p: Point3i, palette: &SdfVoxelPalette) -> bool {
+    let is_empty = |p: Point3i| {
+        let (v_type, v_dist) = voxels.get(p);
+        palette
+            .infos
+            .get(v_type.0 as usize)
+            .map_or(v_dist.0 >= 0, |info| info.is_empty)
+    };
+    if is_empty(p) {
+        return false;
+    }
+
+    Point3i::VON_NEUMANN_OFFSETS
+        .iter()
+        .any(|offset| is_empty(p + *offset))
+}
+
+pub struct MaterialPainterView {
+    material: Handle<StandardMaterial>,
+    /// The brush sphere for the radius that it was built with, so it's only rebuilt when the radius changes.
+    mesh: Option<(u32, Handle<Mesh>)>,
+}
+
+pub fn initialize_material_painter(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MaterialPainter::default());
+
+    let mut color = Color::WHITE;
+    color.set_a(0.3);
+    commands.insert_resource(MaterialPainterView {
+        material: materials.add(StandardMaterial::from(color)),
+        mesh: None,
+    });
+}
+
+/// Shows the brush around the voxel under the cursor.
+pub fn material_painter_preview_system(
+    mut commands: Commands,
+    current_tool: Res<CurrentTool>,
+    painter: Res<MaterialPainter>,
+    voxel_cursor: VoxelCursor,
+    mut view: ResMut<MaterialPainterView>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if let CurrentTool::PaintMaterial = *current_tool {
+    } else {
+        return;
+    }
+
+    let face = if let Some(face) = voxel_cursor.voxel_face() {
+        face
+    } else {
+        return;
+    };
+
+    let radius = painter.brush_radius;
+    let mesh = match &view.mesh {
+        Some((built_radius, mesh)) if *built_radius == radius => mesh.clone(),
+        _ => {
+            let mesh = meshes.add(Mesh::from(shape::Icosphere {
+                radius: radius as f32,
+                subdivisions: 3,
+            }));
+            view.mesh = Some((radius, mesh.clone()));
+            mesh
+        }
+    };
+
+    let voxel_center = Point3f::from(face.point) + PointN([0.5; 3]);
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh,
+            material: view.material.clone(),
+            transform: Transform::from_translation(voxel_center.into()),
+            ..Default::default()
+        })
+        .insert(ImmediateModeTag);
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ambient_sdf_array, VoxelMaterial, VoxelTypeInfo};
+
+    #[test]
+    fn only_solid_voxels_next_to_empty_ones_are_on_the_surface() {
+        let palette = SdfVoxelPalette {
+            infos: vec![
+                VoxelTypeInfo {
+                    is_empty: true,
+                    material: VoxelMaterial::NULL,
+                },
+                VoxelTypeInfo {
+                    is_empty: false,
+                    material: VoxelMaterial(0),
+                },
+            ],
+        };
+        // A 3x3x3 solid cube surrounded by empty voxels.
+        let mut voxels = ambient_sdf_array(Extent3i::from_min_and_shape(
+            PointN([-1; 3]),
+            PointN([5; 3]),
+        ));
+        voxels.for_each_mut(
+            &Extent3i::from_min_and_shape(PointN([0; 3]), PointN([3; 3])),
+            |_p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+                *v_type = VoxelType(1);
+                *v_dist = Sd8::NEG_ONE;
+            },
+        );
+
+        assert!(is_surface_voxel(&voxels, PointN([0; 3]), &palette));
+        assert!(is_surface_voxel(&voxels, PointN([1, 2, 1]), &palette));
+        assert!(!is_surface_voxel(&voxels, PointN([1; 3]), &palette));
+        assert!(!is_surface_voxel(&voxels, PointN([-1; 3]), &palette));
+
+        // A type from another palette falls back to the sign of its distance.
+        let (v_type, v_dist) = voxels.get_mut(PointN([1, 3, 1]));
+        *v_type = VoxelType(7);
+        *v_dist = Sd8::ONE;
+        assert!(is_surface_voxel(&voxels, PointN([1, 2, 1]), &palette));
+        let (_v_type, v_dist) = voxels.get_mut(PointN([1, 3, 1]));
+        *v_dist = Sd8::NEG_ONE;
+        assert!(is_surface_voxel(&voxels, PointN([1, 3, 1]), &palette));
+        assert!(!is_surface_voxel(&voxels, PointN([1, 2, 1]), &palette));
+    }
+}
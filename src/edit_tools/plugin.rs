@@ -1,49 +1,144 @@
 use super::{
+    clipboard::{
+        clipboard_default_input_map, clipboard_preview_system, clipboard_system,
+        initialize_clipboard, ClipboardEvents,
+    },
     drag_face::{
         drag_face_default_input_map, drag_face_tool_system, DragFaceEvents, DragFaceState,
     },
     edit_timeline::EditTimeline,
+    paint_material::{
+        initialize_material_painter, material_painter_default_input_map,
+        material_painter_preview_system, material_painter_system, MaterialPainterEvents,
+    },
     selection::{SelectionEvents, SelectionPlugin},
+    slope::{slope_default_input_map, slope_tool_system, SlopeEvents, SlopeMode},
+    smart_tiles::{smart_tiles_default_input_map, smart_tiles_system, SmartTileEvents},
     terraformer::{
         terraformer_default_input_map, terraformer_system, Terraformer, TerraformerEvents,
     },
-    tool_switcher::tool_switcher_system,
-    undo::undo_system,
+    tile::{tile_default_input_map, tile_tool_system, TileEvents},
+    tool_switcher::{tool_switcher_default_input_map, tool_switcher_system, ToolSwitchEvents},
+    undo::{undo_default_input_map, undo_system, UndoEvents},
     CurrentTool,
 };
-use crate::StatePlugin;
+use crate::{SdfVoxelMap, StatePlugin};
 
 use bevy::{ecs::prelude::*, prelude::AppBuilder};
 
 /// Depends on the `VoxelPickingPlugin`.
+///
+/// Each frame, the input maps turn keyboard and mouse input into edit tool events, and then the tools consume the events. The
+/// input maps run after the `VoxelCursorRayImpact` is updated, so the tools always see the impact and `CursorRay` of the frame
+/// that the events were sent in.
 pub struct EditToolsPlugin;
 
 impl EditToolsPlugin {
-    fn initialize(mut commands: Commands) {
-        commands.insert_resource(EditTimeline::new());
+    fn initialize(mut commands: Commands, voxel_map: Res<SdfVoxelMap>) {
+        commands.insert_resource(EditTimeline::new(voxel_map.voxels.chunk_shape()));
         commands.insert_resource(Terraformer::default());
+        commands.insert_resource(SlopeMode::default());
         commands.insert_resource(CurrentTool::DragFace(DragFaceState::SelectionReady));
     }
 
     pub fn register_events(app: &mut AppBuilder) {
         app.add_event::<TerraformerEvents>();
         app.add_event::<DragFaceEvents>();
+        app.add_event::<ClipboardEvents>();
+        app.add_event::<MaterialPainterEvents>();
+        app.add_event::<TileEvents>();
+        app.add_event::<SlopeEvents>();
+        app.add_event::<SmartTileEvents>();
         app.add_event::<SelectionEvents>();
+        app.add_event::<ToolSwitchEvents>();
+        app.add_event::<UndoEvents>();
+    }
+
+    /// The systems that turn keyboard and mouse input into edit tool events.
+    pub fn add_input_maps(set: SystemSet) -> SystemSet {
+        SelectionPlugin::add_input_map(set)
+            .with_system(
+                undo_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                tool_switcher_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                terraformer_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                drag_face_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                material_painter_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                tile_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                slope_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                smart_tiles_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+            .with_system(
+                clipboard_default_input_map
+                    .system()
+                    .label("edit_tool_input")
+                    .after("voxel_picking"),
+            )
+    }
+
+    /// The systems that consume edit tool events, without the input maps that produce them.
+    pub fn add_tool_systems(set: SystemSet) -> SystemSet {
+        SelectionPlugin::add_update_systems(set)
+            .with_system(undo_system.system().after("edit_tool_input"))
+            .with_system(tool_switcher_system.system().after("edit_tool_input"))
+            .with_system(terraformer_system.system().after("edit_tool_input"))
+            .with_system(drag_face_tool_system.system().after("edit_tool_input"))
+            .with_system(material_painter_system.system().after("edit_tool_input"))
+            .with_system(material_painter_preview_system.system())
+            .with_system(tile_tool_system.system().after("edit_tool_input"))
+            .with_system(slope_tool_system.system().after("edit_tool_input"))
+            .with_system(smart_tiles_system.system().after("edit_tool_input"))
+            .with_system(clipboard_system.system().after("edit_tool_input"))
+            .with_system(clipboard_preview_system.system())
     }
 }
 
 impl StatePlugin for EditToolsPlugin {
     fn add_enter_systems(set: SystemSet) -> SystemSet {
-        SelectionPlugin::add_enter_systems(set).with_system(Self::initialize.system())
+        SelectionPlugin::add_enter_systems(set)
+            .with_system(Self::initialize.system())
+            .with_system(initialize_material_painter.system())
+            .with_system(initialize_clipboard.system())
     }
 
     fn add_update_systems(set: SystemSet) -> SystemSet {
-        SelectionPlugin::add_update_systems(set)
-            .with_system(undo_system.system())
-            .with_system(tool_switcher_system.system())
-            .with_system(terraformer_system.system())
-            .with_system(terraformer_default_input_map.system())
-            .with_system(drag_face_tool_system.system())
-            .with_system(drag_face_default_input_map.system())
+        Self::add_tool_systems(Self::add_input_maps(set))
     }
 }
@@ -1,11 +1,19 @@
-use crate::picking::{VoxelCursor, VoxelFace};
+use crate::{
+    camera::CursorRay,
+    edit_tools::CurrentTool,
+    geometry::{closest_points_on_two_lines, ray_box_intersection, signed_axis3_serde, Ray3},
+    picking::{VoxelCursor, VoxelFace},
+};
 
 use bevy::{
     ecs::prelude::*,
     input::prelude::*,
+    math::prelude::*,
     prelude::{EventReader, EventWriter},
 };
 use building_blocks::core::{prelude::*, SignedAxis3};
+use serde::{Deserialize, Serialize};
+use smooth_bevy_cameras::controllers::unreal::UnrealCameraController;
 
 #[derive(Clone, Copy)]
 pub enum SelectionState {
@@ -22,13 +30,53 @@ pub enum SelectionState {
         /// The normal direction of the selected face.
         normal: SignedAxis3,
     },
+    /// One face of the box is being dragged along its normal. Dragging the selected quad sizes the 3rd dimension of a new box.
+    ResizingBox {
+        /// The box before the drag started.
+        start_extent: Extent3i,
+        /// The box with the dragged face at its current position.
+        extent: Extent3i,
+        face: SignedAxis3,
+    },
+    BoxReady {
+        /// The volume of voxels selected.
+        extent: Extent3i,
+    },
     Invisible,
 }
 
+impl SelectionState {
+    /// The volume of voxels selected, if a box is selected.
+    pub fn selected_volume(&self) -> Option<Extent3i> {
+        if let SelectionState::BoxReady { extent } = self {
+            Some(*extent)
+        } else {
+            None
+        }
+    }
+
+    /// The voxels selected, either the layer of voxels under a selected quad or the volume of a box.
+    pub fn selected_extent(&self) -> Option<Extent3i> {
+        match self {
+            SelectionState::SelectionReady { quad_extent, .. } => Some(*quad_extent),
+            SelectionState::BoxReady { extent } => Some(*extent),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum SelectionEvents {
     SelectFirstCorner(VoxelFace),
     HoverMove(VoxelFace),
     SelectSecondCorner(VoxelFace),
+    /// Start dragging a face of the selected box, or the selected quad to give it depth.
+    StartResizeBox(#[serde(with = "signed_axis3_serde")] SignedAxis3),
+    /// Move the dragged face so it's on the plane at this coordinate along its axis.
+    UpdateResizeBox(i32),
+    FinishResizeBox,
+    CancelResizeBox,
+    ClearSelection,
 }
 
 pub fn initialize_selection_controller(mut commands: Commands) {
@@ -39,7 +87,17 @@ pub fn selection_default_input_map(
     mut events: EventWriter<SelectionEvents>,
     mut selection_state: ResMut<SelectionState>,
     voxel_cursor: VoxelCursor,
+    keyboard: Res<Input<KeyCode>>,
+    cursor_ray: Res<CursorRay>,
+    current_tool: Res<CurrentTool>,
 ) {
+    // These tools have their own uses for clicks.
+    if let CurrentTool::Paste | CurrentTool::Tile(_) | CurrentTool::Slope(_) = *current_tool {
+        return;
+    }
+
+    let shift = keyboard.pressed(KeyCode::LShift) || keyboard.pressed(KeyCode::RShift);
+
     match &mut *selection_state {
         SelectionState::SelectingFirstCorner => {
             if let Some(first_corner) = voxel_cursor.voxel_just_clicked(MouseButton::Left) {
@@ -58,12 +116,56 @@ pub fn selection_default_input_map(
                 }
             }
         }
-        _ => {}
+        SelectionState::SelectionReady {
+            quad_extent,
+            normal,
+        } => {
+            // Without SHIFT, pressing on the quad is left to the drag face tool.
+            if shift {
+                if let Some(voxel_face) = voxel_cursor.voxel_just_pressed(MouseButton::Left) {
+                    if quad_extent.contains(voxel_face.point) {
+                        events.send(SelectionEvents::StartResizeBox(*normal));
+                    }
+                }
+            }
+        }
+        SelectionState::ResizingBox { extent, face, .. } => {
+            if let CursorRay(Some(ray)) = &*cursor_ray {
+                if let Some(plane_coord) = dragged_face_plane(extent, *face, ray) {
+                    if plane_coord != face_plane(extent, *face) {
+                        events.send(SelectionEvents::UpdateResizeBox(plane_coord));
+                    }
+                }
+            }
+            if keyboard.just_pressed(KeyCode::Escape) {
+                events.send(SelectionEvents::CancelResizeBox);
+            } else if voxel_cursor.mouse_input.just_released(MouseButton::Left) {
+                events.send(SelectionEvents::FinishResizeBox);
+            }
+        }
+        SelectionState::BoxReady { extent } => {
+            if keyboard.just_pressed(KeyCode::Escape) {
+                events.send(SelectionEvents::ClearSelection);
+            } else if voxel_cursor.mouse_input.just_pressed(MouseButton::Left) {
+                // Each face of the box is a handle. Clicking anywhere else starts a new selection.
+                let handle = match &*cursor_ray {
+                    CursorRay(Some(ray)) => box_face_hit_by_ray(extent, ray),
+                    CursorRay(None) => None,
+                };
+                if let Some(face) = handle {
+                    events.send(SelectionEvents::StartResizeBox(face));
+                }
+            } else if let Some(first_corner) = voxel_cursor.voxel_just_clicked(MouseButton::Left) {
+                events.send(SelectionEvents::SelectFirstCorner(first_corner));
+            }
+        }
+        SelectionState::Invisible => {}
     }
 }
 
 pub fn selection_control_system(
     mut selection_state: ResMut<SelectionState>,
+    mut mouse_camera_controllers: Query<&mut UnrealCameraController>,
     mut events: EventReader<SelectionEvents>,
 ) {
     for event in events.iter() {
@@ -102,12 +204,164 @@ pub fn selection_control_system(
                     }
                 }
             }
+            SelectionEvents::StartResizeBox(face) => {
+                let start_extent = match *selection_state {
+                    SelectionState::SelectionReady { quad_extent, .. } => quad_extent,
+                    SelectionState::BoxReady { extent } => extent,
+                    _ => continue,
+                };
+                set_camera_enabled(&mut mouse_camera_controllers, false);
+                *selection_state = SelectionState::ResizingBox {
+                    start_extent,
+                    extent: start_extent,
+                    face: *face,
+                };
+            }
+            SelectionEvents::UpdateResizeBox(plane_coord) => {
+                if let SelectionState::ResizingBox { extent, face, .. } = &mut *selection_state {
+                    *extent = move_face_plane(extent, *face, *plane_coord);
+                }
+            }
+            SelectionEvents::FinishResizeBox => {
+                if let SelectionState::ResizingBox { extent, .. } = *selection_state {
+                    set_camera_enabled(&mut mouse_camera_controllers, true);
+                    *selection_state = SelectionState::BoxReady { extent };
+                }
+            }
+            SelectionEvents::CancelResizeBox => {
+                if let SelectionState::ResizingBox { start_extent, .. } = *selection_state {
+                    set_camera_enabled(&mut mouse_camera_controllers, true);
+                    *selection_state = SelectionState::BoxReady {
+                        extent: start_extent,
+                    };
+                }
+            }
+            SelectionEvents::ClearSelection => {
+                if let SelectionState::ResizingBox { .. } = *selection_state {
+                    set_camera_enabled(&mut mouse_camera_controllers, true);
+                }
+                *selection_state = SelectionState::SelectingFirstCorner;
+            }
         }
     }
 }
 
+fn set_camera_enabled(controllers: &mut Query<&mut UnrealCameraController>, enabled: bool) {
+    if let Some(mut controller) = controllers.iter_mut().next() {
+        controller.enabled = enabled;
+    }
+}
+
 fn selection_corners_are_compatible(corner1: &VoxelFace, corner2: &VoxelFace) -> bool {
     corner1.normal == corner2.normal
         && corner1.point.axis_component(corner1.normal.axis)
             == corner2.point.axis_component(corner2.normal.axis)
 }
+
+/// The coordinate along `face.axis` of the plane that `face` of the box around `extent` lies on.
+fn face_plane(extent: &Extent3i, face: SignedAxis3) -> i32 {
+    if face.sign > 0 {
+        extent.least_upper_bound().axis_component(face.axis)
+    } else {
+        extent.minimum.axis_component(face.axis)
+    }
+}
+
+/// Moves `face` of the box around `extent` to the plane at `plane_coord`, without letting the box get thinner than 1 voxel.
+fn move_face_plane(extent: &Extent3i, face: SignedAxis3, plane_coord: i32) -> Extent3i {
+    let mut min = extent.minimum;
+    let mut max = extent.max();
+    if face.sign > 0 {
+        *max.axis_component_mut(face.axis) = (plane_coord - 1).max(min.axis_component(face.axis));
+    } else {
+        *min.axis_component_mut(face.axis) = plane_coord.min(max.axis_component(face.axis));
+    }
+
+    Extent3i::from_min_and_max(min, max)
+}
+
+/// The plane that `face` should be dragged to so it follows `cursor_ray`.
+fn dragged_face_plane(extent: &Extent3i, face: SignedAxis3, cursor_ray: &Ray3) -> Option<i32> {
+    // Like dragging a face with the drag face tool, project the cursor ray onto the face's normal axis, through the center of
+    // the face.
+    let mut normal: Point3i = PointN([0; 3]);
+    *normal.axis_component_mut(face.axis) = face.sign;
+    let mut face_center = Point3f::from(extent.minimum) + Point3f::from(extent.shape) * 0.5;
+    *face_center.axis_component_mut(face.axis) = face_plane(extent, face) as f32;
+    let axis_line = Ray3::new(face_center.into(), Point3f::from(normal).into());
+
+    closest_points_on_two_lines(&axis_line, cursor_ray).map(|(p1, _p2)| {
+        let p1: Point3f = p1.into();
+
+        p1.axis_component(face.axis).round() as i32
+    })
+}
+
+/// The face of the box around `extent` that `ray` enters through, if the ray starts outside of the box.
+pub(super) fn box_face_hit_by_ray(extent: &Extent3i, ray: &Ray3) -> Option<SignedAxis3> {
+    let min: Vec3 = Point3f::from(extent.minimum).into();
+    let max: Vec3 = Point3f::from(extent.least_upper_bound()).into();
+    let (t_enter, _t_exit) = ray_box_intersection(ray, min, max)?;
+    if t_enter <= 0.0 {
+        return None;
+    }
+
+    // The entry point lies on the plane of the face it goes through, so pick the face whose plane is closest.
+    let hit: Point3f = (ray.origin + t_enter * ray.direction).into();
+    let min: Point3f = min.into();
+    let max: Point3f = max.into();
+    let mut closest_face = None;
+    let mut closest_distance = std::f32::INFINITY;
+    for i in 0..3 {
+        let mut normal: Point3i = PointN([0; 3]);
+        for (sign, plane) in [(-1, min.0[i]), (1, max.0[i])].iter() {
+            normal.0[i] = *sign;
+            let distance = (hit.0[i] - plane).abs();
+            if distance < closest_distance {
+                closest_distance = distance;
+                closest_face = SignedAxis3::from_vector(normal);
+            }
+        }
+    }
+
+    closest_face
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_faces_are_hit_and_moved_without_inverting_the_box() {
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([4, 2, 4]));
+        let top = SignedAxis3::from_vector(PointN([0, 1, 0])).unwrap();
+        let west = SignedAxis3::from_vector(PointN([-1, 0, 0])).unwrap();
+
+        let from_above = Ray3::new(Vec3::new(1.5, 10.0, 1.5), Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(box_face_hit_by_ray(&extent, &from_above), Some(top));
+        let from_west = Ray3::new(Vec3::new(-10.0, 1.0, 2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(box_face_hit_by_ray(&extent, &from_west), Some(west));
+        let from_inside = Ray3::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(box_face_hit_by_ray(&extent, &from_inside), None);
+
+        assert_eq!(
+            move_face_plane(&extent, top, 5),
+            Extent3i::from_min_and_shape(PointN([0; 3]), PointN([4, 5, 4]))
+        );
+        assert_eq!(
+            move_face_plane(&extent, top, -3),
+            Extent3i::from_min_and_shape(PointN([0; 3]), PointN([4, 1, 4]))
+        );
+        assert_eq!(
+            move_face_plane(&extent, west, -2),
+            Extent3i::from_min_and_shape(PointN([-2, 0, 0]), PointN([6, 2, 4]))
+        );
+    }
+}
@@ -10,6 +10,19 @@ use bevy::ecs::prelude::*;
 
 pub struct SelectionPlugin;
 
+impl SelectionPlugin {
+    /// The system that turns mouse input into `SelectionEvents`. It's not one of the update systems, so the events can come from
+    /// somewhere else.
+    pub fn add_input_map(set: SystemSet) -> SystemSet {
+        set.with_system(
+            selection_default_input_map
+                .system()
+                .label("edit_tool_input")
+                .after("voxel_picking"),
+        )
+    }
+}
+
 impl StatePlugin for SelectionPlugin {
     fn add_enter_systems(set: SystemSet) -> SystemSet {
         set.with_system(initialize_selection_controller.system())
@@ -17,8 +30,7 @@ impl StatePlugin for SelectionPlugin {
     }
 
     fn add_update_systems(set: SystemSet) -> SystemSet {
-        set.with_system(selection_control_system.system())
-            .with_system(selection_default_input_map.system())
+        set.with_system(selection_control_system.system().after("edit_tool_input"))
             .with_system(selection_view_system.system())
     }
 }
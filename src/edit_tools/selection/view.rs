@@ -1,6 +1,6 @@
-use super::SelectionState;
+use super::{controller::box_face_hit_by_ray, SelectionState};
 
-use crate::{geometry::offset_transform, ImmediateModeTag, VoxelCursorRayImpact};
+use crate::{geometry::offset_transform, CursorRay, ImmediateModeTag, VoxelCursorRayImpact};
 
 use bevy::{
     asset::prelude::*,
@@ -12,7 +12,10 @@ use bevy::{
         prelude::*,
     },
 };
-use building_blocks::mesh::{OrientedCubeFace, PosNormMesh, UnorientedQuad};
+use building_blocks::{
+    core::{prelude::*, SignedAxis3},
+    mesh::{OrientedCubeFace, PosNormMesh, UnorientedQuad},
+};
 
 pub fn initialize_selection_view(
     mut commands: Commands,
@@ -22,16 +25,24 @@ pub fn initialize_selection_view(
     color.set_a(0.5);
     let material = SelectionCursorMaterial(materials.add(StandardMaterial::from(color)));
     commands.insert_resource(material);
+
+    let mut color = Color::ORANGE;
+    color.set_a(0.7);
+    let material = SelectionHandleMaterial(materials.add(StandardMaterial::from(color)));
+    commands.insert_resource(material);
 }
 
 pub fn selection_view_system(
     selection_state: Res<SelectionState>,
     cursor_voxel: Res<VoxelCursorRayImpact>,
+    cursor_ray: Res<CursorRay>,
     material: Res<SelectionCursorMaterial>,
+    handle_material: Res<SelectionHandleMaterial>,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
     let mut quad_face = None;
+    let mut box_and_handle = None;
     match *selection_state {
         SelectionState::SelectingFirstCorner => {
             if let Some(voxel_face) = cursor_voxel.get_voxel_face() {
@@ -62,6 +73,16 @@ pub fn selection_view_system(
             let quad = face.quad_from_extent(&quad_extent);
             quad_face = Some((quad, face));
         }
+        SelectionState::ResizingBox { extent, face, .. } => {
+            box_and_handle = Some((extent, Some(face)));
+        }
+        SelectionState::BoxReady { extent } => {
+            let hovered_handle = match &*cursor_ray {
+                CursorRay(Some(ray)) => box_face_hit_by_ray(&extent, ray),
+                CursorRay(None) => None,
+            };
+            box_and_handle = Some((extent, hovered_handle));
+        }
         SelectionState::Invisible => (),
     }
 
@@ -74,10 +95,51 @@ pub fn selection_view_system(
             &mut *meshes,
         );
     }
+    if let Some((extent, handle)) = box_and_handle {
+        // Every face of the box is a handle, and the one that's hovered or dragged is highlighted.
+        for face in box_faces() {
+            let face_material = if Some(face) == handle {
+                handle_material.0.clone()
+            } else {
+                material.0.clone()
+            };
+            let cube_face = OrientedCubeFace::canonical(face);
+            let quad = cube_face.quad_from_extent(&box_face_layer(&extent, face));
+            create_quad_selection_hint_entity(
+                &quad,
+                &cube_face,
+                face_material,
+                &mut commands,
+                &mut *meshes,
+            );
+        }
+    }
 }
 
 pub struct SelectionCursorMaterial(pub Handle<StandardMaterial>);
 
+pub struct SelectionHandleMaterial(pub Handle<StandardMaterial>);
+
+fn box_faces() -> impl Iterator<Item = SignedAxis3> {
+    (0..6).filter_map(|i| {
+        let mut normal: Point3i = PointN([0; 3]);
+        normal.0[i / 2] = if i % 2 == 0 { -1 } else { 1 };
+
+        SignedAxis3::from_vector(normal)
+    })
+}
+
+/// The layer of voxels in `extent` that touch `face` of its box.
+fn box_face_layer(extent: &Extent3i, face: SignedAxis3) -> Extent3i {
+    let mut layer = *extent;
+    if face.sign > 0 {
+        *layer.minimum.axis_component_mut(face.axis) = extent.max().axis_component(face.axis);
+    }
+    *layer.shape.axis_component_mut(face.axis) = 1;
+
+    layer
+}
+
 fn create_quad_selection_hint_entity(
     quad: &UnorientedQuad,
     face: &OrientedCubeFace,
@@ -0,0 +1,399 @@
+use super::{CurrentTool, SnapshottingVoxelEditor};
+
+use crate::{
+    picking::{VoxelCursor, VoxelFace},
+    voxel::EMPTY_VOXEL_TYPE,
+    SdfVoxelMap, VoxelType,
+};
+
+use bevy::{
+    ecs::prelude::*,
+    input::prelude::*,
+    prelude::{EventReader, EventWriter},
+};
+use building_blocks::{
+    core::{Axis3, SignedAxis3},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// A line of voxel faces, given by the faces at either end.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SlopeEdge {
+    pub start: VoxelFace,
+    pub end: VoxelFace,
+}
+
+#[derive(Clone, Copy)]
+pub enum SlopeState {
+    SelectingFirstEdge {
+        start: Option<VoxelFace>,
+    },
+    SelectingSecondEdge {
+        first_edge: SlopeEdge,
+        start: Option<VoxelFace>,
+    },
+    /// The ramp between the edges is previewed in the `EditTransaction` until it's confirmed.
+    Previewing {
+        first_edge: SlopeEdge,
+        second_edge: SlopeEdge,
+    },
+}
+
+impl Default for SlopeState {
+    fn default() -> Self {
+        SlopeState::SelectingFirstEdge { start: None }
+    }
+}
+
+/// Whether a ramp adds solid voxels below its incline, or removes the solid voxels above it.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum SlopeMode {
+    Fill,
+    Carve,
+}
+
+impl Default for SlopeMode {
+    fn default() -> Self {
+        SlopeMode::Fill
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum SlopeEvents {
+    /// Picks one end of an edge. Every two ends make an edge.
+    SelectEdgeEnd(VoxelFace),
+    ChangeMode(SlopeMode),
+    Confirm,
+    Cancel,
+}
+
+pub fn slope_default_input_map(
+    voxel_cursor: VoxelCursor,
+    keyboard: Res<Input<KeyCode>>,
+    current_tool: Res<CurrentTool>,
+    mode: Res<SlopeMode>,
+    mut events: EventWriter<SlopeEvents>,
+) {
+    let state = if let CurrentTool::Slope(state) = *current_tool {
+        state
+    } else {
+        return;
+    };
+
+    if keyboard.just_pressed(KeyCode::Tab) {
+        let next_mode = match *mode {
+            SlopeMode::Fill => SlopeMode::Carve,
+            SlopeMode::Carve => SlopeMode::Fill,
+        };
+        events.send(SlopeEvents::ChangeMode(next_mode));
+    }
+    if keyboard.just_pressed(KeyCode::Escape) {
+        events.send(SlopeEvents::Cancel);
+        return;
+    }
+
+    match state {
+        SlopeState::SelectingFirstEdge { .. } | SlopeState::SelectingSecondEdge { .. } => {
+            if let Some(face) = voxel_cursor.voxel_just_clicked(MouseButton::Left) {
+                events.send(SlopeEvents::SelectEdgeEnd(face));
+            }
+        }
+        SlopeState::Previewing { .. } => {
+            if keyboard.just_pressed(KeyCode::Return) {
+                events.send(SlopeEvents::Confirm);
+            }
+        }
+    }
+}
+
+pub fn slope_tool_system(
+    mut current_tool: ResMut<CurrentTool>,
+    mut mode: ResMut<SlopeMode>,
+    mut voxel_editor: SnapshottingVoxelEditor,
+    voxel_map: Res<SdfVoxelMap>,
+    mut events: EventReader<SlopeEvents>,
+) {
+    let state = if let CurrentTool::Slope(state) = &mut *current_tool {
+        state
+    } else {
+        return;
+    };
+
+    for event in events.iter() {
+        match event {
+            SlopeEvents::SelectEdgeEnd(face) => match *state {
+                SlopeState::SelectingFirstEdge { start: None } => {
+                    *state = SlopeState::SelectingFirstEdge { start: Some(*face) };
+                }
+                SlopeState::SelectingFirstEdge { start: Some(start) } => {
+                    *state = SlopeState::SelectingSecondEdge {
+                        first_edge: SlopeEdge { start, end: *face },
+                        start: None,
+                    };
+                }
+                SlopeState::SelectingSecondEdge {
+                    first_edge,
+                    start: None,
+                } => {
+                    *state = SlopeState::SelectingSecondEdge {
+                        first_edge,
+                        start: Some(*face),
+                    };
+                }
+                SlopeState::SelectingSecondEdge {
+                    first_edge,
+                    start: Some(start),
+                } => {
+                    let second_edge = SlopeEdge { start, end: *face };
+                    if let Some(ramp) = Ramp::between(&first_edge, &second_edge) {
+                        write_ramp(&ramp, *mode, &*voxel_map, &mut voxel_editor);
+                        *state = SlopeState::Previewing {
+                            first_edge,
+                            second_edge,
+                        };
+                    } else {
+                        println!("Slope edges must be on parallel faces in different places");
+                        *state = SlopeState::default();
+                    }
+                }
+                SlopeState::Previewing { .. } => {}
+            },
+            SlopeEvents::ChangeMode(new_mode) => {
+                println!("Slope mode: {:?}", new_mode);
+                *mode = *new_mode;
+                if let SlopeState::Previewing {
+                    first_edge,
+                    second_edge,
+                } = *state
+                {
+                    if let Some(ramp) = Ramp::between(&first_edge, &second_edge) {
+                        voxel_editor.abort_transaction();
+                        write_ramp(&ramp, *mode, &*voxel_map, &mut voxel_editor);
+                    }
+                }
+            }
+            SlopeEvents::Confirm => {
+                if let SlopeState::Previewing { .. } = *state {
+                    voxel_editor.commit_transaction();
+                    *state = SlopeState::default();
+                }
+            }
+            SlopeEvents::Cancel => {
+                voxel_editor.abort_transaction();
+                *state = SlopeState::default();
+            }
+        }
+    }
+}
+
+/// An incline between two edges. The incline rises linearly along the `run_axis` from the surface at the first edge to the
+/// surface at the second edge, and it spans both edges along the other axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ramp {
+    /// The normal of the faces that the edges are on. Solid voxels are on the opposite side of the incline.
+    up: SignedAxis3,
+    run_axis: Axis3,
+    /// The run coordinate of the voxel centers of each edge.
+    run_start: f32,
+    run_end: f32,
+    /// The up coordinate of the surface at each edge.
+    height_start: f32,
+    height_end: f32,
+    /// The voxels that the ramp can change.
+    extent: Extent3i,
+}
+
+impl Ramp {
+    fn between(first: &SlopeEdge, second: &SlopeEdge) -> Option<Self> {
+        let up = first.start.normal;
+        let faces = [first.start, first.end, second.start, second.end];
+        if faces.iter().any(|f| f.normal != up) {
+            return None;
+        }
+
+        let mut in_plane = [Axis3::X, Axis3::Y, Axis3::Z]
+            .iter()
+            .cloned()
+            .filter(|a| *a != up.axis);
+        let (axis1, axis2) = (in_plane.next().unwrap(), in_plane.next().unwrap());
+        let center = |edge: &SlopeEdge, axis: Axis3| {
+            (edge.start.point.axis_component(axis) + edge.end.point.axis_component(axis)) as f32
+                / 2.0
+                + 0.5
+        };
+        // The ramp runs in the direction that separates the edges the most.
+        let run_axis = if (center(second, axis1) - center(first, axis1)).abs()
+            >= (center(second, axis2) - center(first, axis2)).abs()
+        {
+            axis1
+        } else {
+            axis2
+        };
+        let run_start = center(first, run_axis);
+        let run_end = center(second, run_axis);
+        if run_start == run_end {
+            return None;
+        }
+
+        let surface = |face: &VoxelFace| {
+            let coord = face.point.axis_component(up.axis);
+            if up.sign > 0 {
+                coord + 1
+            } else {
+                coord
+            }
+        };
+        let height = |edge: &SlopeEdge| (surface(&edge.start) + surface(&edge.end)) as f32 / 2.0;
+        let height_start = height(first);
+        let height_end = height(second);
+
+        // Cover every voxel between the edges, plus one layer on either side of the incline so its distances stay smooth.
+        let mut min = faces[0].point;
+        let mut max = faces[0].point;
+        for face in faces.iter() {
+            min = min.meet(face.point);
+            max = max.join(face.point);
+        }
+        let low = height_start.min(height_end).floor() as i32 - 1;
+        let high = height_start.max(height_end).ceil() as i32;
+        *min.axis_component_mut(up.axis) = low;
+        *max.axis_component_mut(up.axis) = high;
+
+        Some(Self {
+            up,
+            run_axis,
+            run_start,
+            run_end,
+            height_start,
+            height_end,
+            extent: Extent3i::from_min_and_max(min, max),
+        })
+    }
+
+    /// The signed distance from the center of the voxel at `p` to the incline. It's negative on the solid side.
+    fn signed_distance(&self, p: Point3i) -> f32 {
+        let run = p.axis_component(self.run_axis) as f32 + 0.5;
+        let slope = (self.height_end - self.height_start) / (self.run_end - self.run_start);
+        let t = ((run - self.run_start) / (self.run_end - self.run_start))
+            .max(0.0)
+            .min(1.0);
+        let height = self.height_start + t * (self.height_end - self.height_start);
+        let above = p.axis_component(self.up.axis) as f32 + 0.5 - height;
+
+        // Scale the vertical distance by the slope to get the distance perpendicular to the incline.
+        self.up.sign as f32 * above / (1.0 + slope * slope).sqrt()
+    }
+}
+
+fn write_ramp(
+    ramp: &Ramp,
+    mode: SlopeMode,
+    voxel_map: &SdfVoxelMap,
+    voxel_editor: &mut SnapshottingVoxelEditor,
+) {
+    let ramp_type = ramp_voxel_type(voxel_map, ramp);
+
+    voxel_editor.edit_extent_in_transaction(
+        ramp.extent,
+        |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+            let ramp_dist = Sd8::from(ramp.signed_distance(p));
+            match mode {
+                SlopeMode::Fill => {
+                    if ramp_dist.0 < v_dist.0 {
+                        *v_dist = ramp_dist;
+                        if v_dist.0 < 0 && voxel_map.palette.get_voxel_type_info(*v_type).is_empty {
+                            *v_type = ramp_type;
+                        }
+                    }
+                }
+                SlopeMode::Carve => {
+                    if ramp_dist.0 > v_dist.0 {
+                        *v_dist = ramp_dist;
+                        if v_dist.0 >= 0 {
+                            *v_type = EMPTY_VOXEL_TYPE;
+                        }
+                    }
+                }
+            }
+        },
+    );
+}
+
+/// The ramp is made of whatever the surface is made of where it starts, i.e. the type of the solid voxel under the first edge.
+fn ramp_voxel_type(voxel_map: &SdfVoxelMap, ramp: &Ramp) -> VoxelType {
+    let surface = ramp.height_start.floor() as i32;
+    let mut below_start = ramp.extent.minimum;
+    *below_start.axis_component_mut(ramp.run_axis) = ramp.run_start.floor() as i32;
+    *below_start.axis_component_mut(ramp.up.axis) = if ramp.up.sign > 0 {
+        surface - 1
+    } else {
+        surface
+    };
+    let voxel = voxel_map
+        .copy_extent_without_caching(Extent3i::from_min_and_shape(below_start, PointN([1; 3])))
+        .get(below_start);
+
+    if voxel_map.palette.get_voxel_type_info(voxel.0).is_empty {
+        VoxelType(1)
+    } else {
+        voxel.0
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn top_face(x: i32, y: i32, z: i32) -> VoxelFace {
+        VoxelFace {
+            point: PointN([x, y, z]),
+            normal: SignedAxis3::from_vector(PointN([0, 1, 0])).unwrap(),
+        }
+    }
+
+    #[test]
+    fn ramp_rises_from_the_first_edge_to_the_second() {
+        let low = SlopeEdge {
+            start: top_face(0, 3, 0),
+            end: top_face(0, 3, 2),
+        };
+        let high = SlopeEdge {
+            start: top_face(4, 7, 0),
+            end: top_face(4, 7, 2),
+        };
+        let ramp = Ramp::between(&low, &high).unwrap();
+
+        assert_eq!(ramp.run_axis, Axis3::X);
+        assert_eq!(
+            ramp.extent,
+            Extent3i::from_min_and_max(PointN([0, 3, 0]), PointN([4, 8, 2]))
+        );
+        // The surface is at y = 4 over the first edge and at y = 8 over the second.
+        assert!(ramp.signed_distance(PointN([0, 3, 1])) < 0.0);
+        assert!(ramp.signed_distance(PointN([0, 4, 1])) > 0.0);
+        assert!(ramp.signed_distance(PointN([2, 5, 1])) < 0.0);
+        assert!(ramp.signed_distance(PointN([2, 6, 1])) > 0.0);
+        assert!(ramp.signed_distance(PointN([4, 7, 1])) < 0.0);
+        // The distance is measured perpendicular to the incline, which has a slope of 1.
+        let d = ramp.signed_distance(PointN([2, 6, 1]));
+        assert!((d - 0.5 / 2.0f32.sqrt()).abs() < 1e-5);
+
+        let wall = SlopeEdge {
+            start: VoxelFace {
+                point: PointN([4, 7, 0]),
+                normal: SignedAxis3::from_vector(PointN([1, 0, 0])).unwrap(),
+            },
+            end: top_face(4, 7, 2),
+        };
+        assert_eq!(Ramp::between(&low, &wall), None);
+        assert_eq!(Ramp::between(&low, &low), None);
+    }
+}
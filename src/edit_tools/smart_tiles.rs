@@ -0,0 +1,114 @@
+mod rules;
+
+pub use rules::{
+    Prefab, PrefabVoxel, SmartTileError, SmartTileRule, SmartTileRuleSet, SmartTileRules,
+    TileAction, TileMatch, TilePattern,
+};
+
+use super::{selection::SelectionState, SnapshottingVoxelEditor};
+
+use crate::{SdfVoxelMap, SdfVoxelPalette, VoxelType};
+
+use bevy::{
+    ecs::prelude::*,
+    input::prelude::*,
+    prelude::{EventReader, EventWriter},
+};
+use building_blocks::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum SmartTileEvents {
+    /// Runs the `SmartTileRules` over the selected voxels. Running them again on the result is harmless, so the selection can
+    /// be tiled again after it's reshaped.
+    Apply,
+}
+
+pub fn smart_tiles_default_input_map(
+    keyboard: Res<Input<KeyCode>>,
+    mut events: EventWriter<SmartTileEvents>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    if ctrl && keyboard.just_pressed(KeyCode::T) {
+        events.send(SmartTileEvents::Apply);
+    }
+}
+
+pub fn smart_tiles_system(
+    rules: Option<Res<SmartTileRules>>,
+    selection_state: Res<SelectionState>,
+    voxel_map: Res<SdfVoxelMap>,
+    mut voxel_editor: SnapshottingVoxelEditor,
+    mut events: EventReader<SmartTileEvents>,
+) {
+    for event in events.iter() {
+        match event {
+            SmartTileEvents::Apply => {
+                let rules = if let Some(rules) = &rules {
+                    rules
+                } else {
+                    println!("No smart tile rules are loaded");
+                    continue;
+                };
+                let extent = if let Some(extent) = selection_state.selected_extent() {
+                    voxel_map.clip_to_bounds(extent)
+                } else {
+                    println!("Nothing is selected to tile");
+                    continue;
+                };
+                if extent.is_empty() {
+                    continue;
+                }
+
+                let neighborhood =
+                    voxel_map.copy_extent_without_caching(extent.padded(rules.reach()));
+                let matches = rules.find_matches(&neighborhood, extent, &voxel_map.palette);
+                // Later types win, so prefabs are stamped over the other types.
+                let types: HashMap<Point3i, VoxelType> =
+                    rules.voxel_types(&matches).into_iter().collect();
+                let write_extent = if let Some(e) = bounding_extent(types.keys()) {
+                    voxel_map.clip_to_bounds(e)
+                } else {
+                    continue;
+                };
+
+                let palette = &voxel_map.palette;
+                voxel_editor.edit_extent_and_touch_neighbors(
+                    write_extent,
+                    |p: Point3i, voxel: (&mut VoxelType, &mut Sd8)| {
+                        if let Some(new_type) = types.get(&p) {
+                            set_voxel_type(voxel, *new_type, palette);
+                        }
+                    },
+                );
+                voxel_editor.finish_edit();
+                println!("Smart tiled {} voxels in {:?}", matches.len(), extent);
+            }
+        }
+    }
+}
+
+fn bounding_extent<'a>(mut points: impl Iterator<Item = &'a Point3i>) -> Option<Extent3i> {
+    let first = *points.next()?;
+    let (min, max) = points.fold((first, first), |(min, max), p| (min.meet(*p), max.join(*p)));
+
+    Some(Extent3i::from_min_and_max(min, max))
+}
+
+/// Gives the voxel `new_type`. If that changes whether the voxel is solid, its distance is moved to just across the surface.
+fn set_voxel_type(
+    (v_type, v_dist): (&mut VoxelType, &mut Sd8),
+    new_type: VoxelType,
+    palette: &SdfVoxelPalette,
+) {
+    let is_empty = |t: VoxelType| palette.get_voxel_type_info(t).is_empty;
+    if is_empty(*v_type) != is_empty(new_type) {
+        *v_dist = if is_empty(new_type) {
+            Sd8::ONE
+        } else {
+            Sd8::NEG_ONE
+        };
+    }
+    *v_type = new_type;
+}
@@ -0,0 +1,455 @@
+use crate::{PaletteAsset, SdfArray, SdfVoxelPalette, VoxelType};
+
+use building_blocks::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+/// Rules for tiling a carved-out volume, as authored in a `.ron` file. Each voxel in the volume is given to the first rule whose
+/// pattern it matches.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SmartTileRuleSet {
+    pub rules: Vec<SmartTileRule>,
+    #[serde(default)]
+    pub prefabs: Vec<Prefab>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SmartTileRule {
+    pub pattern: TilePattern,
+    pub action: TileAction,
+}
+
+/// A neighborhood of voxels that's recognized as part of a structure. Every pattern faces in some direction, which is used to
+/// turn stamped prefabs.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum TilePattern {
+    /// A solid voxel with empty space above it. Faces up.
+    Floor,
+    /// A solid voxel with empty space below it. Faces down.
+    Ceiling,
+    /// A solid voxel with empty space beside it. Faces the empty space.
+    Wall,
+    /// A floor voxel next to a floor that's exactly one voxel higher. Faces up the step.
+    Stair,
+    /// The bottom voxel of an opening through a wall that's one voxel wide, stands on a floor, and is at most `max_height`
+    /// voxels tall. Faces along the passage.
+    Doorway { max_height: i32 },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum TileAction {
+    /// Gives the voxel the named type.
+    SetType(String),
+    /// Places the named prefab with its origin at the voxel, turned so the prefab's +Z faces the way that the pattern faces.
+    Stamp(String),
+}
+
+/// A small structure that's stamped into the map, like a door frame.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Prefab {
+    pub name: String,
+    pub voxels: Vec<PrefabVoxel>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PrefabVoxel {
+    /// Relative to the voxel that matched, before the prefab is turned.
+    pub offset: [i32; 3],
+    /// The name of any voxel type, so empty types carve.
+    pub voxel_type: String,
+}
+
+impl SmartTileRuleSet {
+    pub fn read_file(path: impl AsRef<Path>) -> Result<Self, SmartTileError> {
+        let reader = std::fs::File::open(path)?;
+
+        Ok(ron::de::from_reader(reader)?)
+    }
+
+    pub fn resolve(&self, palette: &PaletteAsset) -> Result<SmartTileRules, SmartTileError> {
+        let find_type = |name: &str| {
+            palette
+                .voxel_type_by_name(name)
+                .ok_or_else(|| SmartTileError::UnknownVoxelType(name.to_string()))
+        };
+
+        let mut rules = Vec::with_capacity(self.rules.len());
+        for rule in self.rules.iter() {
+            let action = match &rule.action {
+                TileAction::SetType(name) => ResolvedAction::SetType(find_type(name)?),
+                TileAction::Stamp(name) => {
+                    let prefab = self
+                        .prefabs
+                        .iter()
+                        .find(|p| p.name == *name)
+                        .ok_or_else(|| SmartTileError::UnknownPrefab(name.clone()))?;
+                    let voxels = prefab
+                        .voxels
+                        .iter()
+                        .map(|v| Ok((PointN(v.offset), find_type(&v.voxel_type)?)))
+                        .collect::<Result<_, SmartTileError>>()?;
+                    ResolvedAction::Stamp(voxels)
+                }
+            };
+            rules.push((rule.pattern, action));
+        }
+
+        Ok(SmartTileRules { rules })
+    }
+}
+
+/// A `SmartTileRuleSet` resolved against a palette.
+#[derive(Clone, Debug)]
+pub struct SmartTileRules {
+    rules: Vec<(TilePattern, ResolvedAction)>,
+}
+
+#[derive(Clone, Debug)]
+enum ResolvedAction {
+    SetType(VoxelType),
+    Stamp(Vec<(Point3i, VoxelType)>),
+}
+
+/// A voxel that matched the pattern of a rule.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TileMatch {
+    pub point: Point3i,
+    /// The index of the rule.
+    pub rule: usize,
+    /// A unit vector in the direction that the pattern faces.
+    pub facing: Point3i,
+}
+
+const X: Point3i = PointN([1, 0, 0]);
+const UP: Point3i = PointN([0, 1, 0]);
+const DOWN: Point3i = PointN([0, -1, 0]);
+const Z: Point3i = PointN([0, 0, 1]);
+const HORIZONTAL_OFFSETS: [Point3i; 4] = [X, PointN([-1, 0, 0]), Z, PointN([0, 0, -1])];
+
+impl SmartTileRules {
+    /// How far from a voxel the patterns look. Matching the voxels of an extent needs the voxels of the extent padded by this
+    /// much.
+    pub fn reach(&self) -> i32 {
+        self.rules
+            .iter()
+            .fold(2, |reach, (pattern, _)| match pattern {
+                TilePattern::Doorway { max_height } => reach.max(*max_height),
+                _ => reach,
+            })
+    }
+
+    /// Finds the first rule that each voxel in `extent` matches. `voxels` should cover `extent` padded by `reach`, and anything
+    /// outside of it is treated as empty.
+    pub fn find_matches(
+        &self,
+        voxels: &SdfArray,
+        extent: Extent3i,
+        palette: &SdfVoxelPalette,
+    ) -> Vec<TileMatch> {
+        let neighborhood = Neighborhood { voxels, palette };
+        let mut matches = Vec::new();
+        voxels.for_each(&extent, |p: Point3i, _voxel: (VoxelType, Sd8)| {
+            let first_match = self.rules.iter().enumerate().find_map(|(i, (pattern, _))| {
+                neighborhood
+                    .match_pattern(*pattern, p)
+                    .map(|facing| TileMatch {
+                        point: p,
+                        rule: i,
+                        facing,
+                    })
+            });
+            matches.extend(first_match);
+        });
+
+        matches
+    }
+
+    /// The voxel types that the rules give to the `matches`. Prefabs are stamped after all of the types are set, so they win
+    /// where they overlap.
+    pub fn voxel_types(&self, matches: &[TileMatch]) -> Vec<(Point3i, VoxelType)> {
+        let mut types = Vec::new();
+        let mut stamps = Vec::new();
+        for m in matches.iter() {
+            match &self.rules[m.rule].1 {
+                ResolvedAction::SetType(voxel_type) => types.push((m.point, *voxel_type)),
+                ResolvedAction::Stamp(voxels) => stamps.extend(
+                    voxels
+                        .iter()
+                        .map(|(offset, t)| (m.point + turn_to_face(*offset, m.facing), *t)),
+                ),
+            }
+        }
+        types.extend(stamps);
+
+        types
+    }
+}
+
+/// Turns `offset` about the Y axis so that +Z points along `facing`. Offsets aren't turned for vertical facings.
+fn turn_to_face(offset: Point3i, facing: Point3i) -> Point3i {
+    let [x, y, z] = offset.0;
+    match facing.0 {
+        [1, 0, 0] => PointN([z, y, -x]),
+        [-1, 0, 0] => PointN([-z, y, x]),
+        [0, 0, -1] => PointN([-x, y, -z]),
+        _ => offset,
+    }
+}
+
+struct Neighborhood<'a> {
+    voxels: &'a SdfArray,
+    palette: &'a SdfVoxelPalette,
+}
+
+impl<'a> Neighborhood<'a> {
+    fn is_solid(&self, p: Point3i) -> bool {
+        self.voxels.extent().contains(p)
+            && !self
+                .palette
+                .get_voxel_type_info(self.voxels.get(p).0)
+                .is_empty
+    }
+
+    /// The direction that the pattern faces at `p`, if `p` matches it.
+    fn match_pattern(&self, pattern: TilePattern, p: Point3i) -> Option<Point3i> {
+        match pattern {
+            TilePattern::Floor => self.is_floor(p).then(|| UP),
+            TilePattern::Ceiling => (self.is_solid(p) && !self.is_solid(p - UP)).then(|| DOWN),
+            TilePattern::Wall => {
+                if !self.is_solid(p) {
+                    return None;
+                }
+                HORIZONTAL_OFFSETS
+                    .iter()
+                    .cloned()
+                    .find(|d| !self.is_solid(p + *d))
+            }
+            TilePattern::Stair => {
+                if !self.is_floor(p) {
+                    return None;
+                }
+                HORIZONTAL_OFFSETS
+                    .iter()
+                    .cloned()
+                    .find(|d| self.is_floor(p + *d + UP))
+            }
+            TilePattern::Doorway { max_height } => self.match_doorway(p, max_height),
+        }
+    }
+
+    fn is_floor(&self, p: Point3i) -> bool {
+        self.is_solid(p) && !self.is_solid(p + UP)
+    }
+
+    fn match_doorway(&self, p: Point3i, max_height: i32) -> Option<Point3i> {
+        if !self.is_solid(p - UP) {
+            return None;
+        }
+
+        // The opening is walled in on one horizontal axis, and the passage goes through it along the other.
+        for (side, passage) in [(X, Z), (Z, X)].iter().cloned() {
+            let is_opening = |q: Point3i| {
+                !self.is_solid(q) && self.is_solid(q + side) && self.is_solid(q - side)
+            };
+            if !is_opening(p) || self.is_solid(p + passage) || self.is_solid(p - passage) {
+                continue;
+            }
+            for height in 1..=max_height {
+                let q = p + PointN([0, height, 0]);
+                if self.is_solid(q) {
+                    return Some(passage);
+                }
+                if !is_opening(q) {
+                    break;
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[derive(Debug)]
+pub enum SmartTileError {
+    Io(std::io::Error),
+    Ron(ron::Error),
+    UnknownVoxelType(String),
+    UnknownPrefab(String),
+}
+
+impl std::fmt::Display for SmartTileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SmartTileError::Io(e) => write!(f, "{}", e),
+            SmartTileError::Ron(e) => write!(f, "{}", e),
+            SmartTileError::UnknownVoxelType(name) => write!(f, "Unknown voxel type {}", name),
+            SmartTileError::UnknownPrefab(name) => write!(f, "Unknown prefab {}", name),
+        }
+    }
+}
+
+impl std::error::Error for SmartTileError {}
+
+impl From<std::io::Error> for SmartTileError {
+    fn from(e: std::io::Error) -> Self {
+        SmartTileError::Io(e)
+    }
+}
+
+impl From<ron::Error> for SmartTileError {
+    fn from(e: ron::Error) -> Self {
+        SmartTileError::Ron(e)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ambient_sdf_array;
+
+    const TEST_PALETTE: &str = r#"(
+        base_color_textures: ["test.png"],
+        material_layers: ["rock", "grass"],
+        voxel_types: [
+            (name: "air", is_empty: true),
+            (name: "rock", is_empty: false, material: Some("rock")),
+            (name: "grass", is_empty: false, material: Some("grass")),
+            (name: "stone", is_empty: false, material: Some("rock")),
+            (name: "wood", is_empty: false, material: Some("grass")),
+        ],
+    )"#;
+
+    const TEST_RULES: &str = r#"(
+        rules: [
+            (pattern: Doorway(max_height: 3), action: Stamp("door_frame")),
+            (pattern: Stair, action: SetType("stone")),
+            (pattern: Floor, action: SetType("grass")),
+            (pattern: Wall, action: SetType("stone")),
+        ],
+        prefabs: [
+            (
+                name: "door_frame",
+                voxels: [
+                    (offset: (-1, 0, 0), voxel_type: "wood"),
+                    (offset: (1, 0, 0), voxel_type: "wood"),
+                    (offset: (0, 2, 0), voxel_type: "wood"),
+                ],
+            ),
+        ],
+    )"#;
+
+    /// A 7x6x7 box of rock with a 5x4x5 room carved out of it, a step along one side of the room, and a doorway 2 voxels tall
+    /// through the +Z wall.
+    fn room_fixture() -> SdfArray {
+        let mut voxels = ambient_sdf_array(Extent3i::from_min_and_shape(
+            PointN([-3; 3]),
+            PointN([13, 12, 13]),
+        ));
+        let mut fill = |min: [i32; 3], shape: [i32; 3], voxel: (VoxelType, Sd8)| {
+            voxels.for_each_mut(
+                &Extent3i::from_min_and_shape(PointN(min), PointN(shape)),
+                |_p: Point3i, v: (&mut VoxelType, &mut Sd8)| {
+                    *v.0 = voxel.0;
+                    *v.1 = voxel.1;
+                },
+            )
+        };
+        let rock = (VoxelType(1), Sd8::NEG_ONE);
+        let air = (VoxelType(0), Sd8::ONE);
+        fill([0, 0, 0], [7, 6, 7], rock);
+        fill([1, 1, 1], [5, 4, 5], air);
+        fill([1, 1, 1], [5, 1, 1], rock);
+        fill([3, 1, 6], [1, 2, 1], air);
+
+        voxels
+    }
+
+    fn test_rules() -> (SmartTileRules, SdfVoxelPalette) {
+        let palette: PaletteAsset = ron::de::from_str(TEST_PALETTE).unwrap();
+        let rule_set: SmartTileRuleSet = ron::de::from_str(TEST_RULES).unwrap();
+
+        (
+            rule_set.resolve(&palette).unwrap(),
+            palette.voxel_palette().unwrap(),
+        )
+    }
+
+    #[test]
+    fn rules_recognize_the_parts_of_a_room() {
+        let (rules, palette) = test_rules();
+        let voxels = room_fixture();
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([7, 6, 7]));
+
+        let matches = rules.find_matches(&voxels, extent, &palette);
+        let find = |p: [i32; 3]| matches.iter().find(|m| m.point == PointN(p)).cloned();
+
+        let doorway = find([3, 1, 6]).unwrap();
+        assert_eq!((doorway.rule, doorway.facing), (0, Z));
+        // Only the bottom of the doorway matches.
+        assert_eq!(find([3, 2, 6]), None);
+
+        let stair = find([3, 0, 2]).unwrap();
+        assert_eq!((stair.rule, stair.facing), (1, PointN([0, 0, -1])));
+
+        let floor = find([3, 0, 3]).unwrap();
+        assert_eq!((floor.rule, floor.facing), (2, UP));
+
+        let wall = find([0, 3, 3]).unwrap();
+        assert_eq!((wall.rule, wall.facing), (3, X));
+
+        // Empty voxels only match doorways.
+        assert_eq!(find([3, 3, 3]), None);
+    }
+
+    #[test]
+    fn prefabs_are_turned_to_face_the_pattern_and_win_over_types() {
+        let (rules, palette) = test_rules();
+        let voxels = room_fixture();
+        let extent = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([7, 6, 7]));
+
+        let matches = rules.find_matches(&voxels, extent, &palette);
+        let types = rules.voxel_types(&matches);
+        let final_type = |p: [i32; 3]| {
+            types
+                .iter()
+                .rev()
+                .find(|(q, _)| *q == PointN(p))
+                .map(|(_, t)| *t)
+        };
+
+        let wood = Some(VoxelType(4));
+        assert_eq!(final_type([2, 1, 6]), wood);
+        assert_eq!(final_type([4, 1, 6]), wood);
+        assert_eq!(final_type([3, 3, 6]), wood);
+        assert_eq!(final_type([2, 3, 6]), Some(VoxelType(3)));
+
+        assert_eq!(turn_to_face(PointN([1, 2, 3]), X), PointN([3, 2, -1]));
+        assert_eq!(turn_to_face(PointN([1, 2, 3]), UP), PointN([1, 2, 3]));
+    }
+
+    #[test]
+    fn resolving_rejects_unknown_names() {
+        let palette: PaletteAsset = ron::de::from_str(TEST_PALETTE).unwrap();
+        let mut rule_set: SmartTileRuleSet = ron::de::from_str(TEST_RULES).unwrap();
+        rule_set.prefabs.clear();
+
+        assert!(matches!(
+            rule_set.resolve(&palette),
+            Err(SmartTileError::UnknownPrefab(name)) if name == "door_frame"
+        ));
+
+        rule_set.rules[0].action = TileAction::SetType("lava".to_string());
+        assert!(matches!(
+            rule_set.resolve(&palette),
+            Err(SmartTileError::UnknownVoxelType(name)) if name == "lava"
+        ));
+    }
+}
@@ -17,7 +17,7 @@ impl<'a> SnapshottingVoxelEditor<'a> {
         edit_func: impl FnMut(Point3i, (&mut VoxelType, &mut Sd8)),
     ) {
         self.timeline
-            .add_extent_to_current_edit(extent, &self.editor.map.voxels);
+            .add_extent_to_current_edit(extent, &*self.editor.map);
         self.editor
             .edit_extent_and_touch_neighbors(extent, edit_func);
     }
@@ -25,4 +25,34 @@ impl<'a> SnapshottingVoxelEditor<'a> {
     pub fn finish_edit(&mut self) {
         self.timeline.store_current_edit();
     }
+
+    pub fn edit_extent_in_transaction(
+        &mut self,
+        extent: Extent3i,
+        edit_func: impl FnMut(Point3i, (&mut VoxelType, &mut Sd8)),
+    ) {
+        self.editor.edit_extent_in_transaction(extent, edit_func);
+    }
+
+    /// Commits the transaction as a single edit on the timeline.
+    pub fn commit_transaction(&mut self) {
+        let chunk_mins = self.editor.commit_transaction();
+        if chunk_mins.is_empty() {
+            return;
+        }
+
+        // The map won't change until the end of the frame, so this snapshots the voxels from before the transaction.
+        let indexer = self.editor.map.voxels.indexer;
+        for chunk_min in chunk_mins.into_iter() {
+            self.timeline.add_extent_to_current_edit(
+                indexer.extent_for_chunk_with_min(chunk_min),
+                &*self.editor.map,
+            );
+        }
+        self.timeline.store_current_edit();
+    }
+
+    pub fn abort_transaction(&mut self) {
+        self.editor.abort_transaction();
+    }
 }
@@ -1,14 +1,25 @@
+mod brush;
+
+pub use brush::BrushShape;
+
+use brush::Brush;
+
 use super::{CurrentTool, SnapshottingVoxelEditor};
 
-use crate::{voxel::EMPTY_VOXEL_TYPE, CursorRay, VoxelCursor, VoxelType};
+use crate::{voxel::EMPTY_VOXEL_TYPE, CursorRay, SdfVoxelMap, VoxelCursor, VoxelType};
 
 use bevy::{ecs::prelude::*, input::prelude::*, prelude::*};
 use building_blocks::{core::prelude::*, storage::Sd8};
+use serde::{Deserialize, Serialize};
 
 pub struct Terraformer {
     edit_radius: u32,
     voxel_type: VoxelType,
+    brush_shape: BrushShape,
     dist_from_camera: Option<f32>,
+    /// The normal of the surface under the cursor, which the `BrushShape::OrientedBox` is turned to. Like the distance from the
+    /// camera, it's locked while editing.
+    surface_normal: Vec3,
 }
 
 impl Default for Terraformer {
@@ -16,14 +27,18 @@ impl Default for Terraformer {
         Self {
             edit_radius: 10,
             voxel_type: VoxelType(1),
+            brush_shape: BrushShape::Sphere,
             dist_from_camera: None,
+            surface_normal: Vec3::Y,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum TerraformerEvents {
     ChangeEditRadius(i8),
     ChangeVoxelType(u8),
+    ChangeBrushShape(BrushShape),
     MakeSolid,
     RemoveSolid,
     FinishEdit,
@@ -32,14 +47,26 @@ pub enum TerraformerEvents {
 pub fn terraformer_default_input_map(
     mut events: EventWriter<TerraformerEvents>,
     keyboard: Res<Input<KeyCode>>,
+    terraformer: Res<Terraformer>,
 ) {
-    // Adjust the edit radius.
+    // Adjust the edit radius, which sizes every brush shape.
     if keyboard.just_pressed(KeyCode::Up) {
         events.send(TerraformerEvents::ChangeEditRadius(1))
     } else if keyboard.just_pressed(KeyCode::Down) {
         events.send(TerraformerEvents::ChangeEditRadius(-1))
     }
 
+    // Cycle through the brush shapes.
+    if keyboard.just_pressed(KeyCode::Right) {
+        events.send(TerraformerEvents::ChangeBrushShape(
+            terraformer.brush_shape.cycle(1),
+        ))
+    } else if keyboard.just_pressed(KeyCode::Left) {
+        events.send(TerraformerEvents::ChangeBrushShape(
+            terraformer.brush_shape.cycle(-1),
+        ))
+    }
+
     // Adjust the voxel type to create.
     if keyboard.just_pressed(KeyCode::Key1) {
         events.send(TerraformerEvents::ChangeVoxelType(1));
@@ -51,9 +78,11 @@ pub fn terraformer_default_input_map(
         events.send(TerraformerEvents::ChangeVoxelType(4));
     }
 
+    // CTRL + X cuts to the clipboard instead.
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
     if keyboard.pressed(KeyCode::Z) {
         events.send(TerraformerEvents::MakeSolid);
-    } else if keyboard.pressed(KeyCode::X) {
+    } else if keyboard.pressed(KeyCode::X) && !ctrl {
         events.send(TerraformerEvents::RemoveSolid);
     }
 
@@ -67,6 +96,7 @@ pub fn terraformer_system(
     mut terraformer: ResMut<Terraformer>,
     mut voxel_editor: SnapshottingVoxelEditor,
     voxel_cursor: VoxelCursor,
+    voxel_map: Res<SdfVoxelMap>,
     cursor_ray: Res<CursorRay>,
     mut events: EventReader<TerraformerEvents>,
 ) {
@@ -81,30 +111,35 @@ pub fn terraformer_system(
         return;
     };
 
-    // Determine the sphere we should edit.
+    // Determine the brush we should edit with.
     let edit_center =
         cursor_ray.origin + terraformer.dist_from_camera.unwrap_or(20.0) * cursor_ray.direction;
     let edit_center = Point3f::from(edit_center).in_voxel();
+    let brush = Brush {
+        shape: terraformer.brush_shape,
+        radius: terraformer.edit_radius as f32,
+        up: terraformer.surface_normal,
+    };
 
     let mut lock_edit_dist_from_camera = false;
     for event in events.iter() {
         match event {
             TerraformerEvents::MakeSolid => {
                 lock_edit_dist_from_camera = true;
-                edit_sphere(
+                edit_brush(
                     TerraformOperation::MakeSolid,
                     edit_center,
-                    terraformer.edit_radius,
+                    &brush,
                     terraformer.voxel_type,
                     &mut voxel_editor,
                 );
             }
             TerraformerEvents::RemoveSolid => {
                 lock_edit_dist_from_camera = true;
-                edit_sphere(
+                edit_brush(
                     TerraformOperation::RemoveSolid,
                     edit_center,
-                    terraformer.edit_radius,
+                    &brush,
                     EMPTY_VOXEL_TYPE,
                     &mut voxel_editor,
                 );
@@ -119,6 +154,10 @@ pub fn terraformer_system(
             TerraformerEvents::ChangeVoxelType(voxel_type) => {
                 terraformer.voxel_type = VoxelType(*voxel_type);
             }
+            TerraformerEvents::ChangeBrushShape(shape) => {
+                println!("Brush shape: {:?}", shape);
+                terraformer.brush_shape = *shape;
+            }
         }
     }
 
@@ -128,32 +167,54 @@ pub fn terraformer_system(
             .maybe_impact
             .as_ref()
             .map(|i| i.impact.toi);
+        if let BrushShape::OrientedBox = terraformer.brush_shape {
+            if let Some((impact, face_normal)) = voxel_cursor.impact.get() {
+                let mut face_vector: Point3f = PointN([0.0; 3]);
+                *face_vector.axis_component_mut(face_normal.axis) = face_normal.sign as f32;
+                terraformer.surface_normal =
+                    surface_normal(&voxel_map, impact.point).unwrap_or_else(|| face_vector.into());
+            }
+        }
+    }
+}
+
+/// The direction that the map's distances increase fastest at `p`, which is the normal of the smooth surface near it.
+fn surface_normal(voxel_map: &SdfVoxelMap, p: Point3i) -> Option<Vec3> {
+    let voxels = voxel_map.copy_extent_without_caching(centered_extent(p, 1));
+    let dist = |offset: [i32; 3]| voxels.get(p + PointN(offset)).1 .0 as f32;
+    let gradient = Vec3::new(
+        dist([1, 0, 0]) - dist([-1, 0, 0]),
+        dist([0, 1, 0]) - dist([0, -1, 0]),
+        dist([0, 0, 1]) - dist([0, 0, -1]),
+    );
+
+    if gradient.length() > 0.0 {
+        Some(gradient.normalize())
+    } else {
+        None
     }
 }
 
-fn edit_sphere(
+fn edit_brush(
     operation: TerraformOperation,
     center: Point3i,
-    radius: u32,
+    brush: &Brush,
     voxel_type: VoxelType,
     voxel_editor: &mut SnapshottingVoxelEditor,
 ) {
-    let fradius = radius as f32;
     let sign = match operation {
         TerraformOperation::MakeSolid => -1,
         TerraformOperation::RemoveSolid => 1,
     };
     voxel_editor.edit_extent_and_touch_neighbors(
-        centered_extent(center, radius),
+        brush.extent(center),
         |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
-            let p_radius = (p - center).norm();
+            let depth = -brush.signed_distance(Point3f::from(p - center).into());
 
-            // Change the SDF faster closer to the center.
+            // Change the SDF faster deeper inside of the brush.
             const SDF_GROWTH_FACTOR: f32 = 20.0;
-            let sdf_delta = sign
-                * (SDF_GROWTH_FACTOR * (1.0 - p_radius / fradius))
-                    .max(0.0)
-                    .round() as i16;
+            let sdf_delta =
+                sign * (SDF_GROWTH_FACTOR * depth / brush.radius).max(0.0).round() as i16;
             let new_dist = v_dist.0 as i16 + sdf_delta;
 
             v_dist.0 = new_dist.max(std::i8::MIN as i16).min(std::i8::MAX as i16) as i8;
@@ -168,7 +229,7 @@ fn edit_sphere(
     );
 }
 
-fn centered_extent(center: Point3i, radius: u32) -> Extent3i {
+pub(super) fn centered_extent(center: Point3i, radius: u32) -> Extent3i {
     let r = radius as i32;
     let min = center - PointN([r; 3]);
     let shape = PointN([2 * r + 1; 3]);
@@ -0,0 +1,208 @@
+use bevy::math::prelude::*;
+use building_blocks::core::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// The shapes that the terraformer can sculpt with.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum BrushShape {
+    Sphere,
+    Cube,
+    /// Upright, as tall as it is wide.
+    Cylinder,
+    /// Upright, half as wide as it is tall.
+    Capsule,
+    /// Upright, with its point at the top.
+    Cone,
+    /// A slab that's half as thick as it is wide, turned so its thickness is along the surface normal under the cursor.
+    OrientedBox,
+}
+
+impl BrushShape {
+    const ALL: [BrushShape; 6] = [
+        BrushShape::Sphere,
+        BrushShape::Cube,
+        BrushShape::Cylinder,
+        BrushShape::Capsule,
+        BrushShape::Cone,
+        BrushShape::OrientedBox,
+    ];
+
+    /// The shape `steps` after this one, wrapping around.
+    pub fn cycle(self, steps: i8) -> Self {
+        let n = Self::ALL.len() as i32;
+        let i = Self::ALL.iter().position(|s| *s == self).unwrap() as i32;
+
+        Self::ALL[(i + steps as i32).rem_euclid(n) as usize]
+    }
+}
+
+/// A `BrushShape` with a size and orientation. All shapes but the `OrientedBox` fit inside of a cube with a half-width of
+/// `radius`; the `OrientedBox` fits inside of one with a half-width of 1.5 times the `radius`.
+#[derive(Clone, Copy, Debug)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub radius: f32,
+    /// The unit vector that the brush's local +Y axis is turned to. Only the `OrientedBox` is turned.
+    pub up: Vec3,
+}
+
+impl Brush {
+    /// The exact signed distance from `offset` (relative to the brush center) to the surface of the brush. It's negative inside.
+    pub fn signed_distance(&self, offset: Vec3) -> f32 {
+        let r = self.radius;
+        match self.shape {
+            BrushShape::Sphere => offset.length() - r,
+            BrushShape::Cube => box_distance(offset, Vec3::splat(r)),
+            BrushShape::Cylinder => {
+                let d = Vec2::new(radial(offset), offset.y).abs() - Vec2::splat(r);
+
+                d.max(Vec2::ZERO).length() + d.x.max(d.y).min(0.0)
+            }
+            BrushShape::Capsule => {
+                let half_segment = 0.5 * r;
+                let on_segment = Vec3::new(0.0, offset.y.max(-half_segment).min(half_segment), 0.0);
+
+                (offset - on_segment).length() - 0.5 * r
+            }
+            BrushShape::Cone => cone_distance(Vec2::new(radial(offset), offset.y), r, r),
+            BrushShape::OrientedBox => {
+                let (tangent, bitangent) = orthonormal_basis(self.up);
+                let local = Vec3::new(
+                    offset.dot(tangent),
+                    offset.dot(self.up),
+                    offset.dot(bitangent),
+                );
+
+                box_distance(local, Vec3::new(r, 0.5 * r, r))
+            }
+        }
+    }
+
+    /// The voxels that the brush can touch when it's centered on the voxel at `center`.
+    pub fn extent(&self, center: Point3i) -> Extent3i {
+        let half_width = match self.shape {
+            BrushShape::OrientedBox => 1.5 * self.radius,
+            _ => self.radius,
+        };
+
+        super::centered_extent(center, half_width.ceil() as u32)
+    }
+}
+
+fn radial(offset: Vec3) -> f32 {
+    Vec2::new(offset.x, offset.z).length()
+}
+
+fn box_distance(offset: Vec3, half_extents: Vec3) -> f32 {
+    let d = offset.abs() - half_extents;
+
+    d.max(Vec3::ZERO).length() + d.x.max(d.y).max(d.z).min(0.0)
+}
+
+/// The distance to a cone with its base of `base_radius` at `-half_height` and its point at `half_height`. `q` is the distance
+/// from the axis and the height along it.
+fn cone_distance(q: Vec2, base_radius: f32, half_height: f32) -> f32 {
+    let h = half_height;
+    let apex = Vec2::new(0.0, h);
+    let side = Vec2::new(-base_radius, 2.0 * h);
+
+    // The closest point on the base disk, and on the slanted side.
+    let cap = Vec2::new(
+        q.x - q.x.min(if q.y < 0.0 { base_radius } else { 0.0 }),
+        q.y.abs() - h,
+    );
+    let t = ((apex - q).dot(side) / side.dot(side)).max(0.0).min(1.0);
+    let slant = q - apex + side * t;
+
+    let sign = if slant.x < 0.0 && cap.y < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+
+    sign * cap.dot(cap).min(slant.dot(slant)).sqrt()
+}
+
+/// Two unit vectors that are perpendicular to `normal` and to each other.
+fn orthonormal_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let not_parallel = if normal.x.abs() < 0.9 {
+        Vec3::X
+    } else {
+        Vec3::Y
+    };
+    let tangent = normal.cross(not_parallel).normalize();
+
+    (tangent, normal.cross(tangent))
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brush(shape: BrushShape) -> Brush {
+        Brush {
+            shape,
+            radius: 4.0,
+            up: Vec3::Y,
+        }
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn brush_distances_are_zero_on_the_surface_and_negative_inside() {
+        let sphere = brush(BrushShape::Sphere);
+        assert_near(sphere.signed_distance(Vec3::new(0.0, 0.0, 4.0)), 0.0);
+        assert_near(sphere.signed_distance(Vec3::ZERO), -4.0);
+
+        let cube = brush(BrushShape::Cube);
+        assert_near(cube.signed_distance(Vec3::new(4.0, 4.0, 4.0)), 0.0);
+        assert_near(cube.signed_distance(Vec3::new(7.0, 0.0, 0.0)), 3.0);
+
+        let cylinder = brush(BrushShape::Cylinder);
+        assert_near(cylinder.signed_distance(Vec3::new(0.0, 4.0, 0.0)), 0.0);
+        assert_near(cylinder.signed_distance(Vec3::new(3.0, 0.0, 0.0)), -1.0);
+
+        let capsule = brush(BrushShape::Capsule);
+        assert_near(capsule.signed_distance(Vec3::new(0.0, 4.0, 0.0)), 0.0);
+        assert_near(capsule.signed_distance(Vec3::new(2.0, 0.0, 0.0)), 0.0);
+
+        let cone = brush(BrushShape::Cone);
+        assert_near(cone.signed_distance(Vec3::new(0.0, 4.0, 0.0)), 0.0);
+        assert_near(cone.signed_distance(Vec3::new(4.0, -4.0, 0.0)), 0.0);
+        assert_near(cone.signed_distance(Vec3::new(0.0, -6.0, 0.0)), 2.0);
+        assert!(cone.signed_distance(Vec3::new(0.0, -3.0, 0.0)) < 0.0);
+        assert!(cone.signed_distance(Vec3::new(3.0, 3.0, 0.0)) > 0.0);
+    }
+
+    #[test]
+    fn oriented_box_is_thin_along_its_up_vector() {
+        let mut slab = brush(BrushShape::OrientedBox);
+        assert_near(slab.signed_distance(Vec3::new(0.0, 2.0, 0.0)), 0.0);
+        assert_near(slab.signed_distance(Vec3::new(4.0, 0.0, 0.0)), 0.0);
+
+        slab.up = Vec3::X;
+        assert_near(slab.signed_distance(Vec3::new(2.0, 0.0, 0.0)), 0.0);
+        assert_near(slab.signed_distance(Vec3::new(0.0, 4.0, 0.0)), 0.0);
+
+        let diagonal = Vec3::new(1.0, 1.0, 0.0).normalize();
+        slab.up = diagonal;
+        assert_near(slab.signed_distance(3.0 * diagonal), 1.0);
+    }
+
+    #[test]
+    fn brush_shapes_cycle_in_both_directions() {
+        assert_eq!(BrushShape::Sphere.cycle(1), BrushShape::Cube);
+        assert_eq!(BrushShape::Sphere.cycle(-1), BrushShape::OrientedBox);
+        assert_eq!(BrushShape::OrientedBox.cycle(1), BrushShape::Sphere);
+    }
+}
@@ -0,0 +1,272 @@
+use super::{
+    clipboard::{paste_min, paste_voxel, Clipboard, PasteMode},
+    CurrentTool, SnapshottingVoxelEditor,
+};
+
+use crate::{
+    camera::CursorRay,
+    geometry::{point3i_serde, ray_plane_intersection, Plane, RayPlaneIntersection},
+    picking::{VoxelCursor, VoxelFace},
+    SdfArray, SdfVoxelMap, SdfVoxelPalette,
+};
+
+use bevy::{
+    ecs::prelude::*,
+    input::prelude::*,
+    prelude::{EventReader, EventWriter},
+};
+use building_blocks::{
+    core::{Axis3, SignedAxis3},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use smooth_bevy_cameras::controllers::unreal::UnrealCameraController;
+
+#[derive(Clone, Copy)]
+pub enum TileState {
+    Ready,
+    Dragging {
+        /// Where the minimum of the first tile is.
+        start_min: Point3i,
+        /// The normal of the face that the tiles are stacked on. They repeat across the plane of the face.
+        normal: SignedAxis3,
+        /// The tile under the cursor, counted in whole tiles from the first one.
+        end_tile: Point3i,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum TileEvents {
+    StartTiling(VoxelFace),
+    /// Repeat the tiles from the first one out to this one, counted in whole tiles.
+    UpdateTiling(#[serde(with = "point3i_serde")] Point3i),
+    FinishTiling,
+    CancelTiling,
+}
+
+pub fn tile_default_input_map(
+    voxel_cursor: VoxelCursor,
+    keyboard: Res<Input<KeyCode>>,
+    current_tool: Res<CurrentTool>,
+    clipboard: Res<Clipboard>,
+    cursor_ray: Res<CursorRay>,
+    mut events: EventWriter<TileEvents>,
+) {
+    let state = if let CurrentTool::Tile(state) = *current_tool {
+        state
+    } else {
+        return;
+    };
+    match state {
+        TileState::Ready => {
+            if let Some(voxel_face) = voxel_cursor.voxel_just_pressed(MouseButton::Left) {
+                events.send(TileEvents::StartTiling(voxel_face));
+            }
+        }
+        TileState::Dragging {
+            start_min,
+            normal,
+            end_tile,
+        } => {
+            let shape = if let Some(voxels) = clipboard.voxels() {
+                voxels.extent().shape
+            } else {
+                return;
+            };
+            if let CursorRay(Some(ray)) = &*cursor_ray {
+                // The tiles follow the cursor across the plane that the first tile sits on.
+                let mut plane_origin = Point3f::from(start_min);
+                if normal.sign < 0 {
+                    *plane_origin.axis_component_mut(normal.axis) +=
+                        shape.axis_component(normal.axis) as f32;
+                }
+                let mut normal_vector: Point3f = PointN([0.0; 3]);
+                *normal_vector.axis_component_mut(normal.axis) = normal.sign as f32;
+                let plane = Plane {
+                    origin: plane_origin.into(),
+                    normal: normal_vector.into(),
+                };
+                if let RayPlaneIntersection::SinglePoint(p) = ray_plane_intersection(ray, &plane) {
+                    let new_end_tile = tile_containing(Point3f::from(p), start_min, shape, normal);
+                    if new_end_tile != end_tile {
+                        events.send(TileEvents::UpdateTiling(new_end_tile));
+                    }
+                }
+            }
+            if keyboard.just_pressed(KeyCode::Escape) {
+                events.send(TileEvents::CancelTiling)
+            } else if voxel_cursor.mouse_input.just_released(MouseButton::Left) {
+                events.send(TileEvents::FinishTiling)
+            }
+        }
+    }
+}
+
+pub fn tile_tool_system(
+    mut current_tool: ResMut<CurrentTool>,
+    mut voxel_editor: SnapshottingVoxelEditor,
+    voxel_map: Res<SdfVoxelMap>,
+    clipboard: Res<Clipboard>,
+    mut mouse_camera_controllers: Query<&mut UnrealCameraController>,
+    mut events: EventReader<TileEvents>,
+) {
+    let state = if let CurrentTool::Tile(state) = &mut *current_tool {
+        state
+    } else {
+        return;
+    };
+    let voxels = if let Some(voxels) = clipboard.voxels() {
+        voxels
+    } else {
+        for _ in events.iter() {
+            println!("Copy something to the clipboard to use it as a tile");
+        }
+        return;
+    };
+
+    for event in events.iter() {
+        match event {
+            TileEvents::StartTiling(voxel_face) => {
+                if let Some(mut controller) = mouse_camera_controllers.iter_mut().next() {
+                    controller.enabled = false;
+                }
+                let start_min = paste_min(voxels.extent().shape, voxel_face);
+                let end_tile = PointN([0; 3]);
+                write_tiles(
+                    voxels,
+                    start_min,
+                    end_tile,
+                    clipboard.paste_mode(),
+                    &voxel_map.palette,
+                    &mut voxel_editor,
+                );
+                *state = TileState::Dragging {
+                    start_min,
+                    normal: voxel_face.normal,
+                    end_tile,
+                };
+            }
+            TileEvents::UpdateTiling(new_end_tile) => {
+                if let TileState::Dragging {
+                    start_min,
+                    end_tile,
+                    ..
+                } = state
+                {
+                    // Rewrite the whole preview, since it can shrink as well as grow.
+                    voxel_editor.abort_transaction();
+                    write_tiles(
+                        voxels,
+                        *start_min,
+                        *new_end_tile,
+                        clipboard.paste_mode(),
+                        &voxel_map.palette,
+                        &mut voxel_editor,
+                    );
+                    *end_tile = *new_end_tile;
+                }
+            }
+            TileEvents::FinishTiling | TileEvents::CancelTiling => {
+                if let Some(mut controller) = mouse_camera_controllers.iter_mut().next() {
+                    controller.enabled = true;
+                }
+                if let TileEvents::FinishTiling = event {
+                    voxel_editor.commit_transaction();
+                } else {
+                    voxel_editor.abort_transaction();
+                }
+                *state = TileState::Ready;
+            }
+        }
+    }
+}
+
+/// The tile that contains `p`, counted in whole tiles of `shape` from the one at `start_min`. Tiles only repeat across the plane
+/// perpendicular to `normal`.
+fn tile_containing(p: Point3f, start_min: Point3i, shape: Point3i, normal: SignedAxis3) -> Point3i {
+    let mut tile = PointN([0; 3]);
+    for axis in [Axis3::X, Axis3::Y, Axis3::Z].iter().cloned() {
+        if axis == normal.axis {
+            continue;
+        }
+        let offset = p.axis_component(axis) - start_min.axis_component(axis) as f32;
+        *tile.axis_component_mut(axis) =
+            (offset / shape.axis_component(axis) as f32).floor() as i32;
+    }
+
+    tile
+}
+
+/// Writes every tile between the one at `start_min` and `end_tile` into the transaction, so they're previewed until it's
+/// committed.
+fn write_tiles(
+    voxels: &SdfArray,
+    start_min: Point3i,
+    end_tile: Point3i,
+    mode: PasteMode,
+    palette: &SdfVoxelPalette,
+    voxel_editor: &mut SnapshottingVoxelEditor,
+) {
+    let pattern_extent = *voxels.extent();
+    let shape = pattern_extent.shape;
+    let scale = |tile: Point3i| {
+        PointN([
+            tile.x() * shape.x(),
+            tile.y() * shape.y(),
+            tile.z() * shape.z(),
+        ])
+    };
+    let first_tile = end_tile.meet(PointN([0; 3]));
+    let last_tile = end_tile.join(PointN([0; 3]));
+    let extent = Extent3i::from_min_and_max(
+        start_min + scale(first_tile),
+        start_min + scale(last_tile) + shape - PointN([1; 3]),
+    );
+
+    voxel_editor.edit_extent_in_transaction(extent, |p, dst| {
+        let local = p - start_min;
+        let wrapped = PointN([
+            local.x().rem_euclid(shape.x()),
+            local.y().rem_euclid(shape.y()),
+            local.z().rem_euclid(shape.z()),
+        ]);
+        paste_voxel(
+            mode,
+            voxels.get(pattern_extent.minimum + wrapped),
+            dst,
+            palette,
+        );
+    });
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_are_counted_in_whole_steps_across_the_plane() {
+        let up = SignedAxis3::from_vector(PointN([0, 1, 0])).unwrap();
+        let start_min = PointN([4, 8, 4]);
+        let shape = PointN([3, 2, 1]);
+
+        assert_eq!(
+            tile_containing(PointN([6.9, 8.0, 4.5]), start_min, shape, up),
+            PointN([0; 3])
+        );
+        assert_eq!(
+            tile_containing(PointN([7.0, 30.0, 6.5]), start_min, shape, up),
+            PointN([1, 0, 2])
+        );
+        assert_eq!(
+            tile_containing(PointN([3.9, 8.0, 3.0]), start_min, shape, up),
+            PointN([-1, 0, -1])
+        );
+    }
+}
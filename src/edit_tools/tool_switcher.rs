@@ -1,13 +1,78 @@
-use super::{CurrentTool, DragFaceState};
-
-use bevy::{ecs::prelude::*, input::prelude::*};
-
-pub fn tool_switcher_system(keyboard: Res<Input<KeyCode>>, mut current_tool: ResMut<CurrentTool>) {
-    if keyboard.just_pressed(KeyCode::D) {
-        println!("Switching to DragFace tool");
-        *current_tool = CurrentTool::DragFace(DragFaceState::SelectionReady);
-    } else if keyboard.just_pressed(KeyCode::T) {
-        println!("Switching to Terraformer tool");
-        *current_tool = CurrentTool::Terraform;
-    }
-}
+use super::{CurrentTool, DragFaceState, SelectionState, SlopeState, TileState};
+use crate::EditTransaction;
+
+use bevy::{
+    ecs::prelude::*,
+    input::prelude::*,
+    prelude::{EventReader, EventWriter},
+};
+use serde::{Deserialize, Serialize};
+use smooth_bevy_cameras::controllers::unreal::UnrealCameraController;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ToolSwitchEvents {
+    SwitchToDragFace,
+    SwitchToTerraformer,
+    SwitchToPaintMaterial,
+    SwitchToTile,
+    SwitchToSlope,
+}
+
+pub fn tool_switcher_default_input_map(
+    keyboard: Res<Input<KeyCode>>,
+    mut events: EventWriter<ToolSwitchEvents>,
+) {
+    // CTRL + letter is a command, not a tool.
+    if keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl) {
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::D) {
+        events.send(ToolSwitchEvents::SwitchToDragFace);
+    } else if keyboard.just_pressed(KeyCode::T) {
+        events.send(ToolSwitchEvents::SwitchToTerraformer);
+    } else if keyboard.just_pressed(KeyCode::B) {
+        events.send(ToolSwitchEvents::SwitchToPaintMaterial);
+    } else if keyboard.just_pressed(KeyCode::G) {
+        events.send(ToolSwitchEvents::SwitchToTile);
+    } else if keyboard.just_pressed(KeyCode::L) {
+        events.send(ToolSwitchEvents::SwitchToSlope);
+    }
+}
+
+pub fn tool_switcher_system(
+    mut current_tool: ResMut<CurrentTool>,
+    mut transaction: ResMut<EditTransaction>,
+    mut selection_state: ResMut<SelectionState>,
+    mut mouse_camera_controllers: Query<&mut UnrealCameraController>,
+    mut events: EventReader<ToolSwitchEvents>,
+) {
+    for event in events.iter() {
+        // The new tool doesn't know about the old tool's preview.
+        current_tool.stop_editing(&mut *selection_state, &mut mouse_camera_controllers);
+        transaction.abort();
+
+        match event {
+            ToolSwitchEvents::SwitchToDragFace => {
+                println!("Switching to DragFace tool");
+                *current_tool = CurrentTool::DragFace(DragFaceState::SelectionReady);
+            }
+            ToolSwitchEvents::SwitchToTerraformer => {
+                println!("Switching to Terraformer tool");
+                *current_tool = CurrentTool::Terraform;
+            }
+            ToolSwitchEvents::SwitchToPaintMaterial => {
+                println!("Switching to PaintMaterial tool");
+                *current_tool = CurrentTool::PaintMaterial;
+            }
+            ToolSwitchEvents::SwitchToTile => {
+                println!("Switching to Tile tool");
+                *current_tool = CurrentTool::Tile(TileState::Ready);
+            }
+            ToolSwitchEvents::SwitchToSlope => {
+                println!("Switching to Slope tool");
+                *current_tool = CurrentTool::Slope(SlopeState::default());
+            }
+        }
+    }
+}
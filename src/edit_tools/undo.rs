@@ -1,17 +1,45 @@
-use super::edit_timeline::EditTimeline;
+use super::{edit_timeline::EditTimeline, CurrentTool, SelectionState};
 use crate::VoxelEditor;
 
-use bevy::{ecs::prelude::*, input::prelude::*};
+use bevy::{
+    ecs::prelude::*,
+    input::prelude::*,
+    prelude::{EventReader, EventWriter},
+};
+use serde::{Deserialize, Serialize};
+use smooth_bevy_cameras::controllers::unreal::UnrealCameraController;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum UndoEvents {
+    Undo,
+    Redo,
+}
+
+pub fn undo_default_input_map(keys: Res<Input<KeyCode>>, mut events: EventWriter<UndoEvents>) {
+    if keys.just_pressed(KeyCode::U) {
+        events.send(UndoEvents::Undo);
+    }
+    if keys.just_pressed(KeyCode::R) {
+        events.send(UndoEvents::Redo);
+    }
+}
 
 pub fn undo_system(
     mut edit_timeline: ResMut<EditTimeline>,
     mut editor: VoxelEditor,
-    keys: Res<Input<KeyCode>>,
+    mut current_tool: ResMut<CurrentTool>,
+    mut selection_state: ResMut<SelectionState>,
+    mut mouse_camera_controllers: Query<&mut UnrealCameraController>,
+    mut events: EventReader<UndoEvents>,
 ) {
-    if keys.just_pressed(KeyCode::U) {
-        edit_timeline.undo(&mut editor);
-    }
-    if keys.just_pressed(KeyCode::R) {
-        edit_timeline.redo(&mut editor);
+    for event in events.iter() {
+        // A preview copied its chunks from before the undo, so committing it would revert the undo.
+        current_tool.stop_editing(&mut *selection_state, &mut mouse_camera_controllers);
+        editor.abort_transaction();
+
+        match event {
+            UndoEvents::Undo => edit_timeline.undo(&mut editor),
+            UndoEvents::Redo => edit_timeline.redo(&mut editor),
+        }
     }
 }
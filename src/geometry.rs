@@ -122,6 +122,72 @@ pub fn ray_plane_intersection(r: &Ray3, p: &Plane) -> RayPlaneIntersection {
     }
 }
 
+/// The range of `t` where `r.origin + t * r.direction` is inside of the axis-aligned box from `min` to `max`, if any part of the
+/// ray is. The range starts at `t = 0` if the ray's origin is inside of the box.
+pub fn ray_box_intersection(r: &Ray3, min: Vec3, max: Vec3) -> Option<(f32, f32)> {
+    let origin: [f32; 3] = r.origin.into();
+    let direction: [f32; 3] = r.direction.into();
+    let min: [f32; 3] = min.into();
+    let max: [f32; 3] = max.into();
+
+    let mut t_enter = 0.0f32;
+    let mut t_exit = std::f32::INFINITY;
+    for i in 0..3 {
+        if relative_eq!(direction[i], 0.0) {
+            if origin[i] < min[i] || origin[i] > max[i] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (min[i] - origin[i]) / direction[i];
+        let t2 = (max[i] - origin[i]) / direction[i];
+        t_enter = t_enter.max(t1.min(t2));
+        t_exit = t_exit.min(t1.max(t2));
+    }
+
+    if t_enter <= t_exit {
+        Some((t_enter, t_exit))
+    } else {
+        None
+    }
+}
+
+/// Serializes a `Point3i` as `[x, y, z]`. Use with `#[serde(with = "point3i_serde")]`.
+pub mod point3i_serde {
+    use building_blocks::core::{Point3i, PointN};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(p: &Point3i, serializer: S) -> Result<S::Ok, S::Error> {
+        p.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Point3i, D::Error> {
+        <[i32; 3]>::deserialize(deserializer).map(PointN)
+    }
+}
+
+/// Serializes a `SignedAxis3` as its unit vector. Use with `#[serde(with = "signed_axis3_serde")]`.
+pub mod signed_axis3_serde {
+    use building_blocks::core::{prelude::*, SignedAxis3};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(a: &SignedAxis3, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut v: Point3i = PointN([0; 3]);
+        *v.axis_component_mut(a.axis) = a.sign;
+
+        v.0.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SignedAxis3, D::Error> {
+        let v = <[i32; 3]>::deserialize(deserializer)?;
+
+        SignedAxis3::from_vector(PointN(v))
+            .ok_or_else(|| D::Error::custom(format!("{:?} is not a unit vector along an axis", v)))
+    }
+}
+
 // ████████╗███████╗███████╗████████╗
 // ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
 //    ██║   █████╗  ███████╗   ██║
@@ -143,4 +209,22 @@ mod tests {
         assert_eq!(p1, Vec3::new(0.0, 0.0, 0.0));
         assert_eq!(p2, Vec3::new(0.0, 1.0, 0.0));
     }
+
+    #[test]
+    fn ray_box_intersection_finds_entry_and_exit() {
+        let min = Vec3::new(0.0, 0.0, 0.0);
+        let max = Vec3::new(4.0, 4.0, 4.0);
+
+        let through = Ray3::new(Vec3::new(-2.0, 1.0, 1.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(ray_box_intersection(&through, min, max), Some((1.0, 3.0)));
+
+        let inside = Ray3::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(ray_box_intersection(&inside, min, max), Some((0.0, 3.0)));
+
+        let away = Ray3::new(Vec3::new(-2.0, 1.0, 1.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(ray_box_intersection(&away, min, max), None);
+
+        let beside = Ray3::new(Vec3::new(-2.0, 5.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(ray_box_intersection(&beside, min, max), None);
+    }
 }
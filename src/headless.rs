@@ -0,0 +1,172 @@
+use crate::{
+    empty_compressible_sdf_chunk_map, geometry::Ray3, plugin::PluginAdder, BVTPlugin,
+    ChunkCacheConfig, CursorRay, EditBuffer, EditToolsPlugin, ImmediateModePlugin, LodConfig,
+    MapIoPlugin, SdfVoxelMap, SdfVoxelPalette, StatePlugin, VoxelPickingPlugin, VoxelType,
+};
+
+use bevy::{
+    app::{prelude::*, Events},
+    asset::{AddAsset, AssetPlugin},
+    ecs::{prelude::*, system::CommandQueue},
+    input::{
+        keyboard::KeyboardInput, mouse::MouseButtonInput, prelude::*, ElementState, InputPlugin,
+    },
+    pbr::prelude::*,
+    render::prelude::*,
+    MinimalPlugins,
+};
+use building_blocks::prelude::*;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum HeadlessState {
+    Editing,
+}
+
+/// Runs the edit tools on an `SdfVoxelMap` without a window or renderer, so they can be driven by fake input on machines
+/// without a display.
+///
+/// Input goes through bevy's `InputPlugin` as if it came from a window, and the `CursorRay` is set directly instead of being
+/// cast from a camera. Each call to `step` runs one frame. Like in the editor, edits are merged at the end of the frame in
+/// which they're made, and the `VoxelBVT` catches up on the next frame.
+pub struct HeadlessEditor {
+    app: App,
+}
+
+impl HeadlessEditor {
+    pub fn new(chunk_shape: Point3i, palette: SdfVoxelPalette) -> Self {
+        let mut builder = App::build();
+        builder
+            .add_plugins(MinimalPlugins)
+            .add_plugin(InputPlugin::default())
+            // Tools spawn meshes to show what they're doing, even though nothing renders them.
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Mesh>()
+            .add_asset::<StandardMaterial>()
+            .add_plugin(ImmediateModePlugin)
+            .add_plugin(MapIoPlugin::new(
+                ChunkCacheConfig::default(),
+                LodConfig::default(),
+            ))
+            .insert_resource(CursorRay::default());
+        EditToolsPlugin::register_events(&mut builder);
+        builder
+            .add_state(HeadlessState::Editing)
+            .add_system_set(
+                PluginAdder(SystemSet::on_enter(HeadlessState::Editing))
+                    .enter_with_plugin::<EditToolsPlugin>()
+                    .enter_with_plugin::<VoxelPickingPlugin>()
+                    .enter_with_plugin::<BVTPlugin>()
+                    .finish(),
+            )
+            .add_system_set(
+                PluginAdder(SystemSet::on_update(HeadlessState::Editing))
+                    .update_with_plugin::<EditToolsPlugin>()
+                    .update_with_plugin::<BVTPlugin>()
+                    .update_with_plugin::<VoxelPickingPlugin>()
+                    .finish(),
+            );
+
+        let mut app = std::mem::take(&mut builder.app);
+        let voxel_map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(
+                chunk_shape,
+                ChunkCacheConfig::default().compression(),
+            ),
+            palette,
+        );
+        let mut queue = CommandQueue::default();
+        MapIoPlugin::insert_voxel_map(&mut Commands::new(&mut queue, &app.world), voxel_map);
+        queue.apply(&mut app.world);
+
+        Self { app }
+    }
+
+    /// Runs one frame.
+    pub fn step(&mut self) {
+        self.app.update();
+    }
+
+    pub fn step_frames(&mut self, num_frames: usize) {
+        for _ in 0..num_frames {
+            self.step();
+        }
+    }
+
+    pub fn press_key(&mut self, key: KeyCode) {
+        self.send_key(key, ElementState::Pressed);
+    }
+
+    pub fn release_key(&mut self, key: KeyCode) {
+        self.send_key(key, ElementState::Released);
+    }
+
+    pub fn press_mouse_button(&mut self, button: MouseButton) {
+        self.send_mouse_button(button, ElementState::Pressed);
+    }
+
+    pub fn release_mouse_button(&mut self, button: MouseButton) {
+        self.send_mouse_button(button, ElementState::Released);
+    }
+
+    /// The ray that would be cast from the camera through the window's cursor.
+    pub fn set_cursor_ray(&mut self, ray: Option<Ray3>) {
+        *self.app.world.get_resource_mut::<CursorRay>().unwrap() = CursorRay(ray);
+    }
+
+    /// Sets every voxel in `extent`, bypassing the edit tools. The voxels are merged into the map at the end of the next frame.
+    pub fn fill_extent(&mut self, extent: Extent3i, voxel: (VoxelType, Sd8)) {
+        self.app
+            .world
+            .resource_scope(|world, mut edit_buffer: Mut<EditBuffer>| {
+                let voxel_map = world.get_resource::<SdfVoxelMap>().unwrap();
+                edit_buffer.edit_voxels_out_of_place(
+                    voxel_map,
+                    extent,
+                    |_p, (v_type, v_dist)| {
+                        *v_type = voxel.0;
+                        *v_dist = voxel.1;
+                    },
+                    true,
+                );
+            });
+    }
+
+    /// The voxel at `p` in the `SdfVoxelMap`. Edits that haven't been merged yet aren't visible.
+    pub fn voxel(&self, p: Point3i) -> (VoxelType, Sd8) {
+        self.voxel_map()
+            .copy_extent_without_caching(Extent3i::from_min_and_shape(p, PointN([1; 3])))
+            .get(p)
+    }
+
+    pub fn voxel_map(&self) -> &SdfVoxelMap {
+        self.app.world.get_resource::<SdfVoxelMap>().unwrap()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.app.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    fn send_key(&mut self, key: KeyCode, state: ElementState) {
+        self.app
+            .world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key),
+                state,
+            });
+    }
+
+    fn send_mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        self.app
+            .world
+            .get_resource_mut::<Events<MouseButtonInput>>()
+            .unwrap()
+            .send(MouseButtonInput { button, state });
+    }
+}
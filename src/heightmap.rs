@@ -0,0 +1,5 @@
+mod plugin;
+mod terrain;
+
+pub use plugin::{HeightmapEvents, HeightmapPlugin};
+pub use terrain::{Heightmap, HeightmapPlacement};
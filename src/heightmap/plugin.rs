@@ -0,0 +1,85 @@
+use super::{Heightmap, HeightmapPlacement};
+
+use crate::{Config, PaletteAsset, PaletteHandle, StatePlugin, VoxelEditor};
+
+use bevy::{app::prelude::*, asset::prelude::*, ecs::prelude::*, input::prelude::*};
+use building_blocks::prelude::*;
+
+/// Imports the heightmap given by `Config::heightmap` as terrain. Depends on the `MapIoPlugin`.
+pub struct HeightmapPlugin;
+
+impl HeightmapPlugin {
+    pub fn register_events(app: &mut AppBuilder) {
+        app.add_event::<HeightmapEvents>();
+    }
+}
+
+impl StatePlugin for HeightmapPlugin {
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(heightmap_default_input_map.system())
+            .with_system(heightmap_import_system.system())
+    }
+}
+
+pub enum HeightmapEvents {
+    Import,
+}
+
+pub fn heightmap_default_input_map(
+    mut events: EventWriter<HeightmapEvents>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    if ctrl && keyboard.just_pressed(KeyCode::I) {
+        events.send(HeightmapEvents::Import);
+    }
+}
+
+pub fn heightmap_import_system(
+    config: Res<Config>,
+    palette_handle: Res<PaletteHandle>,
+    palettes: Res<Assets<PaletteAsset>>,
+    mut voxel_editor: VoxelEditor,
+    mut events: EventReader<HeightmapEvents>,
+) {
+    for event in events.iter() {
+        match event {
+            HeightmapEvents::Import => {
+                let import = &config.heightmap;
+                let heightmap = match Heightmap::open(&import.path) {
+                    Ok(h) => h,
+                    Err(e) => {
+                        println!("Failed to open heightmap {}: {}", import.path, e);
+                        continue;
+                    }
+                };
+
+                // The palette was validated when it was loaded.
+                let height_bands = palettes
+                    .get(&palette_handle.0)
+                    .and_then(|p| p.height_bands().ok())
+                    .unwrap_or_default();
+                if height_bands.is_empty() {
+                    println!(
+                        "Failed to import heightmap {}: palette {} has no height bands",
+                        import.path, config.palette
+                    );
+                    continue;
+                }
+
+                let placement = HeightmapPlacement {
+                    origin: PointN(import.origin),
+                    horizontal_scale: import.horizontal_scale,
+                    vertical_scale: import.vertical_scale,
+                };
+                let extent = placement.extent(&heightmap);
+                voxel_editor.edit_extent_and_touch_neighbors(extent, |p, (voxel_type, dist)| {
+                    let (new_type, new_dist) = placement.voxel(&heightmap, &height_bands, p);
+                    *voxel_type = new_type;
+                    *dist = new_dist;
+                });
+                println!("Imported heightmap {} into {:?}", import.path, extent);
+            }
+        }
+    }
+}
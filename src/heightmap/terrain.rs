@@ -0,0 +1,181 @@
+use crate::{voxel::EMPTY_VOXEL_TYPE, VoxelType};
+
+use building_blocks::{prelude::*, storage::Sd8};
+use std::path::Path;
+
+/// A grid of heights, normalized to the range `[0, 1]`. Row `y` of the image is along the world Z axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    width: u32,
+    depth: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Reads a grayscale PNG with either 8 or 16 bits per channel. Color images are converted to luma.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_luma16();
+        let (width, depth) = image.dimensions();
+        let heights = image
+            .into_raw()
+            .into_iter()
+            .map(|h| h as f32 / u16::MAX as f32)
+            .collect();
+
+        Ok(Self::new(width, depth, heights))
+    }
+
+    pub fn new(width: u32, depth: u32, heights: Vec<f32>) -> Self {
+        assert_eq!(heights.len(), (width * depth) as usize);
+
+        Self {
+            width,
+            depth,
+            heights,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    fn get(&self, x: u32, z: u32) -> f32 {
+        self.heights[(z * self.width + x) as usize]
+    }
+
+    /// Bilinearly interpolates the height at the continuous pixel coordinates `(x, z)`, clamping to the edges of the image.
+    pub fn sample(&self, x: f32, z: f32) -> f32 {
+        let x = x.max(0.0).min((self.width - 1) as f32);
+        let z = z.max(0.0).min((self.depth - 1) as f32);
+        let x0 = x.floor() as u32;
+        let z0 = z.floor() as u32;
+        let x1 = (x0 + 1).min(self.width - 1);
+        let z1 = (z0 + 1).min(self.depth - 1);
+        let tx = x - x0 as f32;
+        let tz = z - z0 as f32;
+
+        let h0 = self.get(x0, z0) * (1.0 - tx) + self.get(x1, z0) * tx;
+        let h1 = self.get(x0, z1) * (1.0 - tx) + self.get(x1, z1) * tx;
+
+        h0 * (1.0 - tz) + h1 * tz
+    }
+}
+
+/// Places a `Heightmap` in the world. Each pixel covers `horizontal_scale` voxels along X and Z, and a height of 1 is
+/// `vertical_scale` voxels above `origin`.
+#[derive(Clone, Copy, Debug)]
+pub struct HeightmapPlacement {
+    pub origin: Point3i,
+    pub horizontal_scale: f32,
+    pub vertical_scale: f32,
+}
+
+impl HeightmapPlacement {
+    /// The extent of voxels covered by the terrain, including a voxel of padding above the highest point so the surface is closed.
+    pub fn extent(&self, heightmap: &Heightmap) -> Extent3i {
+        let shape = PointN([
+            (heightmap.width() as f32 * self.horizontal_scale).ceil() as i32,
+            self.vertical_scale.ceil() as i32 + 2,
+            (heightmap.depth() as f32 * self.horizontal_scale).ceil() as i32,
+        ]);
+
+        Extent3i::from_min_and_shape(self.origin, shape)
+    }
+
+    /// The height of the terrain surface, in voxels above `origin`, at world coordinates `(x, z)`.
+    fn surface_height(&self, heightmap: &Heightmap, x: f32, z: f32) -> f32 {
+        heightmap.sample(
+            (x - self.origin.x() as f32) / self.horizontal_scale,
+            (z - self.origin.z() as f32) / self.horizontal_scale,
+        ) * self.vertical_scale
+    }
+
+    /// Computes the voxel at `p`. Solid voxels get their type from the `height_bands` (see `PaletteAsset::height_bands`), based on
+    /// the voxel's own normalized height.
+    pub fn voxel(
+        &self,
+        heightmap: &Heightmap,
+        height_bands: &[(f32, VoxelType)],
+        p: Point3i,
+    ) -> (VoxelType, Sd8) {
+        // Sample at voxel centers.
+        let x = p.x() as f32 + 0.5;
+        let z = p.z() as f32 + 0.5;
+        let y = (p.y() - self.origin.y()) as f32 + 0.5;
+
+        let h = self.surface_height(heightmap, x, z);
+        // Scale the vertical distance by the slope to approximate the true distance to the surface.
+        let dh_dx =
+            self.surface_height(heightmap, x + 1.0, z) - self.surface_height(heightmap, x - 1.0, z);
+        let dh_dz =
+            self.surface_height(heightmap, x, z + 1.0) - self.surface_height(heightmap, x, z - 1.0);
+        let slope_scale = (1.0 + 0.25 * (dh_dx * dh_dx + dh_dz * dh_dz)).sqrt();
+        let dist = (y - h) / slope_scale;
+
+        if dist > 0.0 {
+            (EMPTY_VOXEL_TYPE, Sd8::from(dist))
+        } else {
+            let normalized_height = y / self.vertical_scale;
+            let voxel_type = height_bands
+                .iter()
+                .find(|(max_height, _)| normalized_height <= *max_height)
+                .or_else(|| height_bands.last())
+                .map_or(EMPTY_VOXEL_TYPE, |(_, t)| *t);
+
+            (voxel_type, Sd8::from(dist))
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_interpolates_and_clamps() {
+        let heightmap = Heightmap::new(2, 2, vec![0.0, 1.0, 0.0, 1.0]);
+
+        assert_eq!(heightmap.sample(0.5, 0.0), 0.5);
+        assert_eq!(heightmap.sample(0.5, 1.0), 0.5);
+        assert_eq!(heightmap.sample(-3.0, 0.0), 0.0);
+        assert_eq!(heightmap.sample(5.0, 7.0), 1.0);
+    }
+
+    #[test]
+    fn flat_terrain_is_banded_and_signed() {
+        let heightmap = Heightmap::new(2, 2, vec![0.5; 4]);
+        let placement = HeightmapPlacement {
+            origin: PointN([0, 10, 0]),
+            horizontal_scale: 4.0,
+            vertical_scale: 8.0,
+        };
+        let bands = [(0.25, VoxelType(4)), (1.0, VoxelType(1))];
+
+        assert_eq!(
+            placement.extent(&heightmap),
+            Extent3i::from_min_and_shape(PointN([0, 10, 0]), PointN([8, 10, 8]))
+        );
+
+        // The surface is 4 voxels above the origin.
+        let (t, d) = placement.voxel(&heightmap, &bands, PointN([3, 10, 3]));
+        assert_eq!(t, VoxelType(4));
+        assert!(d.0 < 0);
+        let (t, d) = placement.voxel(&heightmap, &bands, PointN([3, 13, 3]));
+        assert_eq!(t, VoxelType(1));
+        assert!(d.0 < 0);
+        let (t, d) = placement.voxel(&heightmap, &bands, PointN([3, 14, 3]));
+        assert_eq!(t, EMPTY_VOXEL_TYPE);
+        assert!(d.0 > 0);
+    }
+}
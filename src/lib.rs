@@ -4,34 +4,86 @@ mod config;
 mod cursor_tracker;
 mod edit_tools;
 mod geometry;
+mod headless;
+mod heightmap;
 mod immediate_mode;
 mod map;
+mod map_file;
 mod map_io;
+mod mesh_export;
+mod obj;
+mod palette;
 mod picking;
 mod plugin;
+mod session;
 mod thread_local_resource;
+mod vox;
 mod voxel;
 mod voxel_renderer;
+mod world_bounds;
 
 pub use bvt::{BVTPlugin, VoxelBVT};
 pub use camera::{create_camera_entity, CameraPlugin, CursorRay};
-pub use config::{CameraConfig, Config};
+pub use config::{
+    CameraConfig, Config, ConfigError, ExportConfig, HeightmapConfig, ObjConfig, SessionConfig,
+    VoxConfig, WorldBoundsConfig,
+};
 pub use cursor_tracker::{CursorPosition, CursorPositionPlugin};
-pub use edit_tools::EditToolsPlugin;
+pub use edit_tools::{
+    BrushShape, Clipboard, ClipboardEvents, DragFaceEvents, EditToolsPlugin, MaterialPainterEvents,
+    PasteMode, SelectionEvents, SelectionState, SlopeEdge, SlopeEvents, SlopeMode, SmartTileError,
+    SmartTileEvents, SmartTileRuleSet, SmartTileRules, TerraformerEvents, TileEvents,
+    ToolSwitchEvents, UndoEvents,
+};
+pub use geometry::Ray3;
+pub use headless::HeadlessEditor;
+pub use heightmap::{Heightmap, HeightmapEvents, HeightmapPlacement, HeightmapPlugin};
 pub use immediate_mode::{ImmediateModePlugin, ImmediateModeTag};
 pub use map::{
     ambient_sdf_array, empty_compressible_sdf_chunk_map, empty_sdf_chunk_hash_map,
-    CompressibleSdfChunkMap, SdfArray, SdfChunkCache, SdfChunkHashMap, SdfChunkMapBuilder,
-    SdfVoxelMap, SdfVoxelPalette, CHUNK_SHAPE,
+    is_valid_chunk_shape, sdf_array_is_ambient, CompressibleSdfChunkMap, SdfArray, SdfChunkCache,
+    SdfChunkHashMap, SdfChunkMapBuilder, SdfVoxelMap, SdfVoxelMapReader, SdfVoxelPalette,
+    DEFAULT_CHUNK_COMPRESSION, DEFAULT_CHUNK_SHAPE,
+};
+pub use map_file::{
+    autosave_path, discard_autosave, has_recovery_data, journal_path, open_map, open_map_reader,
+    read_journal, read_map, recover_map, save_map, write_map, write_map_with_compression,
+    BackgroundSave, EditJournal, MapFile, MapFileError, MapFileEvents, MapFilePlugin,
+    MapFileReader, MapSaveEvents, MapStats, RecoverMap, JOURNAL_MAGIC, JOURNAL_VERSION,
+    MAP_FILE_MAGIC, MAP_FILE_VERSION,
 };
 pub use map_io::{
-    ChunkCacheConfig, DirtyChunks, EmptyChunks, MapIoPlugin, ThreadLocalVoxelCache, VoxelEditor,
+    ancestor_chunk_min, downsample_sdf_chunk, ChunkCacheConfig, ChunkChange, ChunkChangeCursor,
+    ChunkChangeLog, ChunkRemoved, ChunkSwap, ChunkTierCounts, ChunksEdited, DirtyChunks,
+    EditTransaction, EmptyChunks, LodChunks, LodConfig, MapIoPlugin, MapSnapshotHeader,
+    ThreadLocalVoxelCache, UniformChunks, VoxelEditor,
+};
+pub use mesh_export::{
+    export_meshes, material_weights_from_counts, write_gltf, ExportMesh, MeshExportEvents,
+    MeshExportPlugin, MATERIAL_WEIGHTS_ATTRIBUTE,
+};
+pub use obj::{
+    read_obj, voxelize_mesh, CsgOperation, ObjError, ObjEvents, ObjPlugin, TriangleMesh,
+};
+pub use palette::{
+    HeightBand, PaletteAsset, PaletteAssetLoader, PaletteError, PaletteHandle, VoxelTypeDef,
+    MAX_MATERIAL_LAYERS,
 };
 pub use picking::{VoxelCursor, VoxelCursorRayImpact, VoxelPickingPlugin};
 pub use plugin::{BevyPlugins, EditorPlugin, StatePlugin};
+pub use session::{
+    open_session, read_session, SessionError, SessionEvent, SessionFrame, SessionHeader,
+    SessionRecorderPlugin, SessionReplayPlugin, SessionWriter, SESSION_VERSION,
+};
 pub use thread_local_resource::{ThreadLocalResource, ThreadLocalResourceHandle};
+pub use vox::{
+    read_vox, sdf_to_vox_model, vox_model_shape, vox_model_to_sdf, write_vox, VoxColorMapping,
+    VoxError, VoxEvents, VoxFile, VoxMapping, VoxModel, VoxPlugin, VoxTypeMap, VoxVoxel,
+    MAX_VOX_MODEL_SIZE, VOX_MAGIC, VOX_VERSION,
+};
 pub use voxel::{
     VoxelMaterial, VoxelType, VoxelTypeInfo, EMPTY_SDF_VOXEL, EMPTY_SIGNED_DISTANCE,
     EMPTY_VOXEL_TYPE,
 };
 pub use voxel_renderer::SmoothVoxelPbrBundle;
+pub use world_bounds::WorldBoundsPlugin;
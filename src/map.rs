@@ -1,18 +1,80 @@
 use crate::{
-    voxel::{EMPTY_SIGNED_DISTANCE, EMPTY_VOXEL_TYPE},
-    ThreadLocalResourceHandle, VoxelType, VoxelTypeInfo,
+    map_file::MapFileError,
+    map_io::{
+        uniform_chunk_value, ChunkSwap, ChunkTierCounts, MapSnapshot, MapSnapshotHeader,
+        UniformChunks,
+    },
+    voxel::{EMPTY_SDF_VOXEL, EMPTY_SIGNED_DISTANCE, EMPTY_VOXEL_TYPE},
+    ThreadLocalResourceHandle, VoxelMaterial, VoxelType, VoxelTypeInfo,
 };
 
 use building_blocks::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
 pub struct SdfVoxelMap {
     pub voxels: CompressibleSdfChunkMap,
     pub palette: SdfVoxelPalette,
+    /// Chunks that were paged out of `voxels` to save memory. The `SdfVoxelMapReader` faults them back in, but readers of
+    /// `voxels` will not see them until they are flushed into memory at the end of the frame.
+    pub swap: ChunkSwap,
+    /// Chunks that are stored as a single value because all of their voxels are the same. They are never in `voxels`, but the
+    /// `SdfVoxelMapReader` expands them.
+    pub uniform: UniformChunks,
+    /// The extent of the world, if it's finite. The `VoxelEditor` clips every edit to these bounds, and exports cover exactly
+    /// this extent.
+    pub bounds: Option<Extent3i>,
+    snapshot: Option<MapSnapshot>,
 }
 
 impl SdfVoxelMap {
+    pub fn new(voxels: CompressibleSdfChunkMap, palette: SdfVoxelPalette) -> Self {
+        Self {
+            voxels,
+            palette,
+            swap: Default::default(),
+            uniform: Default::default(),
+            bounds: None,
+            snapshot: None,
+        }
+    }
+
+    /// The part of `extent` inside of the world bounds.
+    pub fn clip_to_bounds(&self, extent: Extent3i) -> Extent3i {
+        self.bounds
+            .map_or(extent, |bounds| extent.intersection(&bounds))
+    }
+
+    /// Makes every voxel of `chunk` outside of the world bounds ambient. Returns false if the whole chunk is outside.
+    pub fn crop_to_bounds(&self, chunk: &mut SdfArray) -> bool {
+        let bounds = if let Some(b) = self.bounds {
+            b
+        } else {
+            return true;
+        };
+        let chunk_extent = *chunk.extent();
+        let inside = chunk_extent.intersection(&bounds);
+        if inside.is_empty() {
+            return false;
+        }
+        if inside == chunk_extent {
+            return true;
+        }
+        chunk.for_each_mut(
+            &chunk_extent,
+            |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+                if !bounds.contains(p) {
+                    *v_type = EMPTY_SDF_VOXEL.0;
+                    *v_dist = EMPTY_SDF_VOXEL.1;
+                }
+            },
+        );
+
+        true
+    }
+
     /// Returns a closure that transforms voxels into their type's corresponding info. This is intended to be used with a
-    /// `TransformMap`.
+    /// `TransformMap`. Types that aren't in the palette are empty.
     #[inline]
     pub fn voxel_info_transform<'a>(&'a self) -> impl Fn((VoxelType, Sd8)) -> &'a VoxelTypeInfo {
         move |(v_type, _dist): (VoxelType, Sd8)| self.palette.get_voxel_type_info(v_type)
@@ -21,21 +83,292 @@ impl SdfVoxelMap {
     pub fn reader<'a>(
         &'a self,
         handle: &'a ThreadLocalResourceHandle<SdfChunkCache>,
-    ) -> CompressibleSdfChunkMapReader {
+    ) -> SdfVoxelMapReader<'a> {
         let local_cache = handle.get_or_create_with(SdfChunkCache::new);
 
-        self.voxels.reader(local_cache)
+        SdfVoxelMapReader {
+            voxels: self.voxels.reader(local_cache),
+            uniform: &self.uniform,
+            swap: &self.swap,
+        }
+    }
+
+    /// The keys of all chunks, including uniform chunks and those in the swap file.
+    pub fn chunk_keys(&self) -> Vec<ChunkKey3> {
+        self.voxels
+            .storage()
+            .chunk_keys()
+            .chain(self.uniform.chunk_keys())
+            .chain(self.swap.chunk_keys())
+            .cloned()
+            .collect()
+    }
+
+    /// The value of every voxel in the chunk at `chunk_key`, if it's known without reading the chunk. That's the case for uniform
+    /// chunks and for missing chunks, which are ambient.
+    pub fn known_uniform_value(&self, chunk_key: ChunkKey3) -> Option<(VoxelType, Sd8)> {
+        if let Some(value) = self.uniform.get(chunk_key) {
+            return Some(value);
+        }
+        if self.swap.contains(chunk_key)
+            || self
+                .voxels
+                .storage()
+                .copy_without_caching(chunk_key)
+                .is_some()
+        {
+            return None;
+        }
+
+        Some(EMPTY_SDF_VOXEL)
+    }
+
+    /// Copies the chunk at `chunk_key` from whichever tier it's stored in, without caching it. Uniform chunks are expanded.
+    pub fn copy_chunk_without_caching(&self, chunk_key: ChunkKey3) -> Option<SdfArray> {
+        self.voxels
+            .storage()
+            .copy_without_caching(chunk_key)
+            .map(|c| c.into_decompressed())
+            .or_else(|| {
+                let extent = self
+                    .voxels
+                    .indexer
+                    .extent_for_chunk_with_min(chunk_key.minimum);
+
+                self.uniform
+                    .read(chunk_key, extent)
+                    .or_else(|| report_swap_error(chunk_key, self.swap.read(chunk_key, extent)))
+            })
+    }
+
+    /// Copies all voxels in `extent` from whichever tiers they're stored in, without caching any chunks. Voxels in missing
+    /// chunks are ambient.
+    pub fn copy_extent_without_caching(&self, extent: Extent3i) -> SdfArray {
+        let mut dst = ambient_sdf_array(extent);
+        let indexer = self.voxels.indexer;
+        for chunk_min in indexer.chunk_mins_for_extent(&extent) {
+            if let Some(chunk) = self.copy_chunk_without_caching(ChunkKey::new(0, chunk_min)) {
+                let chunk_extent = indexer.extent_for_chunk_with_min(chunk_min);
+                copy_extent(&chunk_extent.intersection(&extent), &chunk, &mut dst);
+            }
+        }
+
+        dst
+    }
+
+    /// Writes `chunk` into the uniform tier if all of its voxels are the same, otherwise into `voxels`. Either way, the copies
+    /// in the other tiers are removed.
+    pub fn write_chunk(&mut self, chunk_key: ChunkKey3, chunk: SdfArray) {
+        self.preserve_for_snapshot(chunk_key);
+        // Any swapped copy of this chunk is now stale.
+        self.swap.remove(chunk_key);
+        if let Some(value) = uniform_chunk_value(&chunk) {
+            self.voxels.storage_mut().remove(chunk_key);
+            self.uniform.insert(chunk_key, value);
+        } else {
+            self.uniform.remove(chunk_key);
+            self.voxels.write_chunk(chunk_key, chunk);
+        }
+    }
+
+    /// Removes the chunk at `chunk_key` from every tier.
+    pub fn remove_chunk(&mut self, chunk_key: ChunkKey3) {
+        self.preserve_for_snapshot(chunk_key);
+        self.voxels.storage_mut().remove(chunk_key);
+        self.uniform.remove(chunk_key);
+        self.swap.remove(chunk_key);
+    }
+
+    /// Takes a snapshot of the current chunk set, unless one is already in progress. This only copies the chunk keys; the
+    /// chunks are copied as they're taken with `take_snapshot_chunks`, or just before they're overwritten, whichever comes
+    /// first.
+    pub fn begin_snapshot(&mut self) -> Option<MapSnapshotHeader> {
+        if self.snapshot.is_some() {
+            return None;
+        }
+        let chunk_keys = self.chunk_keys();
+        let header = MapSnapshotHeader {
+            chunk_shape: self.voxels.chunk_shape(),
+            palette: self.palette.clone(),
+            bounds: self.bounds,
+            num_chunks: chunk_keys.len(),
+        };
+        self.snapshot = Some(MapSnapshot::new(chunk_keys));
+
+        Some(header)
+    }
+
+    /// Copies up to `max_chunks` of the snapshot's chunks, in the same order as a map file, as they were when the snapshot was
+    /// taken. The snapshot ends once all of its chunks are taken.
+    pub fn take_snapshot_chunks(&mut self, max_chunks: usize) -> Vec<(ChunkKey3, SdfArray)> {
+        let snapshot = if let Some(s) = self.snapshot.as_mut() {
+            s
+        } else {
+            return Vec::new();
+        };
+        let taken = snapshot.take_next(max_chunks);
+        if snapshot.is_finished() {
+            self.snapshot = None;
+        }
+
+        taken
+            .into_iter()
+            .map(|(chunk_key, preserved)| {
+                let chunk = preserved.unwrap_or_else(|| {
+                    self.copy_chunk_without_caching(chunk_key)
+                        .expect("Unedited snapshot chunks are still in the map")
+                });
+
+                (chunk_key, chunk)
+            })
+            .collect()
+    }
+
+    fn preserve_for_snapshot(&mut self, chunk_key: ChunkKey3) {
+        let needs_preserving = self
+            .snapshot
+            .as_ref()
+            .map_or(false, |s| s.needs_preserving(chunk_key));
+        if !needs_preserving {
+            return;
+        }
+        if let Some(chunk) = self.copy_chunk_without_caching(chunk_key) {
+            self.snapshot.as_mut().unwrap().preserve(chunk_key, chunk);
+        }
+    }
+
+    /// Moves any swapped chunks at `chunk_mins` back into memory, so they are visible to readers.
+    pub fn fault_in_chunks(&mut self, chunk_mins: impl Iterator<Item = Point3i>) {
+        for chunk_min in chunk_mins {
+            let chunk_key = ChunkKey::new(0, chunk_min);
+            let extent = self.voxels.indexer.extent_for_chunk_with_min(chunk_min);
+            if let Some(chunk) = report_swap_error(chunk_key, self.swap.take(chunk_key, extent)) {
+                self.voxels.write_chunk(chunk_key, chunk);
+            }
+        }
+    }
+
+    /// Moves the chunks that the `SdfVoxelMapReader` faulted in from the swap file into memory.
+    pub fn flush_faulted_chunks(&mut self) {
+        for (chunk_key, chunk) in self.swap.take_faulted().into_iter() {
+            self.voxels.write_chunk(chunk_key, chunk);
+        }
+    }
+
+    pub fn tier_counts(&self) -> ChunkTierCounts {
+        let storage = self.voxels.storage();
+
+        ChunkTierCounts {
+            cached: storage.len_cached(),
+            compressed: storage.len_compressed(),
+            uniform: self.uniform.len(),
+            swapped: self.swap.len(),
+        }
+    }
+}
+
+/// Reads the chunks of an `SdfVoxelMap` from every tier. Uniform chunks are expanded into arrays as they're read, and swapped
+/// chunks are faulted in, so they're only read from the swap file once. Chunks that can't be read from the swap file are reported
+/// and read as missing.
+pub struct SdfVoxelMapReader<'a> {
+    pub voxels: CompressibleSdfChunkMapReader<'a>,
+    pub uniform: &'a UniformChunks,
+    pub swap: &'a ChunkSwap,
+}
+
+impl<'a> SdfVoxelMapReader<'a> {
+    pub fn get_chunk(&self, chunk_key: ChunkKey3) -> Option<Cow<SdfArray>> {
+        if let Some(chunk) = self.voxels.get_chunk(chunk_key) {
+            return Some(Cow::Borrowed(chunk));
+        }
+        let extent = self
+            .voxels
+            .indexer
+            .extent_for_chunk_with_min(chunk_key.minimum);
+
+        self.uniform
+            .read(chunk_key, extent)
+            .or_else(|| report_swap_error(chunk_key, self.swap.fault_in(chunk_key, extent)))
+            .map(Cow::Owned)
+    }
+
+    /// Copies all voxels in `extent` into `dst`. Voxels in missing chunks are ambient.
+    pub fn copy_extent_into(&self, extent: &Extent3i, dst: &mut SdfArray) {
+        copy_extent(extent, &self.voxels.lod_view(0), dst);
+
+        let indexer = self.voxels.indexer;
+        for chunk_min in indexer.chunk_mins_for_extent(extent) {
+            let chunk_key = ChunkKey::new(0, chunk_min);
+            let chunk_extent = indexer.extent_for_chunk_with_min(chunk_min);
+            let overlap = chunk_extent.intersection(extent);
+            if let Some(value) = self.uniform.get(chunk_key) {
+                dst.for_each_mut(
+                    &overlap,
+                    |_p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+                        *v_type = value.0;
+                        *v_dist = value.1;
+                    },
+                );
+            } else if let Some(chunk) =
+                report_swap_error(chunk_key, self.swap.fault_in(chunk_key, chunk_extent))
+            {
+                copy_extent(&overlap, &chunk, dst);
+            }
+        }
     }
 }
 
-#[derive(Clone, Default)]
+fn report_swap_error(
+    chunk_key: ChunkKey3,
+    result: Result<Option<SdfArray>, MapFileError>,
+) -> Option<SdfArray> {
+    result.unwrap_or_else(|e| {
+        println!("Failed to read chunk {:?} from swap: {}", chunk_key, e);
+        None
+    })
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct SdfVoxelPalette {
     pub infos: Vec<VoxelTypeInfo>,
 }
 
+/// The info for voxel types that aren't in the palette, e.g. when a map was saved with a different palette. They have no material,
+/// so they're treated as empty.
+static UNDEFINED_VOXEL_TYPE_INFO: VoxelTypeInfo = VoxelTypeInfo {
+    is_empty: true,
+    material: VoxelMaterial::NULL,
+};
+
 impl SdfVoxelPalette {
     pub fn get_voxel_type_info(&self, voxel: VoxelType) -> &VoxelTypeInfo {
-        &self.infos[voxel.0 as usize]
+        self.infos
+            .get(voxel.0 as usize)
+            .unwrap_or(&UNDEFINED_VOXEL_TYPE_INFO)
+    }
+
+    /// The first voxel type in `chunks` that isn't in the palette.
+    pub fn find_undefined_voxel_type<'a>(
+        &self,
+        chunks: impl IntoIterator<Item = &'a SdfArray>,
+    ) -> Option<VoxelType> {
+        let num_types = self.infos.len();
+        let mut undefined = None;
+        for chunk in chunks.into_iter() {
+            chunk.for_each(
+                chunk.extent(),
+                |_p: Point3i, (v_type, _dist): (VoxelType, Sd8)| {
+                    if undefined.is_none() && v_type.0 as usize >= num_types {
+                        undefined = Some(v_type);
+                    }
+                },
+            );
+            if undefined.is_some() {
+                break;
+            }
+        }
+
+        undefined
     }
 }
 
@@ -43,9 +376,12 @@ pub fn sdf_chunk_map_builder(chunk_shape: Point3i) -> SdfChunkMapBuilder {
     SdfChunkMapBuilder::new(chunk_shape, (EMPTY_VOXEL_TYPE, EMPTY_SIGNED_DISTANCE))
 }
 
-pub fn empty_compressible_sdf_chunk_map(chunk_shape: Point3i) -> CompressibleSdfChunkMap {
+pub fn empty_compressible_sdf_chunk_map(
+    chunk_shape: Point3i,
+    compression: Lz4,
+) -> CompressibleSdfChunkMap {
     sdf_chunk_map_builder(chunk_shape).build_with_write_storage(
-        FastCompressibleChunkStorageNx2::with_bytes_compression(Lz4 { level: 10 }),
+        FastCompressibleChunkStorageNx2::with_bytes_compression(compression),
     )
 }
 
@@ -57,6 +393,16 @@ pub fn ambient_sdf_array(extent: Extent3i) -> SdfArray {
     SdfArray::fill(extent, (VoxelType(0), Sd8::ONE))
 }
 
+/// True if every voxel in `array` is `EMPTY_SDF_VOXEL`.
+pub fn sdf_array_is_ambient(array: &SdfArray) -> bool {
+    let mut ambient = true;
+    array.for_each(array.extent(), |_p: Point3i, voxel: (VoxelType, Sd8)| {
+        ambient &= voxel == EMPTY_SDF_VOXEL;
+    });
+
+    ambient
+}
+
 pub type SdfArray = Array3x2<VoxelType, Sd8>;
 
 pub type SdfChunkMapBuilder = ChunkMapBuilder3x2<VoxelType, Sd8>;
@@ -67,4 +413,16 @@ pub type SdfChunkCache = LocalChunkCache3<SdfArray>;
 pub type CompressibleSdfChunkMap = CompressibleChunkMap3x2<Lz4, VoxelType, Sd8>;
 pub type CompressibleSdfChunkMapReader<'a> = CompressibleChunkMapReader3x2<'a, Lz4, VoxelType, Sd8>;
 
-pub const CHUNK_SHAPE: Point3i = PointN([16; 3]);
+/// The chunk shape of new maps, unless `Config::chunk_shape` says otherwise. Existing maps keep the shape they were saved with.
+pub const DEFAULT_CHUNK_SHAPE: Point3i = PointN([16; 3]);
+
+/// The compression of chunks in memory and in map files, unless `ChunkCacheConfig::compression_level` says otherwise.
+pub const DEFAULT_CHUNK_COMPRESSION: Lz4 = Lz4 { level: 10 };
+
+/// Chunk indexing relies on bit masks, so every dimension of a chunk must be a power of two.
+pub fn is_valid_chunk_shape(chunk_shape: Point3i) -> bool {
+    chunk_shape
+        .0
+        .iter()
+        .all(|&s| s > 0 && (s as u32).is_power_of_two())
+}
@@ -0,0 +1,19 @@
+mod background_save;
+mod format;
+mod journal;
+mod plugin;
+mod stats;
+
+pub use background_save::{BackgroundSave, MapSaveEvents, SAVE_CHUNKS_PER_FRAME};
+pub use format::{
+    open_map, open_map_reader, read_map, save_map, write_map, write_map_with_compression, MapFile,
+    MapFileError, MapFileReader, MAP_FILE_MAGIC, MAP_FILE_VERSION,
+};
+pub use journal::{
+    autosave_path, discard_autosave, has_recovery_data, journal_path, read_journal, recover_map,
+    EditJournal, JOURNAL_MAGIC, JOURNAL_VERSION,
+};
+pub use plugin::{MapFileEvents, MapFilePlugin, RecoverMap};
+pub use stats::MapStats;
+
+pub(crate) use format::{decode_chunk, encode_chunk};
@@ -0,0 +1,271 @@
+use super::{
+    format::{write_map_chunk, write_map_header},
+    journal::append_extension,
+    MapFileError,
+};
+
+use crate::{map_io::MapSnapshotHeader, SdfArray};
+
+use building_blocks::storage::{ChunkKey3, Lz4};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{Receiver, Sender, TryRecvError},
+};
+
+/// How many snapshot chunks are copied out of the `SdfVoxelMap` and handed to the save thread each frame.
+pub const SAVE_CHUNKS_PER_FRAME: usize = 256;
+
+/// Sent while a map is saved in the background.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapSaveEvents {
+    Progress {
+        chunks_written: usize,
+        num_chunks: usize,
+    },
+    Finished,
+    Failed(String),
+}
+
+/// A map file being written by its own thread, from a snapshot of the `SdfVoxelMap`. Chunks are fed to the thread a batch at a
+/// time with `send_chunks`, and the thread reports back through `poll`. The thread spends most of the save blocked on the next
+/// batch, so it doesn't run on the `IoTaskPool`, where it would hold up asset loading.
+///
+/// The file is written next to its destination and only renamed over it once it's complete, so a failed save never clobbers
+/// the previous version.
+pub struct BackgroundSave {
+    path: PathBuf,
+    num_chunks: usize,
+    chunk_sender: Option<Sender<Vec<(ChunkKey3, SdfArray)>>>,
+    progress_receiver: Receiver<SaveProgress>,
+    /// The number of journal records when the snapshot was taken. Edits after that aren't in the saved file.
+    pub journal_records_at_snapshot: Option<usize>,
+    /// Whether this is writing the autosave instead of the map file.
+    pub is_autosave: bool,
+}
+
+enum SaveProgress {
+    Wrote(usize),
+    Finished(Result<(), MapFileError>),
+}
+
+impl BackgroundSave {
+    /// Starts the thread that writes the file. Chunks are written with the `compression` level.
+    pub fn start(
+        path: impl AsRef<Path>,
+        header: MapSnapshotHeader,
+        compression: Lz4,
+        journal_records_at_snapshot: Option<usize>,
+    ) -> Self {
+        let path = path.as_ref().to_owned();
+        let num_chunks = header.num_chunks;
+        let (chunk_sender, chunk_receiver) = std::sync::mpsc::channel();
+        let (progress_sender, progress_receiver) = std::sync::mpsc::channel();
+
+        let thread_path = path.clone();
+        // If the thread can't be spawned, the progress sender is dropped, and `poll` reports the failure.
+        let _ = std::thread::Builder::new()
+            .name("map save".to_string())
+            .spawn(move || {
+                let result = write_snapshot(
+                    &thread_path,
+                    &header,
+                    compression,
+                    &chunk_receiver,
+                    &progress_sender,
+                );
+                // The receiver is gone if the save was dropped, and then nobody cares about the result.
+                let _ = progress_sender.send(SaveProgress::Finished(result));
+            });
+
+        Self {
+            path,
+            num_chunks,
+            chunk_sender: Some(chunk_sender),
+            progress_receiver,
+            journal_records_at_snapshot,
+            is_autosave: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Hands a batch of snapshot chunks to the thread. An empty batch means there are no more chunks.
+    pub fn send_chunks(&mut self, chunks: Vec<(ChunkKey3, SdfArray)>) {
+        if chunks.is_empty() {
+            self.chunk_sender = None;
+            return;
+        }
+        if let Some(sender) = &self.chunk_sender {
+            // If the thread stopped early, it will report why.
+            let _ = sender.send(chunks);
+        }
+    }
+
+    pub fn is_sending(&self) -> bool {
+        self.chunk_sender.is_some()
+    }
+
+    /// Returns the events for everything the thread reported since the last poll. The save is over after `Finished` or `Failed`.
+    pub fn poll(&mut self) -> Vec<MapSaveEvents> {
+        let num_chunks = self.num_chunks;
+        let mut events = Vec::new();
+        loop {
+            match self.progress_receiver.try_recv() {
+                Ok(SaveProgress::Wrote(chunks_written)) => events.push(MapSaveEvents::Progress {
+                    chunks_written,
+                    num_chunks,
+                }),
+                Ok(SaveProgress::Finished(Ok(()))) => events.push(MapSaveEvents::Finished),
+                Ok(SaveProgress::Finished(Err(e))) => {
+                    events.push(MapSaveEvents::Failed(e.to_string()))
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !events.iter().any(is_final_event) {
+                        events.push(MapSaveEvents::Failed(
+                            "The save thread stopped unexpectedly".to_string(),
+                        ));
+                    }
+                    break;
+                }
+            }
+        }
+
+        events
+    }
+}
+
+pub fn is_final_event(event: &MapSaveEvents) -> bool {
+    matches!(event, MapSaveEvents::Finished | MapSaveEvents::Failed(_))
+}
+
+fn write_snapshot(
+    path: &Path,
+    header: &MapSnapshotHeader,
+    compression: Lz4,
+    chunk_receiver: &Receiver<Vec<(ChunkKey3, SdfArray)>>,
+    progress_sender: &Sender<SaveProgress>,
+) -> Result<(), MapFileError> {
+    let saving_path = append_extension(path, "saving");
+    let mut writer = BufWriter::new(File::create(&saving_path)?);
+    write_map_header(&mut writer, header)?;
+
+    let mut chunks_written = 0;
+    let mut voxel_bytes = Vec::new();
+    let mut compressed_bytes = Vec::new();
+    // Ends when the sender is dropped.
+    for chunks in chunk_receiver.iter() {
+        for (chunk_key, chunk) in chunks.iter() {
+            write_map_chunk(
+                &mut writer,
+                *chunk_key,
+                chunk,
+                compression,
+                &mut voxel_bytes,
+                &mut compressed_bytes,
+            )?;
+        }
+        chunks_written += chunks.len();
+        let _ = progress_sender.send(SaveProgress::Wrote(chunks_written));
+    }
+    if chunks_written != header.num_chunks {
+        return Err(MapFileError::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "Only {} of {} chunks were saved",
+                chunks_written, header.num_chunks
+            ),
+        )));
+    }
+
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    drop(writer);
+    std::fs::rename(&saving_path, path)?;
+    sync_parent_dir(path)?;
+
+    Ok(())
+}
+
+/// Makes a rename into the directory of `path` durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    File::open(dir)?.sync_all()
+}
+
+/// Directories can't be opened for syncing on other platforms.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        ambient_sdf_array, empty_compressible_sdf_chunk_map, open_map, SdfVoxelMap, VoxelType,
+        DEFAULT_CHUNK_COMPRESSION, DEFAULT_CHUNK_SHAPE,
+    };
+
+    use building_blocks::prelude::*;
+
+    #[test]
+    fn background_save_writes_the_snapshot() {
+        let mut map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION),
+            Default::default(),
+        );
+        for i in 0..5 {
+            let chunk_min = PointN([16 * i, 0, 0]);
+            let mut chunk =
+                ambient_sdf_array(Extent3i::from_min_and_shape(chunk_min, DEFAULT_CHUNK_SHAPE));
+            let solid_extent = Extent3i::from_min_and_shape(chunk_min, PointN([1; 3]));
+            chunk.for_each_mut(
+                &solid_extent,
+                |_p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+                    *v_type = VoxelType(1);
+                    *v_dist = Sd8::NEG_ONE;
+                },
+            );
+            map.write_chunk(ChunkKey::new(0, chunk_min), chunk);
+        }
+
+        let path = std::env::temp_dir().join("bbe_background_save_writes_the_snapshot.bbmap");
+        let header = map.begin_snapshot().unwrap();
+        let mut save = BackgroundSave::start(&path, header, DEFAULT_CHUNK_COMPRESSION, None);
+
+        let mut events = Vec::new();
+        while !events.iter().any(is_final_event) {
+            if save.is_sending() {
+                save.send_chunks(map.take_snapshot_chunks(2));
+            }
+            events.extend(save.poll());
+        }
+        assert_eq!(events.last(), Some(&MapSaveEvents::Finished));
+        assert!(events.contains(&MapSaveEvents::Progress {
+            chunks_written: 5,
+            num_chunks: 5
+        }));
+
+        let map_file = open_map(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(map_file.chunks.len(), 5);
+    }
+}
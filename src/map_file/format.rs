@@ -0,0 +1,727 @@
+use crate::{
+    ambient_sdf_array, empty_compressible_sdf_chunk_map, empty_sdf_chunk_hash_map,
+    is_valid_chunk_shape, map_io::MapSnapshotHeader, sdf_array_is_ambient, SdfArray, SdfVoxelMap,
+    SdfVoxelPalette, VoxelType, DEFAULT_CHUNK_COMPRESSION, EMPTY_SDF_VOXEL,
+};
+
+use building_blocks::{
+    prelude::*,
+    storage::{BytesCompression, ChunkKey3, Lz4},
+};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// Every map file starts with these bytes.
+pub const MAP_FILE_MAGIC: [u8; 8] = *b"BBEDMAP\0";
+
+/// Bump this whenever the layout of the file changes.
+pub const MAP_FILE_VERSION: u32 = 2;
+
+/// Version 1 files have no world bounds, but are otherwise the same.
+const MAP_FILE_VERSION_WITHOUT_BOUNDS: u32 = 1;
+
+/// The contents of a map file, decoded but not yet inserted into an `SdfVoxelMap`.
+///
+/// The layout on disk (all integers little-endian) is:
+///
+/// - `MAP_FILE_MAGIC`
+/// - `u32` version
+/// - `[i32; 3]` chunk shape
+/// - `u32` length of the palette, followed by the palette as RON text
+/// - `u8` 1 if the world is bounded, followed by the `[i32; 3]` minimum and `[i32; 3]` shape of the bounds, or 0 otherwise
+/// - `u64` number of chunks, followed by each chunk:
+///   - `u8` LOD and `[i32; 3]` chunk minimum
+///   - `u32` length of the chunk bytes, followed by the LZ4-compressed `(VoxelType, Sd8)` pairs in extent order
+pub struct MapFile {
+    pub chunk_shape: Point3i,
+    pub palette: SdfVoxelPalette,
+    pub bounds: Option<Extent3i>,
+    pub chunks: Vec<(ChunkKey3, SdfArray)>,
+    /// The total size of the compressed chunks that were read from the file.
+    pub compressed_bytes: usize,
+}
+
+impl MapFile {
+    /// Writes all of the chunks directly into a new `SdfVoxelMap` that compresses its chunks with `compression`. This bypasses
+    /// the `EditBuffer`, so no chunks will be marked dirty.
+    pub fn into_voxel_map(self, compression: Lz4) -> SdfVoxelMap {
+        let MapFile {
+            chunk_shape,
+            palette,
+            bounds,
+            chunks,
+            ..
+        } = self;
+
+        let mut map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(chunk_shape, compression),
+            palette,
+        );
+        map.bounds = bounds;
+        for (chunk_key, chunk) in chunks.into_iter() {
+            map.write_chunk(chunk_key, chunk);
+        }
+
+        map
+    }
+
+    /// Splits `voxels` into chunks. Chunks that would only contain ambient voxels are left out.
+    pub fn from_sdf_array(
+        chunk_shape: Point3i,
+        palette: SdfVoxelPalette,
+        voxels: &SdfArray,
+    ) -> Self {
+        let indexer = empty_sdf_chunk_hash_map(chunk_shape).indexer;
+        let mut chunks = Vec::new();
+        for chunk_min in indexer.chunk_mins_for_extent(voxels.extent()) {
+            let chunk_extent = indexer.extent_for_chunk_with_min(chunk_min);
+            let mut chunk = ambient_sdf_array(chunk_extent);
+            copy_extent(
+                &chunk_extent.intersection(voxels.extent()),
+                voxels,
+                &mut chunk,
+            );
+            if !sdf_array_is_ambient(&chunk) {
+                chunks.push((ChunkKey::new(0, chunk_min), chunk));
+            }
+        }
+
+        Self {
+            chunk_shape,
+            palette,
+            bounds: None,
+            chunks,
+            compressed_bytes: 0,
+        }
+    }
+
+    /// Removes every voxel outside of `extent`. Chunks that end up only containing ambient voxels are removed. Any world bounds
+    /// shrink to fit inside of `extent`.
+    pub fn crop(&mut self, extent: &Extent3i) {
+        self.bounds = self.bounds.map(|b| b.intersection(extent));
+        self.chunks
+            .retain(|(_, chunk)| !chunk.extent().intersection(extent).is_empty());
+        for (_, chunk) in self.chunks.iter_mut() {
+            let chunk_extent = *chunk.extent();
+            chunk.for_each_mut(
+                &chunk_extent,
+                |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+                    if !extent.contains(p) {
+                        *v_type = EMPTY_SDF_VOXEL.0;
+                        *v_dist = EMPTY_SDF_VOXEL.1;
+                    }
+                },
+            );
+        }
+        self.chunks
+            .retain(|(_, chunk)| !sdf_array_is_ambient(chunk));
+    }
+}
+
+#[derive(Debug)]
+pub enum MapFileError {
+    Io(io::Error),
+    Palette(ron::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    BadChunkShape(Point3i),
+    BadBounds(Point3i),
+    BadChunkLength {
+        chunk_key: ChunkKey3,
+        num_bytes: usize,
+    },
+    NoRecoveryData,
+}
+
+impl std::fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapFileError::Io(e) => write!(f, "I/O error: {}", e),
+            MapFileError::Palette(e) => write!(f, "Failed to (de)serialize palette: {}", e),
+            MapFileError::BadMagic => write!(f, "Not a map file"),
+            MapFileError::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported map file version {} (expected {})",
+                v, MAP_FILE_VERSION
+            ),
+            MapFileError::BadChunkShape(s) => write!(f, "Invalid chunk shape {:?}", s),
+            MapFileError::BadBounds(s) => write!(f, "Invalid world bounds shape {:?}", s),
+            MapFileError::BadChunkLength {
+                chunk_key,
+                num_bytes,
+            } => write!(
+                f,
+                "Chunk {:?} decompressed to {} bytes, which doesn't match the chunk shape",
+                chunk_key, num_bytes
+            ),
+            MapFileError::NoRecoveryData => {
+                write!(f, "There is no map, autosave or edit journal to recover")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapFileError {}
+
+impl From<io::Error> for MapFileError {
+    fn from(e: io::Error) -> Self {
+        MapFileError::Io(e)
+    }
+}
+
+impl From<ron::Error> for MapFileError {
+    fn from(e: ron::Error) -> Self {
+        MapFileError::Palette(e)
+    }
+}
+
+/// Writes the entire `map` to a new file at `path`. Chunks that only live in thread-local caches are not visible here, but
+/// they are always copies of chunks in the global storage, so nothing is lost.
+pub fn save_map(path: impl AsRef<Path>, map: &SdfVoxelMap) -> Result<(), MapFileError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_map(&mut writer, map)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn open_map(path: impl AsRef<Path>) -> Result<MapFile, MapFileError> {
+    read_map(BufReader::new(File::open(path)?))
+}
+
+pub fn write_map(writer: impl Write, map: &SdfVoxelMap) -> Result<(), MapFileError> {
+    write_map_with_compression(writer, map, DEFAULT_CHUNK_COMPRESSION)
+}
+
+/// Like `write_map`, but with a different LZ4 level. Readers don't need to know the level.
+pub fn write_map_with_compression(
+    mut writer: impl Write,
+    map: &SdfVoxelMap,
+    compression: Lz4,
+) -> Result<(), MapFileError> {
+    // Sort the keys so the same map always produces the same file.
+    let mut chunk_keys = map.chunk_keys();
+    chunk_keys.sort_by_key(|k| (k.lod, k.minimum.0));
+
+    write_map_header(
+        &mut writer,
+        &MapSnapshotHeader {
+            chunk_shape: map.voxels.chunk_shape(),
+            palette: map.palette.clone(),
+            bounds: map.bounds,
+            num_chunks: chunk_keys.len(),
+        },
+    )?;
+    let mut voxel_bytes = Vec::new();
+    let mut compressed_bytes = Vec::new();
+    for chunk_key in chunk_keys.into_iter() {
+        let chunk = map.copy_chunk_without_caching(chunk_key).unwrap();
+        write_map_chunk(
+            &mut writer,
+            chunk_key,
+            &chunk,
+            compression,
+            &mut voxel_bytes,
+            &mut compressed_bytes,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes everything that comes before the chunks. Exactly `header.num_chunks` chunks must follow.
+pub(super) fn write_map_header(
+    writer: &mut impl Write,
+    header: &MapSnapshotHeader,
+) -> Result<(), MapFileError> {
+    writer.write_all(&MAP_FILE_MAGIC)?;
+    write_u32(writer, MAP_FILE_VERSION)?;
+    write_point(writer, header.chunk_shape)?;
+
+    let palette = ron::ser::to_string(&header.palette)?;
+    write_u32(writer, palette.len() as u32)?;
+    writer.write_all(palette.as_bytes())?;
+
+    if let Some(bounds) = header.bounds {
+        writer.write_all(&[1])?;
+        write_point(writer, bounds.minimum)?;
+        write_point(writer, bounds.shape)?;
+    } else {
+        writer.write_all(&[0])?;
+    }
+
+    writer.write_all(&(header.num_chunks as u64).to_le_bytes())?;
+
+    Ok(())
+}
+
+/// `voxel_bytes` and `compressed_bytes` are scratch space.
+pub(super) fn write_map_chunk(
+    writer: &mut impl Write,
+    chunk_key: ChunkKey3,
+    chunk: &SdfArray,
+    compression: Lz4,
+    voxel_bytes: &mut Vec<u8>,
+    compressed_bytes: &mut Vec<u8>,
+) -> io::Result<()> {
+    compressed_bytes.clear();
+    encode_chunk_with_compression(chunk, compression, voxel_bytes, compressed_bytes);
+
+    writer.write_all(&[chunk_key.lod])?;
+    write_point(writer, chunk_key.minimum)?;
+    write_u32(writer, compressed_bytes.len() as u32)?;
+    writer.write_all(compressed_bytes)
+}
+
+pub fn read_map(reader: impl Read) -> Result<MapFile, MapFileError> {
+    let mut reader = MapFileReader::new(reader)?;
+    let mut chunks = Vec::with_capacity(reader.num_chunks_left().min(1 << 16));
+    while let Some(chunk) = reader.read_chunk()? {
+        chunks.push(chunk);
+    }
+    let MapSnapshotHeader {
+        chunk_shape,
+        palette,
+        bounds,
+        ..
+    } = reader.header;
+
+    Ok(MapFile {
+        chunk_shape,
+        palette,
+        bounds,
+        chunks,
+        compressed_bytes: reader.compressed_bytes_read,
+    })
+}
+
+/// Opens a map file for reading one chunk at a time.
+pub fn open_map_reader(
+    path: impl AsRef<Path>,
+) -> Result<MapFileReader<BufReader<File>>, MapFileError> {
+    MapFileReader::new(BufReader::new(File::open(path)?))
+}
+
+/// Reads the chunks of a map file one at a time, so the whole map never needs to be decompressed at once.
+pub struct MapFileReader<R> {
+    reader: R,
+    /// Everything that comes before the chunks.
+    pub header: MapSnapshotHeader,
+    /// The total size of the compressed chunks that were read so far.
+    pub compressed_bytes_read: usize,
+    num_chunks_left: usize,
+    compressed_bytes: Vec<u8>,
+    voxel_bytes: Vec<u8>,
+}
+
+impl<R: Read> MapFileReader<R> {
+    /// Reads the header.
+    pub fn new(mut reader: R) -> Result<Self, MapFileError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAP_FILE_MAGIC {
+            return Err(MapFileError::BadMagic);
+        }
+        let version = read_u32(&mut reader)?;
+        if version != MAP_FILE_VERSION && version != MAP_FILE_VERSION_WITHOUT_BOUNDS {
+            return Err(MapFileError::UnsupportedVersion(version));
+        }
+        let chunk_shape = read_point(&mut reader)?;
+        if !is_valid_chunk_shape(chunk_shape) {
+            return Err(MapFileError::BadChunkShape(chunk_shape));
+        }
+
+        let palette_len = read_u32(&mut reader)? as usize;
+        let mut palette_bytes = vec![0; palette_len];
+        reader.read_exact(&mut palette_bytes)?;
+        let palette: SdfVoxelPalette = ron::de::from_bytes(&palette_bytes)?;
+
+        let mut bounds = None;
+        if version != MAP_FILE_VERSION_WITHOUT_BOUNDS {
+            let mut is_bounded = [0];
+            reader.read_exact(&mut is_bounded)?;
+            if is_bounded[0] != 0 {
+                let minimum = read_point(&mut reader)?;
+                let shape = read_point(&mut reader)?;
+                if shape.0.iter().any(|&s| s <= 0) {
+                    return Err(MapFileError::BadBounds(shape));
+                }
+                bounds = Some(Extent3i::from_min_and_shape(minimum, shape));
+            }
+        }
+
+        let mut num_chunks = [0; 8];
+        reader.read_exact(&mut num_chunks)?;
+        let num_chunks = u64::from_le_bytes(num_chunks) as usize;
+
+        Ok(Self {
+            reader,
+            header: MapSnapshotHeader {
+                chunk_shape,
+                palette,
+                bounds,
+                num_chunks,
+            },
+            compressed_bytes_read: 0,
+            num_chunks_left: num_chunks,
+            compressed_bytes: Vec::new(),
+            voxel_bytes: Vec::new(),
+        })
+    }
+
+    pub fn num_chunks_left(&self) -> usize {
+        self.num_chunks_left
+    }
+
+    /// Reads the next chunk, or returns `None` once every chunk has been read.
+    pub fn read_chunk(&mut self) -> Result<Option<(ChunkKey3, SdfArray)>, MapFileError> {
+        if self.num_chunks_left == 0 {
+            return Ok(None);
+        }
+
+        let mut lod = [0];
+        self.reader.read_exact(&mut lod)?;
+        let chunk_min = read_point(&mut self.reader)?;
+        let chunk_key = ChunkKey::new(lod[0], chunk_min);
+
+        // The length isn't trusted enough to allocate all of it up front.
+        let compressed_len = read_u32(&mut self.reader)? as usize;
+        self.compressed_bytes.clear();
+        (&mut self.reader)
+            .take(compressed_len as u64)
+            .read_to_end(&mut self.compressed_bytes)?;
+        if self.compressed_bytes.len() != compressed_len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        self.compressed_bytes_read += compressed_len;
+
+        let extent = Extent3i::from_min_and_shape(chunk_min, self.header.chunk_shape);
+        let chunk = decode_chunk(
+            chunk_key,
+            extent,
+            &self.compressed_bytes,
+            &mut self.voxel_bytes,
+        )?;
+        self.num_chunks_left -= 1;
+
+        Ok(Some((chunk_key, chunk)))
+    }
+}
+
+/// Appends the LZ4-compressed `(VoxelType, Sd8)` pairs of `chunk` to `compressed_bytes`. `voxel_bytes` is scratch space.
+pub(crate) fn encode_chunk(
+    chunk: &SdfArray,
+    voxel_bytes: &mut Vec<u8>,
+    compressed_bytes: &mut Vec<u8>,
+) {
+    encode_chunk_with_compression(
+        chunk,
+        DEFAULT_CHUNK_COMPRESSION,
+        voxel_bytes,
+        compressed_bytes,
+    );
+}
+
+pub(super) fn encode_chunk_with_compression(
+    chunk: &SdfArray,
+    compression: Lz4,
+    voxel_bytes: &mut Vec<u8>,
+    compressed_bytes: &mut Vec<u8>,
+) {
+    voxel_bytes.clear();
+    chunk.for_each(chunk.extent(), |_p, (v_type, v_dist): (VoxelType, Sd8)| {
+        voxel_bytes.push(v_type.0);
+        voxel_bytes.push(v_dist.0 as u8);
+    });
+    compression.compress_bytes(voxel_bytes.as_slice(), compressed_bytes);
+}
+
+/// The inverse of `encode_chunk`. `voxel_bytes` is scratch space.
+pub(crate) fn decode_chunk(
+    chunk_key: ChunkKey3,
+    extent: Extent3i,
+    compressed_bytes: &[u8],
+    voxel_bytes: &mut Vec<u8>,
+) -> Result<SdfArray, MapFileError> {
+    voxel_bytes.clear();
+    // `Lz4::decompress_bytes` panics on corrupt input, so the frame is decoded here instead. Reading one byte more than the chunk
+    // needs is enough to tell that it's too long, without decompressing all of it.
+    let expected_len = 2 * extent.shape.volume() as usize;
+    lz4::Decoder::new(compressed_bytes)?
+        .take(expected_len as u64 + 1)
+        .read_to_end(voxel_bytes)?;
+    if voxel_bytes.len() != expected_len {
+        return Err(MapFileError::BadChunkLength {
+            chunk_key,
+            num_bytes: voxel_bytes.len(),
+        });
+    }
+
+    let mut chunk = SdfArray::fill(extent, (VoxelType(0), Sd8::ONE));
+    let mut pairs = voxel_bytes.chunks_exact(2);
+    chunk.for_each_mut(
+        &extent,
+        |_p, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+            let pair = pairs.next().unwrap();
+            *v_type = VoxelType(pair[0]);
+            *v_dist = Sd8(pair[1] as i8);
+        },
+    );
+
+    Ok(chunk)
+}
+
+pub(super) fn write_u32(writer: &mut impl Write, x: u32) -> io::Result<()> {
+    writer.write_all(&x.to_le_bytes())
+}
+
+pub(super) fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(super) fn write_point(writer: &mut impl Write, p: Point3i) -> io::Result<()> {
+    for c in p.0.iter() {
+        writer.write_all(&c.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+pub(super) fn read_point(reader: &mut impl Read) -> io::Result<Point3i> {
+    let mut p = [0; 3];
+    for c in p.iter_mut() {
+        let mut bytes = [0; 4];
+        reader.read_exact(&mut bytes)?;
+        *c = i32::from_le_bytes(bytes);
+    }
+
+    Ok(PointN(p))
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::DEFAULT_CHUNK_SHAPE;
+
+    fn test_map() -> SdfVoxelMap {
+        let mut voxels =
+            empty_compressible_sdf_chunk_map(DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION);
+        for chunk_min in [
+            PointN([0, 0, 0]),
+            PointN([16, 0, 0]),
+            PointN([-16, 32, -48]),
+        ]
+        .iter()
+        .cloned()
+        {
+            let extent = Extent3i::from_min_and_shape(chunk_min, DEFAULT_CHUNK_SHAPE);
+            let mut chunk = SdfArray::fill(extent, (VoxelType(0), Sd8::ONE));
+            chunk.for_each_mut(&extent, |p: Point3i, (v_type, v_dist)| {
+                *v_type = VoxelType((p.x() + p.y() + p.z()).rem_euclid(5) as u8);
+                *v_dist = Sd8((p.x() * 7 - p.y() * 3 + p.z()).rem_euclid(256) as u8 as i8);
+            });
+            voxels.write_chunk(ChunkKey::new(0, chunk_min), chunk);
+        }
+
+        let mut map = SdfVoxelMap::new(voxels, SdfVoxelPalette::default());
+        map.bounds = Some(Extent3i::from_min_and_shape(
+            PointN([-16, 0, -48]),
+            PointN([48, 48, 64]),
+        ));
+
+        map
+    }
+
+    fn assert_maps_equal(map1: &SdfVoxelMap, map2: &SdfVoxelMap) {
+        assert_eq!(map1.voxels.chunk_shape(), map2.voxels.chunk_shape());
+        assert_eq!(map1.palette.infos, map2.palette.infos);
+        assert_eq!(map1.bounds, map2.bounds);
+
+        let mut keys1 = map1.chunk_keys();
+        let mut keys2 = map2.chunk_keys();
+        keys1.sort_by_key(|k| (k.lod, k.minimum.0));
+        keys2.sort_by_key(|k| (k.lod, k.minimum.0));
+        assert_eq!(keys1, keys2);
+
+        for chunk_key in keys1.into_iter() {
+            let chunk1 = map1.copy_chunk_without_caching(chunk_key).unwrap();
+            let chunk2 = map2.copy_chunk_without_caching(chunk_key).unwrap();
+            assert_eq!(chunk1.extent(), chunk2.extent());
+            chunk1.for_each(
+                chunk1.extent(),
+                |p: Point3i, (v_type, v_dist): (VoxelType, Sd8)| {
+                    let (other_type, other_dist) = chunk2.get(p);
+                    assert_eq!(v_type, other_type);
+                    assert_eq!(v_dist.0, other_dist.0);
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn write_then_read_round_trips_every_voxel() {
+        let map = test_map();
+
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &map).unwrap();
+        let loaded = read_map(bytes.as_slice())
+            .unwrap()
+            .into_voxel_map(DEFAULT_CHUNK_COMPRESSION);
+
+        assert_maps_equal(&map, &loaded);
+    }
+
+    #[test]
+    fn save_then_open_round_trips_every_voxel() {
+        let map = test_map();
+
+        let path = std::env::temp_dir().join("bbe_save_then_open_round_trips_every_voxel.bbmap");
+        save_map(&path, &map).unwrap();
+        let loaded = open_map(&path)
+            .unwrap()
+            .into_voxel_map(DEFAULT_CHUNK_COMPRESSION);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_maps_equal(&map, &loaded);
+    }
+
+    #[test]
+    fn any_compression_level_can_be_read() {
+        let map = test_map();
+
+        let mut bytes = Vec::new();
+        write_map_with_compression(&mut bytes, &map, Lz4 { level: 1 }).unwrap();
+        let loaded = read_map(bytes.as_slice())
+            .unwrap()
+            .into_voxel_map(DEFAULT_CHUNK_COMPRESSION);
+
+        assert_maps_equal(&map, &loaded);
+    }
+
+    #[test]
+    fn reader_reads_one_chunk_at_a_time() {
+        let map = test_map();
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &map).unwrap();
+
+        let mut reader = MapFileReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.header.chunk_shape, DEFAULT_CHUNK_SHAPE);
+        assert_eq!(reader.header.bounds, map.bounds);
+        assert_eq!(reader.header.num_chunks, 3);
+        for num_chunks_left in (0..3).rev() {
+            let (chunk_key, chunk) = reader.read_chunk().unwrap().unwrap();
+            assert_eq!(reader.num_chunks_left(), num_chunks_left);
+            let original = map.copy_chunk_without_caching(chunk_key).unwrap();
+            chunk.for_each(chunk.extent(), |p: Point3i, voxel: (VoxelType, Sd8)| {
+                assert_eq!(voxel, original.get(p));
+            });
+        }
+        assert!(reader.read_chunk().unwrap().is_none());
+        assert_eq!(
+            reader.compressed_bytes_read,
+            read_map(bytes.as_slice()).unwrap().compressed_bytes
+        );
+    }
+
+    #[test]
+    fn crop_clears_voxels_outside_of_the_extent() {
+        let map = test_map();
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &map).unwrap();
+        let mut map_file = read_map(bytes.as_slice()).unwrap();
+
+        let crop_extent = Extent3i::from_min_and_shape(PointN([8, 0, 0]), PointN([16, 1, 1]));
+        map_file.crop(&crop_extent);
+        assert_eq!(map_file.bounds, Some(crop_extent));
+
+        let mut keys: Vec<_> = map_file.chunks.iter().map(|(k, _)| k.minimum).collect();
+        keys.sort_by_key(|k| k.0);
+        assert_eq!(keys, vec![PointN([0, 0, 0]), PointN([16, 0, 0])]);
+        for (chunk_key, chunk) in map_file.chunks.iter() {
+            let original = map.copy_chunk_without_caching(*chunk_key).unwrap();
+            chunk.for_each(chunk.extent(), |p: Point3i, voxel: (VoxelType, Sd8)| {
+                if crop_extent.contains(p) {
+                    assert_eq!(voxel, original.get(p));
+                } else {
+                    assert_eq!(voxel, EMPTY_SDF_VOXEL);
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn rejects_chunk_shapes_that_arent_powers_of_two() {
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &test_map()).unwrap();
+        // The chunk shape follows the magic and version.
+        bytes[12..16].copy_from_slice(&24i32.to_le_bytes());
+
+        assert!(matches!(
+            read_map(bytes.as_slice()),
+            Err(MapFileError::BadChunkShape(PointN([24, 16, 16])))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let mut bytes = Vec::new();
+        write_map(&mut bytes, &test_map()).unwrap();
+        bytes.truncate(bytes.len() - 10);
+
+        assert!(matches!(
+            read_map(bytes.as_slice()),
+            Err(MapFileError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_chunks() {
+        let chunk_key = ChunkKey::new(0, PointN([0; 3]));
+        let extent = Extent3i::from_min_and_shape(chunk_key.minimum, DEFAULT_CHUNK_SHAPE);
+        assert!(matches!(
+            decode_chunk(chunk_key, extent, b"not lz4", &mut Vec::new()),
+            Err(MapFileError::Io(_))
+        ));
+
+        // A chunk from a map with a smaller chunk shape.
+        let mut compressed_bytes = Vec::new();
+        encode_chunk(
+            &ambient_sdf_array(Extent3i::from_min_and_shape(
+                chunk_key.minimum,
+                PointN([8; 3]),
+            )),
+            &mut Vec::new(),
+            &mut compressed_bytes,
+        );
+        assert!(matches!(
+            decode_chunk(chunk_key, extent, &compressed_bytes, &mut Vec::new()),
+            Err(MapFileError::BadChunkLength {
+                num_bytes: 1024,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let bytes = b"definitely not a map file".to_vec();
+        assert!(matches!(
+            read_map(bytes.as_slice()),
+            Err(MapFileError::BadMagic)
+        ));
+    }
+}
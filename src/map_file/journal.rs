@@ -0,0 +1,381 @@
+use super::{
+    decode_chunk,
+    format::{encode_chunk_with_compression, read_point, read_u32, write_point, write_u32},
+    open_map, MapFile, MapFileError,
+};
+
+use crate::{is_valid_chunk_shape, SdfArray, SdfVoxelPalette};
+
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, Lz4, SmallKeyHashMap},
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+/// Every journal file starts with these bytes.
+pub const JOURNAL_MAGIC: [u8; 8] = *b"BBEDJRNL";
+
+/// Bump this whenever the layout of the journal changes.
+pub const JOURNAL_VERSION: u32 = 1;
+
+const HEADER_LEN: u64 = 8 + 4 + 12;
+
+/// An append-only log of every chunk merged into the `SdfVoxelMap` since the last save or autosave. If the editor crashes, the
+/// latest state of the map can be recovered by replaying the journal over the last autosave (or the map file itself).
+///
+/// After the header (magic, `u32` version, `[i32; 3]` chunk shape), each record is a whole chunk in the same encoding as the map
+/// file. A crash may leave a truncated record at the end, which is ignored on recovery.
+pub struct EditJournal {
+    writer: BufWriter<File>,
+    chunk_shape: Point3i,
+    compression: Lz4,
+    num_records: usize,
+    skip_next_merge: bool,
+    voxel_bytes: Vec<u8>,
+    compressed_bytes: Vec<u8>,
+}
+
+impl EditJournal {
+    /// Creates an empty journal at `path`, replacing any existing file. Records are compressed with the `compression` level.
+    pub fn create(
+        path: impl AsRef<Path>,
+        chunk_shape: Point3i,
+        compression: Lz4,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut journal = Self {
+            writer: BufWriter::new(file),
+            chunk_shape,
+            compression,
+            num_records: 0,
+            skip_next_merge: false,
+            voxel_bytes: Vec::new(),
+            compressed_bytes: Vec::new(),
+        };
+        journal.write_header()?;
+
+        Ok(journal)
+    }
+
+    pub fn num_records(&self) -> usize {
+        self.num_records
+    }
+
+    /// Appends the latest version of a chunk. Call `flush` once all of a frame's chunks are appended, since the records aren't
+    /// durable until then.
+    pub fn append(&mut self, chunk_key: ChunkKey3, chunk: &SdfArray) -> io::Result<()> {
+        self.compressed_bytes.clear();
+        encode_chunk_with_compression(
+            chunk,
+            self.compression,
+            &mut self.voxel_bytes,
+            &mut self.compressed_bytes,
+        );
+
+        self.writer.write_all(&[chunk_key.lod])?;
+        write_point(&mut self.writer, chunk_key.minimum)?;
+        write_u32(&mut self.writer, self.compressed_bytes.len() as u32)?;
+        self.writer.write_all(&self.compressed_bytes)?;
+        self.num_records += 1;
+
+        Ok(())
+    }
+
+    /// Writes the buffered records and waits for them to reach the disk, so they survive a power loss or OS crash.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// Throws away all records, e.g. because the map was just saved.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let file = self.writer.get_mut();
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        self.num_records = 0;
+
+        self.write_header()
+    }
+
+    /// Don't record the chunks of the next merge. This is for edits that only reproduce what's already on disk, like loading
+    /// the map.
+    pub fn skip_next_merge(&mut self) {
+        self.skip_next_merge = true;
+    }
+
+    /// Returns true if the current merge should be skipped, and clears the flag.
+    pub fn take_skip_merge(&mut self) -> bool {
+        std::mem::replace(&mut self.skip_next_merge, false)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        self.writer.write_all(&JOURNAL_MAGIC)?;
+        write_u32(&mut self.writer, JOURNAL_VERSION)?;
+        write_point(&mut self.writer, self.chunk_shape)?;
+
+        self.flush()
+    }
+}
+
+pub fn journal_path(map_file: impl AsRef<Path>) -> PathBuf {
+    append_extension(map_file.as_ref(), "journal")
+}
+
+pub fn autosave_path(map_file: impl AsRef<Path>) -> PathBuf {
+    append_extension(map_file.as_ref(), "autosave")
+}
+
+pub(super) fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+
+    path.into()
+}
+
+/// Reads all of the complete records in the journal at `path`. Later records for the same chunk replace earlier ones.
+pub fn read_journal(
+    path: impl AsRef<Path>,
+) -> Result<(Point3i, Vec<(ChunkKey3, SdfArray)>), MapFileError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if magic != JOURNAL_MAGIC {
+        return Err(MapFileError::BadMagic);
+    }
+    let version = read_u32(&mut reader)?;
+    if version != JOURNAL_VERSION {
+        return Err(MapFileError::UnsupportedVersion(version));
+    }
+    let chunk_shape = read_point(&mut reader)?;
+    if !is_valid_chunk_shape(chunk_shape) {
+        return Err(MapFileError::BadChunkShape(chunk_shape));
+    }
+
+    let mut latest_chunks = SmallKeyHashMap::default();
+    let mut order = Vec::new();
+    let mut compressed_bytes = Vec::new();
+    let mut voxel_bytes = Vec::new();
+    loop {
+        let (chunk_key, extent) =
+            match read_record_header(&mut reader, chunk_shape, &mut compressed_bytes) {
+                Ok(Some(x)) => x,
+                // A clean end of the journal, or a record that was only partially written before a crash.
+                Ok(None) => break,
+                Err(e) => return Err(e.into()),
+            };
+        let chunk = decode_chunk(chunk_key, extent, &compressed_bytes, &mut voxel_bytes)?;
+        if latest_chunks.insert(chunk_key, chunk).is_none() {
+            order.push(chunk_key);
+        }
+    }
+
+    let chunks = order
+        .into_iter()
+        .map(|k| {
+            let chunk = latest_chunks.remove(&k).unwrap();
+            (k, chunk)
+        })
+        .collect();
+
+    Ok((chunk_shape, chunks))
+}
+
+fn read_record_header(
+    reader: &mut impl Read,
+    chunk_shape: Point3i,
+    compressed_bytes: &mut Vec<u8>,
+) -> io::Result<Option<(ChunkKey3, Extent3i)>> {
+    let result = (|| {
+        let mut lod = [0];
+        reader.read_exact(&mut lod)?;
+        let chunk_min = read_point(reader)?;
+        let len = read_u32(reader)? as usize;
+        compressed_bytes.resize(len, 0);
+        reader.read_exact(compressed_bytes)?;
+
+        Ok((
+            ChunkKey::new(lod[0], chunk_min),
+            Extent3i::from_min_and_shape(chunk_min, chunk_shape),
+        ))
+    })();
+
+    match result {
+        Ok(x) => Ok(Some(x)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns true if a previous session left edits behind that were never saved to `map_file`.
+pub fn has_recovery_data(map_file: impl AsRef<Path>) -> bool {
+    let journal_has_records =
+        std::fs::metadata(journal_path(&map_file)).map_or(false, |m| m.len() > HEADER_LEN);
+
+    journal_has_records || autosave_path(&map_file).exists()
+}
+
+/// Rebuilds the latest state of the map by replaying the journal over the last autosave, or over `map_file` if there is no
+/// autosave. A journal without a complete header has no records. Fails with `MapFileError::NoRecoveryData` if there's neither a
+/// map to replay over nor a journal to say what the chunk shape is.
+pub fn recover_map(map_file: impl AsRef<Path>) -> Result<MapFile, MapFileError> {
+    let autosave = autosave_path(&map_file);
+    let base = if autosave.exists() {
+        Some(open_map(&autosave)?)
+    } else if map_file.as_ref().exists() {
+        Some(open_map(&map_file)?)
+    } else {
+        None
+    };
+
+    let journal = journal_path(&map_file);
+    let journal_has_header = std::fs::metadata(&journal).map_or(false, |m| m.len() >= HEADER_LEN);
+    let (journal_chunk_shape, journal_chunks) = match (&base, journal_has_header) {
+        (_, true) => read_journal(&journal)?,
+        (Some(base), false) => (base.chunk_shape, Vec::new()),
+        (None, false) => return Err(MapFileError::NoRecoveryData),
+    };
+
+    let mut map_file = base.unwrap_or_else(|| MapFile {
+        chunk_shape: journal_chunk_shape,
+        palette: SdfVoxelPalette::default(),
+        bounds: None,
+        chunks: Vec::new(),
+        compressed_bytes: 0,
+    });
+    if map_file.chunk_shape != journal_chunk_shape {
+        return Err(MapFileError::BadChunkShape(journal_chunk_shape));
+    }
+
+    let mut chunk_indices: SmallKeyHashMap<ChunkKey3, usize> = map_file
+        .chunks
+        .iter()
+        .enumerate()
+        .map(|(i, (k, _))| (*k, i))
+        .collect();
+    for (chunk_key, chunk) in journal_chunks.into_iter() {
+        if let Some(&i) = chunk_indices.get(&chunk_key) {
+            map_file.chunks[i].1 = chunk;
+        } else {
+            chunk_indices.insert(chunk_key, map_file.chunks.len());
+            map_file.chunks.push((chunk_key, chunk));
+        }
+    }
+
+    Ok(map_file)
+}
+
+/// Deletes the autosave for `map_file`. The journal itself is truncated when the next `EditJournal` is created.
+pub fn discard_autosave(map_file: impl AsRef<Path>) -> io::Result<()> {
+    match std::fs::remove_file(autosave_path(map_file)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{VoxelType, DEFAULT_CHUNK_COMPRESSION, DEFAULT_CHUNK_SHAPE};
+
+    fn solid_chunk(chunk_min: Point3i, voxel_type: VoxelType) -> SdfArray {
+        SdfArray::fill(
+            Extent3i::from_min_and_shape(chunk_min, DEFAULT_CHUNK_SHAPE),
+            (voxel_type, Sd8::NEG_ONE),
+        )
+    }
+
+    #[test]
+    fn later_records_win_and_truncated_records_are_ignored() {
+        let path = std::env::temp_dir().join("bbe_later_records_win.journal");
+        let key1 = ChunkKey::new(0, PointN([0, 0, 0]));
+        let key2 = ChunkKey::new(0, PointN([16, 0, 0]));
+        {
+            let mut journal =
+                EditJournal::create(&path, DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION).unwrap();
+            journal
+                .append(key1, &solid_chunk(key1.minimum, VoxelType(1)))
+                .unwrap();
+            journal
+                .append(key2, &solid_chunk(key2.minimum, VoxelType(2)))
+                .unwrap();
+            journal
+                .append(key1, &solid_chunk(key1.minimum, VoxelType(3)))
+                .unwrap();
+            journal.flush().unwrap();
+        }
+        // Simulate a crash in the middle of writing a record.
+        {
+            let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(&[0, 1, 2, 3]).unwrap();
+        }
+
+        let (chunk_shape, chunks) = read_journal(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(chunk_shape, DEFAULT_CHUNK_SHAPE);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].0, key1);
+        assert_eq!(chunks[0].1.get(key1.minimum).0, VoxelType(3));
+        assert_eq!(chunks[1].0, key2);
+        assert_eq!(chunks[1].1.get(key2.minimum).0, VoxelType(2));
+    }
+
+    #[test]
+    fn reset_discards_records() {
+        let path = std::env::temp_dir().join("bbe_reset_discards_records.journal");
+        let key = ChunkKey::new(0, PointN([0, 0, 0]));
+
+        let mut journal =
+            EditJournal::create(&path, DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION).unwrap();
+        journal
+            .append(key, &solid_chunk(key.minimum, VoxelType(1)))
+            .unwrap();
+        journal.reset().unwrap();
+        journal.flush().unwrap();
+
+        let (_, chunks) = read_journal(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(chunks.is_empty());
+    }
+
+    #[test]
+    fn recovering_needs_a_map_or_a_journal_header() {
+        let map_file =
+            std::env::temp_dir().join("bbe_recovering_needs_a_map_or_a_journal_header.bbmap");
+        let journal = journal_path(&map_file);
+
+        // A crash before the header was written leaves an empty journal.
+        File::create(&journal).unwrap();
+        let result = recover_map(&map_file);
+        std::fs::remove_file(&journal).unwrap();
+        assert!(matches!(result, Err(MapFileError::NoRecoveryData)));
+
+        // Without a map, the journal's header decides the chunk shape.
+        EditJournal::create(&journal, DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION).unwrap();
+        let result = recover_map(&map_file);
+        std::fs::remove_file(&journal).unwrap();
+        let recovered = result.unwrap();
+        assert_eq!(recovered.chunk_shape, DEFAULT_CHUNK_SHAPE);
+        assert!(recovered.chunks.is_empty());
+    }
+}
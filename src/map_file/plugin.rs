@@ -0,0 +1,275 @@
+use super::{
+    autosave_path, background_save::is_final_event, discard_autosave, open_map, BackgroundSave,
+    EditJournal, MapSaveEvents, SAVE_CHUNKS_PER_FRAME,
+};
+
+use crate::{
+    ambient_sdf_array,
+    map_io::{EditBuffer, EditTransaction},
+    Config, SdfVoxelMap, StatePlugin,
+};
+
+use bevy::{app::prelude::*, core::Time, ecs::prelude::*, input::prelude::*};
+use building_blocks::storage::SmallKeyHashSet;
+
+/// Saves and opens the `SdfVoxelMap` at the path given by `Config::map_file`. Depends on the `MapIoPlugin`.
+///
+/// Saving happens in the background from a snapshot of the map, so editing can continue while the file is written. Progress is
+/// reported with `MapSaveEvents`.
+///
+/// If there is an `EditJournal` resource, the map is also autosaved every `Config::autosave_interval_secs`, and the journal is
+/// truncated whenever the map is saved or autosaved.
+pub struct MapFilePlugin;
+
+impl MapFilePlugin {
+    pub fn register_events(app: &mut AppBuilder) {
+        app.add_event::<MapFileEvents>()
+            .add_event::<MapSaveEvents>();
+    }
+}
+
+impl StatePlugin for MapFilePlugin {
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(map_file_default_input_map.system())
+            .with_system(map_file_system.system())
+            .with_system(background_save_system.system())
+            .with_system(autosave_system.system())
+    }
+}
+
+/// Whether the editor should start by recovering unsaved edits from the last session's autosave and journal.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecoverMap(pub bool);
+
+pub enum MapFileEvents {
+    Save,
+    Open,
+}
+
+pub fn map_file_default_input_map(
+    mut events: EventWriter<MapFileEvents>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    if !ctrl {
+        return;
+    }
+
+    if keyboard.just_pressed(KeyCode::S) {
+        events.send(MapFileEvents::Save);
+    } else if keyboard.just_pressed(KeyCode::O) {
+        events.send(MapFileEvents::Open);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn map_file_system(
+    mut commands: Commands,
+    config: Res<Config>,
+    mut voxel_map: ResMut<SdfVoxelMap>,
+    mut edit_buffer: ResMut<EditBuffer>,
+    mut transaction: ResMut<EditTransaction>,
+    mut journal: Option<ResMut<EditJournal>>,
+    background_save: Option<Res<BackgroundSave>>,
+    mut events: EventReader<MapFileEvents>,
+) {
+    for event in events.iter() {
+        match event {
+            MapFileEvents::Save => {
+                // The save's snapshot may already be drained while its thread is still writing, and a new snapshot would never
+                // be drained.
+                if background_save.is_some() {
+                    println!("Already saving map to {}", config.map_file);
+                    continue;
+                }
+                let header = if let Some(h) = voxel_map.begin_snapshot() {
+                    h
+                } else {
+                    println!("Already saving map to {}", config.map_file);
+                    continue;
+                };
+                println!("Saving {} chunks to {}", header.num_chunks, config.map_file);
+                let save = BackgroundSave::start(
+                    &config.map_file,
+                    header,
+                    config.chunk_cache.compression(),
+                    journal.as_ref().map(|j| j.num_records()),
+                );
+                commands.insert_resource(save);
+            }
+            MapFileEvents::Open => {
+                let map_file = match open_map(&config.map_file) {
+                    Ok(m) => m,
+                    Err(e) => {
+                        println!("Failed to open map {}: {}", config.map_file, e);
+                        continue;
+                    }
+                };
+                if map_file.chunk_shape != voxel_map.voxels.chunk_shape() {
+                    println!(
+                        "Failed to open map {}: chunk shape {:?} doesn't match the editor's {:?}",
+                        config.map_file,
+                        map_file.chunk_shape,
+                        voxel_map.voxels.chunk_shape()
+                    );
+                    continue;
+                }
+                // The palette asset is the source of truth for voxel types, so the map can only use the types it defines.
+                if let Some(voxel_type) = voxel_map
+                    .palette
+                    .find_undefined_voxel_type(map_file.chunks.iter().map(|(_, chunk)| chunk))
+                {
+                    println!(
+                        "Failed to open map {}: voxel type {} is not in {}",
+                        config.map_file, voxel_type.0, config.palette
+                    );
+                    continue;
+                }
+                println!("Opened map {}", config.map_file);
+
+                // Unsaved edits are thrown away, including any that are only previewed, and loading the file doesn't need to be
+                // journaled.
+                transaction.abort();
+                if let Some(journal) = journal.as_mut() {
+                    discard_recovery_data(&config.map_file, journal);
+                    journal.skip_next_merge();
+                }
+
+                if map_file.palette != voxel_map.palette {
+                    println!(
+                        "WARNING: The palette saved in {} differs from {}; using {}",
+                        config.map_file, config.palette, config.palette
+                    );
+                }
+
+                if map_file.bounds != voxel_map.bounds {
+                    println!("World bounds are now {:?}", map_file.bounds);
+                    voxel_map.bounds = map_file.bounds;
+                }
+
+                // Replace every chunk through the edit buffer so that meshes and octrees get regenerated. Chunks that aren't in
+                // the file are overwritten with ambient values, which will get them removed as empty chunks.
+                let new_chunk_mins: SmallKeyHashSet<_> =
+                    map_file.chunks.iter().map(|(k, _)| k.minimum).collect();
+                let indexer = voxel_map.voxels.indexer;
+                for chunk_key in voxel_map.chunk_keys().into_iter() {
+                    if !new_chunk_mins.contains(&chunk_key.minimum) {
+                        edit_buffer.insert_chunk(
+                            true,
+                            chunk_key.minimum,
+                            ambient_sdf_array(indexer.extent_for_chunk_with_min(chunk_key.minimum)),
+                        );
+                    }
+                }
+                for (chunk_key, chunk) in map_file.chunks.into_iter() {
+                    edit_buffer.insert_chunk(true, chunk_key.minimum, chunk);
+                }
+            }
+        }
+    }
+}
+
+/// Feeds the snapshot of the `SdfVoxelMap` to the `BackgroundSave` a few chunks per frame, and sends `MapSaveEvents` for its
+/// progress.
+pub fn background_save_system(
+    mut commands: Commands,
+    config: Res<Config>,
+    mut voxel_map: ResMut<SdfVoxelMap>,
+    background_save: Option<ResMut<BackgroundSave>>,
+    mut journal: Option<ResMut<EditJournal>>,
+    mut save_events: EventWriter<MapSaveEvents>,
+) {
+    let mut save = if let Some(s) = background_save {
+        s
+    } else {
+        return;
+    };
+
+    if save.is_sending() {
+        save.send_chunks(voxel_map.take_snapshot_chunks(SAVE_CHUNKS_PER_FRAME));
+    }
+
+    for event in save.poll().into_iter() {
+        match &event {
+            MapSaveEvents::Finished => {
+                println!("Saved map to {}", save.path().display());
+                if let Some(journal) = journal.as_mut() {
+                    // Edits made while saving aren't in the saved file, so they still need to be recoverable.
+                    if save.journal_records_at_snapshot == Some(journal.num_records()) {
+                        if save.is_autosave {
+                            // The autosave contains every journaled edit.
+                            if let Err(e) = journal.reset() {
+                                println!("WARNING: Failed to reset edit journal: {}", e);
+                            }
+                        } else {
+                            discard_recovery_data(&config.map_file, journal);
+                        }
+                    }
+                }
+            }
+            MapSaveEvents::Failed(e) => {
+                println!("Failed to save map to {}: {}", save.path().display(), e)
+            }
+            MapSaveEvents::Progress { .. } => (),
+        }
+        if is_final_event(&event) {
+            commands.remove_resource::<BackgroundSave>();
+        }
+        save_events.send(event);
+    }
+}
+
+/// Autosaves in the background, the same way as saving, but to the autosave path.
+pub fn autosave_system(
+    mut commands: Commands,
+    config: Res<Config>,
+    time: Res<Time>,
+    mut voxel_map: ResMut<SdfVoxelMap>,
+    journal: Option<Res<EditJournal>>,
+    background_save: Option<Res<BackgroundSave>>,
+    mut last_autosave_secs: Local<f64>,
+) {
+    let journal = if let Some(j) = journal {
+        j
+    } else {
+        return;
+    };
+    // The save will take care of the journal.
+    if background_save.is_some() {
+        return;
+    }
+
+    let now = time.seconds_since_startup();
+    if now - *last_autosave_secs < config.autosave_interval_secs {
+        return;
+    }
+    *last_autosave_secs = now;
+
+    if journal.num_records() == 0 {
+        return;
+    }
+
+    let header = if let Some(h) = voxel_map.begin_snapshot() {
+        h
+    } else {
+        return;
+    };
+    println!("Autosaving map {}", config.map_file);
+    let mut save = BackgroundSave::start(
+        autosave_path(&config.map_file),
+        header,
+        config.chunk_cache.compression(),
+        Some(journal.num_records()),
+    );
+    save.is_autosave = true;
+    commands.insert_resource(save);
+}
+
+fn discard_recovery_data(map_file: &str, journal: &mut EditJournal) {
+    if let Err(e) = journal.reset() {
+        println!("WARNING: Failed to reset edit journal: {}", e);
+    }
+    if let Err(e) = discard_autosave(map_file) {
+        println!("WARNING: Failed to delete autosave of {}: {}", map_file, e);
+    }
+}
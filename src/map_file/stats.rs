@@ -0,0 +1,48 @@
+use super::MapFile;
+
+use crate::VoxelType;
+
+use building_blocks::{prelude::*, storage::Sd8};
+
+/// Sizes and voxel counts of a map file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MapStats {
+    pub num_chunks: usize,
+    pub compressed_bytes: usize,
+    /// The size of the chunks' `(VoxelType, Sd8)` pairs once decompressed.
+    pub decompressed_bytes: usize,
+    /// `voxel_counts[i]` is the number of voxels of `VoxelType(i)`.
+    pub voxel_counts: Vec<u64>,
+}
+
+impl MapStats {
+    pub fn new(map_file: &MapFile) -> Self {
+        let mut voxel_counts = vec![0; 256];
+        let mut decompressed_bytes = 0;
+        for (_, chunk) in map_file.chunks.iter() {
+            decompressed_bytes += 2 * chunk.extent().shape.volume() as usize;
+            chunk.for_each(
+                chunk.extent(),
+                |_p: Point3i, (v_type, _): (VoxelType, Sd8)| {
+                    voxel_counts[v_type.0 as usize] += 1;
+                },
+            );
+        }
+
+        Self {
+            num_chunks: map_file.chunks.len(),
+            compressed_bytes: map_file.compressed_bytes,
+            decompressed_bytes,
+            voxel_counts,
+        }
+    }
+
+    /// The types that appear in the map, with their counts.
+    pub fn used_voxel_types(&self) -> impl Iterator<Item = (VoxelType, u64)> + '_ {
+        self.voxel_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| (VoxelType(i as u8), count))
+    }
+}
@@ -1,15 +1,31 @@
 mod chunk_cache_flusher;
 mod chunk_compressor;
+mod chunk_events;
+mod chunk_swap;
 mod edit_buffer;
+mod edit_transaction;
 mod editor;
 mod empty_chunk_remover;
+mod lod_downsampler;
 mod plugin;
+mod snapshot;
+mod uniform_chunks;
 
 pub use chunk_compressor::ChunkCacheConfig;
+pub use chunk_events::{
+    ChunkChange, ChunkChangeCursor, ChunkChangeLog, ChunkRemoved, ChunksEdited,
+};
+pub use chunk_swap::{ChunkSwap, ChunkTierCounts};
 pub use edit_buffer::{double_buffering_system, DirtyChunks, EditBuffer};
+pub use edit_transaction::EditTransaction;
 pub use editor::VoxelEditor;
 pub use empty_chunk_remover::EmptyChunks;
+pub use lod_downsampler::{ancestor_chunk_min, downsample_sdf_chunk, LodChunks, LodConfig};
 pub use plugin::MapIoPlugin;
+pub use snapshot::MapSnapshotHeader;
+pub use uniform_chunks::{uniform_chunk_value, UniformChunks};
+
+pub(crate) use snapshot::MapSnapshot;
 
 use crate::{SdfChunkCache, ThreadLocalResource};
 
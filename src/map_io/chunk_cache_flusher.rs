@@ -4,7 +4,8 @@ use crate::SdfVoxelMap;
 
 use bevy::prelude::*;
 
-/// A system that flushes thread-local voxel chunk caches into the global map's cache.
+/// A system that flushes thread-local voxel chunk caches into the global map's cache, along with the chunks that were faulted in
+/// from swap.
 pub fn chunk_cache_flusher_system(
     mut local_caches: ResMut<ThreadLocalVoxelCache>,
     mut voxel_map: ResMut<SdfVoxelMap>,
@@ -13,4 +14,5 @@ pub fn chunk_cache_flusher_system(
     for cache in taken_caches.into_iter() {
         voxel_map.voxels.storage_mut().flush_local_cache(cache);
     }
+    voxel_map.flush_faulted_chunks();
 }
@@ -1,13 +1,20 @@
-use crate::SdfVoxelMap;
+use crate::{map_file::encode_chunk, SdfVoxelMap, DEFAULT_CHUNK_COMPRESSION};
 
 use bevy::{prelude::*, tasks::ComputeTaskPool};
 use building_blocks::storage::{Compression, FastArrayCompression, FromBytesCompression, Lz4};
+use serde::Deserialize;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
 pub struct ChunkCacheConfig {
     // These constants should be correlated with the size of a chunk.
     pub max_cached_chunks: usize,
     pub max_chunks_compressed_per_frame_per_thread: usize,
+    // Compressed chunks beyond this budget get paged out to the swap file.
+    pub max_compressed_bytes: usize,
+    pub max_chunks_swapped_per_frame: usize,
+    // The LZ4 level for chunks that get compressed in memory. Higher levels are smaller but slower to compress.
+    pub compression_level: u32,
 }
 
 impl Default for ChunkCacheConfig {
@@ -18,6 +25,18 @@ impl Default for ChunkCacheConfig {
             // Avoid high latency from compressing too many chunks in one frame. 8192-byte chunk
             // compression latency is around 0.01 ms.
             max_chunks_compressed_per_frame_per_thread: 50,
+            max_compressed_bytes: 1 << 30,
+            // Swapping is bound by disk latency, so keep the per-frame work small.
+            max_chunks_swapped_per_frame: 100,
+            compression_level: DEFAULT_CHUNK_COMPRESSION.level,
+        }
+    }
+}
+
+impl ChunkCacheConfig {
+    pub fn compression(&self) -> Lz4 {
+        Lz4 {
+            level: self.compression_level,
         }
     }
 }
@@ -48,17 +67,24 @@ pub fn chunk_compressor_system(
         }
     }
 
-    let compression = FastArrayCompression::from_bytes_compression(Lz4 { level: 10 });
+    let compression = FastArrayCompression::from_bytes_compression(cache_config.compression());
     let compressed_chunks = pool.scope(|s| {
         for (key, chunk) in chunks_to_compress.into_iter() {
-            s.spawn(async move { (key, compression.compress(&chunk)) });
+            s.spawn(async move {
+                // Measure the chunk the same way it would be written to swap, so the swapper can keep to its byte budget.
+                let mut compressed_bytes = Vec::new();
+                encode_chunk(&chunk, &mut Vec::new(), &mut compressed_bytes);
+
+                (key, compression.compress(&chunk), compressed_bytes.len())
+            });
         }
     });
 
-    for (key, compressed_chunk) in compressed_chunks.into_iter() {
+    for (key, compressed_chunk, num_bytes) in compressed_chunks.into_iter() {
         voxel_map
             .voxels
             .storage_mut()
             .insert_compressed(key, compressed_chunk);
+        voxel_map.swap.note_compressed(key, num_bytes);
     }
 }
@@ -0,0 +1,154 @@
+use building_blocks::{prelude::*, storage::ChunkKey3};
+use std::collections::VecDeque;
+
+/// Sent at the end of every frame in which edits were merged into the `SdfVoxelMap`. This is the same information as the
+/// `DirtyChunks` resource, which only lasts for a single frame.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChunksEdited {
+    /// The minimums of the chunks that were written.
+    pub edited: Vec<Point3i>,
+    /// The minimums of the edited chunks and any neighbors that were touched.
+    pub dirty: Vec<Point3i>,
+}
+
+/// Sent when a chunk is removed from the `SdfVoxelMap` because it was empty.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ChunkRemoved(pub ChunkKey3);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChunkChange {
+    Edited(ChunksEdited),
+    Removed(ChunkKey3),
+}
+
+/// Every chunk change that hasn't been read by all of the subscribers yet. Unlike bevy's `Events`, which are dropped after two
+/// frames, a subscriber can read as rarely as it wants without missing anything.
+///
+/// Changes are only kept while there are subscribers, and a subscriber that never reads will keep every change in memory, so
+/// unsubscribe when you're done.
+#[derive(Default)]
+pub struct ChunkChangeLog {
+    changes: VecDeque<ChunkChange>,
+    // The sequence number of the first change in `changes`.
+    first_seq: u64,
+    // The sequence number of the next change that each subscriber will read, or `None` if the subscriber is gone.
+    cursors: Vec<Option<u64>>,
+}
+
+/// A subscriber's position in the `ChunkChangeLog`.
+#[derive(Debug)]
+pub struct ChunkChangeCursor(usize);
+
+impl ChunkChangeLog {
+    /// The new subscriber will see every change pushed after this call.
+    pub fn subscribe(&mut self) -> ChunkChangeCursor {
+        let end = self.end_seq();
+        if let Some(i) = self.cursors.iter().position(Option::is_none) {
+            self.cursors[i] = Some(end);
+
+            ChunkChangeCursor(i)
+        } else {
+            self.cursors.push(Some(end));
+
+            ChunkChangeCursor(self.cursors.len() - 1)
+        }
+    }
+
+    pub fn unsubscribe(&mut self, cursor: ChunkChangeCursor) {
+        self.cursors[cursor.0] = None;
+        self.trim();
+    }
+
+    pub fn push(&mut self, change: ChunkChange) {
+        self.trim();
+        if self.cursors.iter().all(Option::is_none) {
+            self.first_seq += 1;
+            return;
+        }
+        self.changes.push_back(change);
+    }
+
+    /// Returns every change since the last time `cursor` was read.
+    pub fn read(&mut self, cursor: &ChunkChangeCursor) -> impl Iterator<Item = &ChunkChange> + '_ {
+        // Trim before moving the cursor, so the changes we return stay in the log until the next push or read.
+        self.trim();
+
+        let end = self.end_seq();
+        let start = self.cursors[cursor.0]
+            .replace(end)
+            .expect("Read with a cursor that was unsubscribed");
+        // Our cursor was part of the minimum that we trimmed to, so our changes are still here.
+        let skip = (start - self.first_seq) as usize;
+        self.changes.iter().skip(skip)
+    }
+
+    fn end_seq(&self) -> u64 {
+        self.first_seq + self.changes.len() as u64
+    }
+
+    fn trim(&mut self) {
+        let min_cursor = self
+            .cursors
+            .iter()
+            .filter_map(|c| *c)
+            .min()
+            .unwrap_or_else(|| self.end_seq());
+        while self.first_seq < min_cursor {
+            self.changes.pop_front();
+            self.first_seq += 1;
+        }
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn removed(x: i32) -> ChunkChange {
+        ChunkChange::Removed(ChunkKey::new(0, PointN([x, 0, 0])))
+    }
+
+    #[test]
+    fn slow_subscribers_see_every_change() {
+        let mut log = ChunkChangeLog::default();
+        log.push(removed(-1));
+
+        let fast = log.subscribe();
+        let slow = log.subscribe();
+        for x in 0..3 {
+            log.push(removed(x));
+            assert_eq!(
+                log.read(&fast).cloned().collect::<Vec<_>>(),
+                vec![removed(x)]
+            );
+        }
+
+        assert_eq!(
+            log.read(&slow).cloned().collect::<Vec<_>>(),
+            vec![removed(0), removed(1), removed(2)]
+        );
+        assert_eq!(log.read(&slow).count(), 0);
+        // Everything has been read by everyone, which the last read trimmed.
+        assert!(log.changes.is_empty());
+
+        log.unsubscribe(slow);
+        log.push(removed(3));
+        let late = log.subscribe();
+        log.push(removed(4));
+        assert_eq!(
+            log.read(&fast).cloned().collect::<Vec<_>>(),
+            vec![removed(3), removed(4)]
+        );
+        assert_eq!(
+            log.read(&late).cloned().collect::<Vec<_>>(),
+            vec![removed(4)]
+        );
+    }
+}
@@ -0,0 +1,525 @@
+use super::ChunkCacheConfig;
+
+use crate::{
+    map_file::{decode_chunk, encode_chunk, MapFileError},
+    SdfArray, SdfVoxelMap,
+};
+
+use bevy::ecs::prelude::*;
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, MaybeCompressed, SmallKeyHashMap},
+};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// The second storage tier, for compressed chunks that have been evicted from memory. Chunks are appended to a local swap file
+/// using the same LZ4 encoding as the map file, and they are read back when they're needed again.
+///
+/// Readers with a shared reference fault chunks in with `fault_in`, which only reads each chunk from the file once. The faulted
+/// chunks are moved back into memory at the end of the frame by `SdfVoxelMap::flush_faulted_chunks`.
+///
+/// The swap file is created on the first write and deleted when the `ChunkSwap` is dropped.
+pub struct ChunkSwap {
+    path: PathBuf,
+    // Only locked for the duration of a single read or write, so readers with a shared reference can fault chunks in.
+    file: Mutex<Option<File>>,
+    slots: SmallKeyHashMap<ChunkKey3, SwapSlot>,
+    // Chunks that were faulted in since the last flush. They stay in `slots` until they're flushed into memory.
+    faulted: Mutex<SmallKeyHashMap<ChunkKey3, SdfArray>>,
+    file_len: u64,
+    stale_bytes: u64,
+    // Keys of chunks in the order they were compressed. Some of them may since have been decompressed or removed.
+    compressed_order: VecDeque<ChunkKey3>,
+    // The size of each chunk in `compressed_order` when it was compressed, so each chunk is only in there once.
+    compressed_chunk_bytes: SmallKeyHashMap<ChunkKey3, usize>,
+    // The sum of `compressed_chunk_bytes`.
+    compressed_bytes: usize,
+}
+
+#[derive(Clone, Copy)]
+struct SwapSlot {
+    offset: u64,
+    len: u32,
+}
+
+/// The number of chunks in each storage tier of an `SdfVoxelMap`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ChunkTierCounts {
+    pub cached: usize,
+    pub compressed: usize,
+    pub uniform: usize,
+    pub swapped: usize,
+}
+
+impl Default for ChunkSwap {
+    fn default() -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+
+        Self::new(std::env::temp_dir().join(format!(
+            "building-blocks-editor-{}-{}.swap",
+            std::process::id(),
+            id
+        )))
+    }
+}
+
+impl Drop for ChunkSwap {
+    fn drop(&mut self) {
+        if self.file.get_mut().unwrap().take().is_some() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl ChunkSwap {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+            slots: Default::default(),
+            faulted: Default::default(),
+            file_len: 0,
+            stale_bytes: 0,
+            compressed_order: Default::default(),
+            compressed_chunk_bytes: Default::default(),
+            compressed_bytes: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn contains(&self, chunk_key: ChunkKey3) -> bool {
+        self.slots.contains_key(&chunk_key)
+    }
+
+    pub fn chunk_keys(&self) -> impl Iterator<Item = &ChunkKey3> {
+        self.slots.keys()
+    }
+
+    /// Reads a copy of the chunk at `chunk_key` if it's swapped out. The chunk stays in the swap file.
+    pub fn read(
+        &self,
+        chunk_key: ChunkKey3,
+        extent: Extent3i,
+    ) -> Result<Option<SdfArray>, MapFileError> {
+        if let Some(chunk) = self.faulted.lock().unwrap().get(&chunk_key) {
+            return Ok(Some(chunk.clone()));
+        }
+        let slot = if let Some(s) = self.slots.get(&chunk_key) {
+            *s
+        } else {
+            return Ok(None);
+        };
+
+        let mut compressed_bytes = vec![0; slot.len as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            let file = file
+                .as_mut()
+                .expect("Swap file must exist if there are slots");
+            file.seek(SeekFrom::Start(slot.offset))?;
+            file.read_exact(&mut compressed_bytes)?;
+        }
+
+        decode_chunk(chunk_key, extent, &compressed_bytes, &mut Vec::new()).map(Some)
+    }
+
+    /// Like `read`, but the chunk is kept until the next `take_faulted`, so it's only read from the file once.
+    pub fn fault_in(
+        &self,
+        chunk_key: ChunkKey3,
+        extent: Extent3i,
+    ) -> Result<Option<SdfArray>, MapFileError> {
+        let chunk = self.read(chunk_key, extent)?;
+        if let Some(chunk) = &chunk {
+            self.faulted
+                .lock()
+                .unwrap()
+                .entry(chunk_key)
+                .or_insert_with(|| chunk.clone());
+        }
+
+        Ok(chunk)
+    }
+
+    /// Removes the chunks that were faulted in since the last call, so they can be moved into memory.
+    pub fn take_faulted(&mut self) -> Vec<(ChunkKey3, SdfArray)> {
+        let faulted = std::mem::take(self.faulted.get_mut().unwrap());
+        for chunk_key in faulted.keys() {
+            self.forget(*chunk_key);
+        }
+
+        faulted.into_iter().collect()
+    }
+
+    /// Removes the chunk at `chunk_key` from the swap file and returns it.
+    pub fn take(
+        &mut self,
+        chunk_key: ChunkKey3,
+        extent: Extent3i,
+    ) -> Result<Option<SdfArray>, MapFileError> {
+        let chunk = self.read(chunk_key, extent)?;
+        self.remove(chunk_key);
+
+        Ok(chunk)
+    }
+
+    /// Forgets about the chunk at `chunk_key`. Its bytes stay in the file until the next compaction.
+    pub fn remove(&mut self, chunk_key: ChunkKey3) {
+        self.faulted.get_mut().unwrap().remove(&chunk_key);
+        self.forget(chunk_key);
+    }
+
+    fn forget(&mut self, chunk_key: ChunkKey3) {
+        if let Some(slot) = self.slots.remove(&chunk_key) {
+            self.stale_bytes += slot.len as u64;
+        }
+    }
+
+    /// Appends `chunk` to the swap file. Returns the number of bytes written.
+    pub fn write(&mut self, chunk_key: ChunkKey3, chunk: &SdfArray) -> io::Result<u32> {
+        let mut compressed_bytes = Vec::new();
+        encode_chunk(chunk, &mut Vec::new(), &mut compressed_bytes);
+
+        let offset = self.file_len;
+        {
+            let mut file = self.file.lock().unwrap();
+            if file.is_none() {
+                *file = Some(
+                    OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&self.path)?,
+                );
+            }
+            let file = file.as_mut().unwrap();
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&compressed_bytes)?;
+        }
+
+        let len = compressed_bytes.len() as u32;
+        self.file_len += len as u64;
+        self.remove(chunk_key);
+        self.slots.insert(chunk_key, SwapSlot { offset, len });
+
+        Ok(len)
+    }
+
+    /// Rewrites the swap file without any stale bytes, once they make up at least half of the file. Chunk bytes are copied
+    /// without being decoded.
+    pub fn maybe_compact(&mut self) -> io::Result<()> {
+        if self.stale_bytes < MIN_STALE_BYTES_FOR_COMPACTION || 2 * self.stale_bytes < self.file_len
+        {
+            return Ok(());
+        }
+
+        let compact_path = self.path.with_extension("swap.compact");
+        let mut compact_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&compact_path)?;
+
+        let file = self.file.get_mut().unwrap();
+        let old_file = file
+            .as_mut()
+            .expect("Swap file must exist if there are stale bytes");
+        let mut new_len = 0;
+        let mut bytes = Vec::new();
+        for slot in self.slots.values_mut() {
+            bytes.resize(slot.len as usize, 0);
+            old_file.seek(SeekFrom::Start(slot.offset))?;
+            old_file.read_exact(&mut bytes)?;
+            compact_file.write_all(&bytes)?;
+            slot.offset = new_len;
+            new_len += slot.len as u64;
+        }
+
+        std::fs::rename(&compact_path, &self.path)?;
+        *file = Some(compact_file);
+        self.file_len = new_len;
+        self.stale_bytes = 0;
+
+        Ok(())
+    }
+
+    /// Remember that the chunk at `chunk_key` was just compressed into `num_bytes`, making it a candidate for swapping.
+    pub fn note_compressed(&mut self, chunk_key: ChunkKey3, num_bytes: usize) {
+        if let Some(old_bytes) = self.compressed_chunk_bytes.insert(chunk_key, num_bytes) {
+            // A chunk that's already in the order keeps its place.
+            self.compressed_bytes -= old_bytes;
+        } else {
+            self.compressed_order.push_back(chunk_key);
+        }
+        self.compressed_bytes += num_bytes;
+    }
+
+    fn pop_compressed(&mut self) {
+        if let Some(chunk_key) = self.compressed_order.pop_front() {
+            if let Some(num_bytes) = self.compressed_chunk_bytes.remove(&chunk_key) {
+                self.compressed_bytes -= num_bytes;
+            }
+        }
+    }
+
+    /// The sum of the sizes of the compressed chunks, as they were noted. Chunks that were decompressed or removed since are
+    /// still counted until the swapper skips over them, so this is never less than the bytes really used.
+    pub fn compressed_bytes(&self) -> usize {
+        self.compressed_bytes
+    }
+}
+
+const MIN_STALE_BYTES_FOR_COMPACTION: u64 = 64 << 20;
+
+/// A system that pages the oldest compressed chunks out to the swap file when the compressed tier grows beyond
+/// `ChunkCacheConfig::max_compressed_bytes`.
+pub fn chunk_swapper_system(
+    cache_config: Res<ChunkCacheConfig>,
+    mut voxel_map: ResMut<SdfVoxelMap>,
+) {
+    let voxel_map = &mut *voxel_map;
+
+    let mut num_swapped = 0;
+    while num_swapped < cache_config.max_chunks_swapped_per_frame {
+        if voxel_map.swap.compressed_bytes() <= cache_config.max_compressed_bytes {
+            break;
+        }
+
+        let (chunk_key, chunk) = if let Some(c) = oldest_compressed_chunk(voxel_map) {
+            c
+        } else {
+            break;
+        };
+
+        match voxel_map.swap.write(chunk_key, &chunk) {
+            Ok(_) => {
+                voxel_map.swap.pop_compressed();
+                voxel_map.voxels.storage_mut().remove(chunk_key);
+                num_swapped += 1;
+            }
+            Err(e) => {
+                // Keep the chunk in memory.
+                println!("Failed to swap out chunk {:?}: {}", chunk_key, e);
+                break;
+            }
+        }
+    }
+
+    if num_swapped > 0 {
+        if let Err(e) = voxel_map.swap.maybe_compact() {
+            println!("Failed to compact swap file: {}", e);
+        }
+
+        let counts = voxel_map.tier_counts();
+        println!(
+            "Swapped out {} chunks; {} cached, {} compressed, {} uniform, {} swapped",
+            num_swapped, counts.cached, counts.compressed, counts.uniform, counts.swapped
+        );
+    }
+}
+
+/// Copies the oldest chunk that is still compressed. Keys in front of it are dropped from the order; anything that got
+/// decompressed since will be noted again when it's compressed again.
+fn oldest_compressed_chunk(voxel_map: &mut SdfVoxelMap) -> Option<(ChunkKey3, SdfArray)> {
+    while let Some(&chunk_key) = voxel_map.swap.compressed_order.front() {
+        if let Some(c @ MaybeCompressed::Compressed(_)) =
+            voxel_map.voxels.storage().copy_without_caching(chunk_key)
+        {
+            return Some((chunk_key, c.into_decompressed()));
+        }
+        voxel_map.swap.pop_compressed();
+    }
+
+    None
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        empty_compressible_sdf_chunk_map, ThreadLocalVoxelCache, VoxelType,
+        DEFAULT_CHUNK_COMPRESSION, DEFAULT_CHUNK_SHAPE,
+    };
+
+    use building_blocks::storage::{Compression, FastArrayCompression, FromBytesCompression};
+
+    #[test]
+    fn swapped_chunks_read_back_unchanged() {
+        let mut swap = ChunkSwap::default();
+
+        let mut chunks = Vec::new();
+        for i in 0..3 {
+            let chunk = patterned_chunk(i);
+            let chunk_key = ChunkKey::new(0, chunk.extent().minimum);
+            swap.write(chunk_key, &chunk).unwrap();
+            chunks.push((chunk_key, chunk));
+        }
+        assert_eq!(swap.len(), 3);
+
+        // Overwriting a chunk leaves stale bytes behind, but reads see the newest copy.
+        let (key0, chunk0) = &chunks[0];
+        swap.write(*key0, chunk0).unwrap();
+        assert_eq!(swap.len(), 3);
+
+        let (key1, chunk1) = &chunks[1];
+        let taken = swap.take(*key1, *chunk1.extent()).unwrap().unwrap();
+        assert!(!swap.contains(*key1));
+        assert!(swap.read(*key1, *chunk1.extent()).unwrap().is_none());
+        assert_chunks_equal(&taken, chunk1);
+
+        for (chunk_key, chunk) in [&chunks[0], &chunks[2]].iter() {
+            let read = swap.read(*chunk_key, *chunk.extent()).unwrap().unwrap();
+            assert_chunks_equal(&read, chunk);
+        }
+    }
+
+    #[test]
+    fn swapper_pages_out_the_oldest_compressed_chunks_past_the_budget() {
+        let mut map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION),
+            Default::default(),
+        );
+        let chunks: Vec<_> = (0..4).map(patterned_chunk).collect();
+        for chunk in chunks.iter() {
+            map.voxels
+                .write_chunk(ChunkKey::new(0, chunk.extent().minimum), chunk.clone());
+        }
+
+        // A chunk that was removed before it got swapped is skipped.
+        map.swap
+            .note_compressed(ChunkKey::new(0, PointN([-16, 0, 0])), 100);
+        let compression = FastArrayCompression::from_bytes_compression(DEFAULT_CHUNK_COMPRESSION);
+        while let Some((chunk_key, chunk)) = map.voxels.storage_mut().remove_lru() {
+            map.voxels
+                .storage_mut()
+                .insert_compressed(chunk_key, compression.compress(&chunk));
+            map.swap.note_compressed(chunk_key, 50);
+            // Noting a chunk again doesn't queue it twice, and only its latest size counts.
+            map.swap.note_compressed(chunk_key, 100);
+        }
+        assert_eq!(map.swap.compressed_order.len(), 5);
+        assert_eq!(map.swap.compressed_bytes(), 500);
+
+        let mut world = World::default();
+        world.insert_resource(ChunkCacheConfig {
+            max_compressed_bytes: 150,
+            max_chunks_swapped_per_frame: 3,
+            ..Default::default()
+        });
+        world.insert_resource(map);
+        let mut stage = SystemStage::single(chunk_swapper_system.system());
+
+        // The removed chunk is skipped, and three chunks get the rest under the budget.
+        stage.run(&mut world);
+        let expected_counts = ChunkTierCounts {
+            cached: 0,
+            compressed: 1,
+            uniform: 0,
+            swapped: 3,
+        };
+        let map = world.get_resource::<SdfVoxelMap>().unwrap();
+        assert_eq!(map.tier_counts(), expected_counts);
+        assert_eq!(map.swap.compressed_bytes(), 100);
+
+        stage.run(&mut world);
+        assert_eq!(
+            world.get_resource::<SdfVoxelMap>().unwrap().tier_counts(),
+            expected_counts
+        );
+
+        // The last chunk grows past the budget when it's compressed again.
+        world
+            .get_resource_mut::<SdfVoxelMap>()
+            .unwrap()
+            .swap
+            .note_compressed(ChunkKey::new(0, chunks[3].extent().minimum), 200);
+        stage.run(&mut world);
+        let map = world.get_resource::<SdfVoxelMap>().unwrap();
+        assert_eq!(
+            map.tier_counts(),
+            ChunkTierCounts {
+                cached: 0,
+                compressed: 0,
+                uniform: 0,
+                swapped: 4,
+            }
+        );
+        assert!(map.swap.compressed_order.is_empty());
+        assert_eq!(map.swap.compressed_bytes(), 0);
+
+        // Readers fault the swapped chunks in, and they're moved into memory by the next flush.
+        let local_caches = ThreadLocalVoxelCache::new();
+        let cache_tls = local_caches.get();
+        let reader = map.reader(&cache_tls);
+        for chunk in chunks[..2].iter() {
+            let chunk_key = ChunkKey::new(0, chunk.extent().minimum);
+            assert_chunks_equal(&reader.get_chunk(chunk_key).unwrap(), chunk);
+        }
+
+        let mut map = world.get_resource_mut::<SdfVoxelMap>().unwrap();
+        map.flush_faulted_chunks();
+        assert_eq!(
+            map.tier_counts(),
+            ChunkTierCounts {
+                cached: 2,
+                compressed: 0,
+                uniform: 0,
+                swapped: 2,
+            }
+        );
+        for chunk in chunks.iter() {
+            let chunk_key = ChunkKey::new(0, chunk.extent().minimum);
+            assert_chunks_equal(&map.copy_chunk_without_caching(chunk_key).unwrap(), chunk);
+        }
+    }
+
+    fn patterned_chunk(i: i32) -> SdfArray {
+        let extent = Extent3i::from_min_and_shape(PointN([16 * i, 0, 0]), DEFAULT_CHUNK_SHAPE);
+        let mut chunk = SdfArray::fill(extent, (VoxelType(0), Sd8::ONE));
+        chunk.for_each_mut(&extent, |p: Point3i, (v_type, v_dist)| {
+            *v_type = VoxelType((p.x() % 3) as u8);
+            *v_dist = Sd8((p.y() - p.z()) as i8);
+        });
+
+        chunk
+    }
+
+    fn assert_chunks_equal(chunk1: &SdfArray, chunk2: &SdfArray) {
+        assert_eq!(chunk1.extent(), chunk2.extent());
+        chunk1.for_each(
+            chunk1.extent(),
+            |p: Point3i, (v_type, v_dist): (VoxelType, Sd8)| {
+                let (other_type, other_dist) = chunk2.get(p);
+                assert_eq!(v_type, other_type);
+                assert_eq!(v_dist.0, other_dist.0);
+            },
+        );
+    }
+}
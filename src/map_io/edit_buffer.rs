@@ -1,10 +1,15 @@
+use super::{ChunkChange, ChunkChangeLog, ChunksEdited};
+
 use crate::{
-    map::{empty_sdf_chunk_hash_map, CompressibleSdfChunkMapReader, SdfArray, SdfChunkHashMap},
-    CompressibleSdfChunkMap, SdfVoxelMap, VoxelType, EMPTY_SDF_VOXEL,
+    map::{empty_sdf_chunk_hash_map, SdfArray, SdfChunkHashMap},
+    EditJournal, SdfVoxelMap, VoxelType, EMPTY_SDF_VOXEL,
 };
 
 use bevy::prelude::*;
-use building_blocks::{prelude::*, storage::SmallKeyHashSet};
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, SmallKeyHashSet},
+};
 
 /// For the sake of pipelining, all voxels edits are first written out of place here. They can later be merged into another
 /// chunk map by overwriting the dirty chunks.
@@ -23,36 +28,33 @@ impl EditBuffer {
     }
 
     /// This function does read-modify-write of the voxels in `extent`. If a chunk is missing from the backbuffer, it will be
-    /// copied from the `reader` before being written.
+    /// copied from the `map` before being written. Chunks in the map's swap file are read directly from swap.
     ///
     /// If `touch_neighbors`, then all chunks in the Moore Neighborhood of any edited chunk will be marked as dirty. This is
     /// useful when there are dependencies between adjacent chunks that must be considered during post-processing (e.g. during
     /// mesh generation).
     pub fn edit_voxels_out_of_place(
         &mut self,
-        reader: &CompressibleSdfChunkMapReader,
+        map: &SdfVoxelMap,
         extent: Extent3i,
         edit_func: impl FnMut(Point3i, (&mut VoxelType, &mut Sd8)),
         touch_neighbors: bool,
     ) {
-        debug_assert!(reader.chunk_shape().eq(&self.edited_voxels.chunk_shape()));
+        let indexer = map.voxels.indexer;
+        debug_assert!(indexer.chunk_shape().eq(&self.edited_voxels.chunk_shape()));
 
         // Copy any of the overlapping chunks that don't already exist in the backbuffer, i.e. those chunks which haven't been
         // modified yet.
-        for chunk_min in reader.indexer.chunk_mins_for_extent(&extent) {
+        for chunk_min in indexer.chunk_mins_for_extent(&extent) {
             let chunk_key = ChunkKey::new(0, chunk_min);
             self.edited_voxels
                 .get_mut_chunk_or_insert_with(chunk_key, || {
-                    reader
-                        .storage()
-                        .storage
-                        // We don't cache the chunk yet, because we're just going to modify this copy and insert back into the
-                        // map later.
-                        .copy_without_caching(chunk_key)
-                        .map(|c| c.into_decompressed())
+                    // We don't cache the chunk yet, because we're just going to modify this copy and insert back into the map
+                    // later.
+                    map.copy_chunk_without_caching(chunk_key)
                         .unwrap_or_else(|| {
                             SdfArray::fill(
-                                reader.indexer.extent_for_chunk_with_min(chunk_min),
+                                indexer.extent_for_chunk_with_min(chunk_min),
                                 EMPTY_SDF_VOXEL,
                             )
                         })
@@ -78,8 +80,13 @@ impl EditBuffer {
             .write_chunk(ChunkKey::new(0, chunk_min), chunk);
     }
 
-    /// Write all of the edited chunks into `dst_map`. Returns the dirty chunks.
-    pub fn merge_edits(self, dst_map: &mut CompressibleSdfChunkMap) -> DirtyChunks {
+    /// The chunks that have been written so far.
+    pub fn edited_chunks(&self) -> impl Iterator<Item = (&ChunkKey3, &SdfArray)> {
+        self.edited_voxels.storage().iter()
+    }
+
+    /// Write all of the edited chunks into `dst_map`, storing the uniform ones compactly. Returns the dirty chunks.
+    pub fn merge_edits(self, dst_map: &mut SdfVoxelMap) -> DirtyChunks {
         let EditBuffer {
             edited_voxels,
             dirty_chunk_mins,
@@ -125,15 +132,49 @@ pub struct DirtyChunks {
 }
 
 /// Merges edits from the `EditBuffer` into the `SdfVoxelMap`. By setting the `DirtyChunks` resource, the `chunk_processor_system`
-/// will be notified to process dirty chunks on the next frame.
+/// will be notified to process dirty chunks on the next frame. Dirty chunks are faulted in from swap so they can be read.
+///
+/// If there is an `EditJournal`, the edited chunks are appended to it before they are merged. Any edits are also announced with a
+/// `ChunksEdited` event and in the `ChunkChangeLog`.
 pub fn double_buffering_system(
     mut voxel_map: ResMut<SdfVoxelMap>,
     mut edit_buffer: ResMut<EditBuffer>,
     mut dirty_chunks: ResMut<DirtyChunks>,
+    mut edited_events: EventWriter<ChunksEdited>,
+    mut change_log: ResMut<ChunkChangeLog>,
+    journal: Option<ResMut<EditJournal>>,
 ) {
+    if let Some(mut journal) = journal {
+        journal_edits(&edit_buffer, &mut *journal);
+    }
+
     let edit_buffer = std::mem::replace(
         &mut *edit_buffer,
         EditBuffer::new(voxel_map.voxels.chunk_shape()),
     );
-    *dirty_chunks = edit_buffer.merge_edits(&mut voxel_map.voxels);
+    *dirty_chunks = edit_buffer.merge_edits(&mut *voxel_map);
+    voxel_map.fault_in_chunks(dirty_chunks.dirty_chunk_mins.iter().cloned());
+
+    if !dirty_chunks.dirty_chunk_mins.is_empty() {
+        let event = ChunksEdited {
+            edited: dirty_chunks.edited_chunk_mins.clone(),
+            dirty: dirty_chunks.dirty_chunk_mins.iter().cloned().collect(),
+        };
+        change_log.push(ChunkChange::Edited(event.clone()));
+        edited_events.send(event);
+    }
+}
+
+fn journal_edits(edit_buffer: &EditBuffer, journal: &mut EditJournal) {
+    let mut edited_chunks = edit_buffer.edited_chunks().peekable();
+    if edited_chunks.peek().is_none() || journal.take_skip_merge() {
+        return;
+    }
+
+    let result = edited_chunks
+        .try_for_each(|(chunk_key, chunk)| journal.append(*chunk_key, chunk))
+        .and_then(|()| journal.flush());
+    if let Err(e) = result {
+        println!("WARNING: Failed to write edit journal: {}", e);
+    }
 }
@@ -0,0 +1,214 @@
+use super::DirtyChunks;
+
+use crate::{
+    map::{empty_sdf_chunk_hash_map, SdfArray, SdfChunkHashMap},
+    SdfVoxelMap, VoxelType, EMPTY_SDF_VOXEL,
+};
+
+use bevy::prelude::*;
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, SmallKeyHashSet},
+};
+
+/// Edits that are collected over any number of frames without being merged into the `SdfVoxelMap`. The mesh generator renders
+/// LOD 0 as if these edits were already merged, so a tool can preview an edit until it's committed or aborted with the
+/// `VoxelEditor`.
+///
+/// There is only one transaction at a time. It's aborted if any of its chunks are edited outside of it, since committing it would
+/// overwrite those edits with the chunks it copied before them.
+pub struct EditTransaction {
+    edited_voxels: SdfChunkHashMap,
+    // Chunks whose preview changed this frame, along with their neighbors.
+    changed_preview_chunk_mins: SmallKeyHashSet<Point3i>,
+    /// The chunks whose preview changed during the last frame, which need to be re-meshed. Unlike `DirtyChunks`, nothing in the
+    /// `SdfVoxelMap` changed.
+    pub dirty_preview_chunk_mins: SmallKeyHashSet<Point3i>,
+}
+
+impl EditTransaction {
+    pub fn new(chunk_shape: Point3i) -> Self {
+        Self {
+            edited_voxels: empty_sdf_chunk_hash_map(chunk_shape),
+            changed_preview_chunk_mins: Default::default(),
+            dirty_preview_chunk_mins: Default::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edited_voxels.storage().is_empty()
+    }
+
+    pub fn chunk_mins(&self) -> impl Iterator<Item = Point3i> + '_ {
+        self.edited_voxels.storage().keys().map(|k| k.minimum)
+    }
+
+    pub fn contains_chunk(&self, chunk_min: Point3i) -> bool {
+        self.edited_voxels
+            .storage()
+            .contains_key(&ChunkKey::new(0, chunk_min))
+    }
+
+    /// Like `EditBuffer::edit_voxels_out_of_place`, but chunks are only copied from the `map` the first time the transaction
+    /// touches them, so edits accumulate until the transaction ends.
+    pub fn edit_voxels(
+        &mut self,
+        map: &SdfVoxelMap,
+        extent: Extent3i,
+        edit_func: impl FnMut(Point3i, (&mut VoxelType, &mut Sd8)),
+    ) {
+        let indexer = map.voxels.indexer;
+        debug_assert!(indexer.chunk_shape().eq(&self.edited_voxels.chunk_shape()));
+
+        for chunk_min in indexer.chunk_mins_for_extent(&extent) {
+            let chunk_key = ChunkKey::new(0, chunk_min);
+            self.edited_voxels
+                .get_mut_chunk_or_insert_with(chunk_key, || {
+                    map.copy_chunk_without_caching(chunk_key)
+                        .unwrap_or_else(|| {
+                            SdfArray::fill(
+                                indexer.extent_for_chunk_with_min(chunk_min),
+                                EMPTY_SDF_VOXEL,
+                            )
+                        })
+                });
+            self.touch_preview(chunk_min);
+        }
+
+        self.edited_voxels
+            .lod_view_mut(0)
+            .for_each_mut(&extent, edit_func);
+    }
+
+    /// Removes all of the edited chunks, leaving the transaction empty.
+    pub fn take_chunks(&mut self) -> Vec<(ChunkKey3, SdfArray)> {
+        let chunk_shape = self.edited_voxels.chunk_shape();
+        let edited_voxels = std::mem::replace(
+            &mut self.edited_voxels,
+            empty_sdf_chunk_hash_map(chunk_shape),
+        );
+
+        edited_voxels.take_storage().into_iter().collect()
+    }
+
+    /// Throws away all of the edited chunks. Their previews will be re-meshed from the `SdfVoxelMap`.
+    pub fn abort(&mut self) {
+        for (chunk_key, _) in self.take_chunks().into_iter() {
+            self.touch_preview(chunk_key.minimum);
+        }
+    }
+
+    /// Overwrites the part of `dst` that overlaps the transaction with the edited voxels.
+    pub fn copy_preview(&self, dst: &mut SdfArray) {
+        let dst_extent = *dst.extent();
+        for chunk_min in self
+            .edited_voxels
+            .indexer
+            .chunk_mins_for_extent(&dst_extent)
+        {
+            if let Some(chunk) = self
+                .edited_voxels
+                .storage()
+                .get(&ChunkKey::new(0, chunk_min))
+            {
+                copy_extent(&chunk.extent().intersection(&dst_extent), chunk, dst);
+            }
+        }
+    }
+
+    /// Moves the chunks whose preview changed this frame into `dirty_preview_chunk_mins`.
+    pub fn publish_preview_changes(&mut self) {
+        self.dirty_preview_chunk_mins = std::mem::take(&mut self.changed_preview_chunk_mins);
+    }
+
+    fn touch_preview(&mut self, chunk_min: Point3i) {
+        // Meshes read the neighboring chunks for padding.
+        let chunk_shape = self.edited_voxels.chunk_shape();
+        let neighborhood = Extent3i::from_min_and_max(
+            chunk_min - chunk_shape,
+            chunk_min + chunk_shape + chunk_shape - PointN([1; 3]),
+        );
+        for neighbor_min in self
+            .edited_voxels
+            .indexer
+            .chunk_mins_for_extent(&neighborhood)
+        {
+            self.changed_preview_chunk_mins.insert(neighbor_min);
+        }
+    }
+}
+
+/// Aborts the transaction if any of its chunks were just edited in the `SdfVoxelMap`, and publishes the chunks whose preview
+/// changed this frame in `EditTransaction::dirty_preview_chunk_mins`. Must run after the edits are merged.
+pub fn edit_transaction_system(
+    mut transaction: ResMut<EditTransaction>,
+    dirty_chunks: Res<DirtyChunks>,
+) {
+    if dirty_chunks
+        .edited_chunk_mins
+        .iter()
+        .any(|chunk_min| transaction.contains_chunk(*chunk_min))
+    {
+        println!("The map changed under the previewed edit, so it was thrown away");
+        transaction.abort();
+    }
+    transaction.publish_preview_changes();
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        ambient_sdf_array, empty_compressible_sdf_chunk_map, DEFAULT_CHUNK_COMPRESSION,
+        DEFAULT_CHUNK_SHAPE,
+    };
+
+    fn solid_voxel() -> (VoxelType, Sd8) {
+        (VoxelType(2), Sd8::NEG_ONE)
+    }
+
+    #[test]
+    fn edits_accumulate_and_abort_leaves_nothing_behind() {
+        let map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION),
+            Default::default(),
+        );
+        let mut transaction = EditTransaction::new(DEFAULT_CHUNK_SHAPE);
+
+        let first = Extent3i::from_min_and_shape(PointN([0; 3]), PointN([2; 3]));
+        let second = Extent3i::from_min_and_shape(PointN([4; 3]), PointN([2; 3]));
+        for extent in [first, second].iter() {
+            transaction.edit_voxels(&map, *extent, |_p, (v_type, v_dist)| {
+                *v_type = solid_voxel().0;
+                *v_dist = solid_voxel().1;
+            });
+        }
+
+        let mut preview = ambient_sdf_array(Extent3i::from_min_and_shape(
+            PointN([-1; 3]),
+            PointN([8; 3]),
+        ));
+        transaction.copy_preview(&mut preview);
+        assert_eq!(preview.get(PointN([1; 3])), solid_voxel());
+        assert_eq!(preview.get(PointN([5; 3])), solid_voxel());
+        assert_eq!(preview.get(PointN([3; 3])), EMPTY_SDF_VOXEL);
+        // None of the transaction's chunks cover this voxel.
+        assert_eq!(preview.get(PointN([-1; 3])), EMPTY_SDF_VOXEL);
+
+        transaction.abort();
+        assert!(transaction.is_empty());
+        transaction.publish_preview_changes();
+        assert!(transaction
+            .dirty_preview_chunk_mins
+            .contains(&PointN([0; 3])));
+        assert!(map.chunk_keys().is_empty());
+    }
+}
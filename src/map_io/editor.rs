@@ -1,5 +1,5 @@
 use crate::{
-    map_io::{EditBuffer, ThreadLocalVoxelCache},
+    map_io::{EditBuffer, EditTransaction, ThreadLocalVoxelCache},
     SdfArray, SdfVoxelMap, VoxelType,
 };
 use bevy::ecs::{prelude::*, system::SystemParam};
@@ -7,11 +7,16 @@ use building_blocks::prelude::*;
 
 /// A `SystemParam` that double-buffers writes to the `SdfVoxelMap` and detects which chunks are changed each frame. On the
 /// subsequent frame, the set of dirty and edited chunk keys will be available in the `DirtyChunks` resource.
+///
+/// Edits can also be made in the `EditTransaction`, which keeps them out of the map until `commit_transaction`.
+///
+/// Every edit is clipped to the world bounds of the `SdfVoxelMap`, if it has any.
 #[derive(SystemParam)]
 pub struct VoxelEditor<'a> {
     pub map: Res<'a, SdfVoxelMap>,
     pub local_cache: Res<'a, ThreadLocalVoxelCache>,
     edit_buffer: ResMut<'a, EditBuffer>,
+    transaction: ResMut<'a, EditTransaction>,
 }
 
 impl<'a> VoxelEditor<'a> {
@@ -40,17 +45,58 @@ impl<'a> VoxelEditor<'a> {
         extent: Extent3i,
         edit_func: impl FnMut(Point3i, (&mut VoxelType, &mut Sd8)),
     ) {
-        let tls = self.local_cache.get();
-        let reader = self.map.reader(&tls);
+        let extent = self.map.clip_to_bounds(extent);
+        if extent.is_empty() {
+            return;
+        }
         self.edit_buffer
-            .edit_voxels_out_of_place(&reader, extent, edit_func, touch_neighbors);
+            .edit_voxels_out_of_place(&*self.map, extent, edit_func, touch_neighbors);
     }
 
     pub fn insert_chunk_and_touch_neighbors(&mut self, chunk_key: Point3i, chunk: SdfArray) {
-        self.edit_buffer.insert_chunk(true, chunk_key, chunk);
+        self._insert_chunk(true, chunk_key, chunk);
     }
 
     pub fn insert_chunk(&mut self, chunk_key: Point3i, chunk: SdfArray) {
-        self.edit_buffer.insert_chunk(false, chunk_key, chunk);
+        self._insert_chunk(false, chunk_key, chunk);
+    }
+
+    fn _insert_chunk(&mut self, touch_neighbors: bool, chunk_key: Point3i, mut chunk: SdfArray) {
+        if self.map.crop_to_bounds(&mut chunk) {
+            self.edit_buffer
+                .insert_chunk(touch_neighbors, chunk_key, chunk);
+        }
+    }
+
+    /// Run `edit_func` on all voxels in `extent` within the transaction. The edit is only previewed until the transaction is
+    /// committed.
+    pub fn edit_extent_in_transaction(
+        &mut self,
+        extent: Extent3i,
+        edit_func: impl FnMut(Point3i, (&mut VoxelType, &mut Sd8)),
+    ) {
+        let extent = self.map.clip_to_bounds(extent);
+        if extent.is_empty() {
+            return;
+        }
+        self.transaction.edit_voxels(&*self.map, extent, edit_func);
+    }
+
+    /// Merges every chunk of the transaction at the end of this frame, all at once. The chunks and their neighbors will be
+    /// marked as dirty. Returns the minimums of the committed chunks.
+    pub fn commit_transaction(&mut self) -> Vec<Point3i> {
+        let chunks = self.transaction.take_chunks();
+        let chunk_mins = chunks.iter().map(|(k, _)| k.minimum).collect();
+        for (chunk_key, chunk) in chunks.into_iter() {
+            self.edit_buffer
+                .insert_chunk(true, chunk_key.minimum, chunk);
+        }
+
+        chunk_mins
+    }
+
+    /// Throws away every edit in the transaction. Neither the `SdfVoxelMap` nor the `DirtyChunks` will change.
+    pub fn abort_transaction(&mut self) {
+        self.transaction.abort();
     }
 }
@@ -1,6 +1,8 @@
+use super::{ChunkChange, ChunkChangeLog, ChunkRemoved};
+
 use crate::SdfVoxelMap;
 
-use bevy::ecs::prelude::*;
+use bevy::{app::prelude::*, ecs::prelude::*};
 use building_blocks::storage::ChunkKey3;
 
 /// The resource that tracks which chunks recently became empty and should be removed. This enables multiple methods of
@@ -18,11 +20,16 @@ impl EmptyChunks {
     }
 }
 
+/// Removes the chunks marked as empty, announcing each one with a `ChunkRemoved` event and in the `ChunkChangeLog`.
 pub fn empty_chunk_remover_system(
     mut empty_chunks: ResMut<EmptyChunks>,
     mut voxel_map: ResMut<SdfVoxelMap>,
+    mut removed_events: EventWriter<ChunkRemoved>,
+    mut change_log: ResMut<ChunkChangeLog>,
 ) {
     for chunk_key in empty_chunks.chunks_to_remove.drain(..) {
-        voxel_map.voxels.storage_mut().remove(chunk_key);
+        voxel_map.remove_chunk(chunk_key);
+        change_log.push(ChunkChange::Removed(chunk_key));
+        removed_events.send(ChunkRemoved(chunk_key));
     }
 }
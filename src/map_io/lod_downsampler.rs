@@ -0,0 +1,231 @@
+use super::DirtyChunks;
+
+use crate::{
+    ambient_sdf_array, empty_sdf_chunk_hash_map, sdf_array_is_ambient, SdfArray, SdfChunkHashMap,
+    SdfVoxelMap, VoxelType,
+};
+
+use bevy::ecs::prelude::*;
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, SmallKeyHashSet},
+};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct LodConfig {
+    /// The number of levels of detail, including LOD 0. Each level halves the resolution of the one below it.
+    pub num_lods: u8,
+    /// A chunk is rendered with its children at the next finer LOD when the camera is within this many chunk widths of it.
+    pub clip_radius: f32,
+}
+
+impl Default for LodConfig {
+    fn default() -> Self {
+        Self {
+            num_lods: 4,
+            clip_radius: 2.0,
+        }
+    }
+}
+
+/// The downsampled chunks for LODs 1 and up. LOD 0 lives in the `SdfVoxelMap`.
+///
+/// At LOD `n`, each voxel covers `2^n` LOD 0 voxels along each axis, and chunk keys are in LOD `n` voxel coordinates. So a chunk at
+/// LOD `n + 1` covers the 8 chunks at LOD `n` whose minimums are `2 * minimum + chunk_shape * {0, 1}^3`.
+///
+/// These aren't saved with the map. Loading a map inserts its chunks through the `EditBuffer`, so they're downsampled like any
+/// other edit as they're loaded.
+pub struct LodChunks {
+    pub chunks: SdfChunkHashMap,
+    /// The chunks that were downsampled at the end of the last frame, along with their neighbors, all of which need to be
+    /// re-meshed.
+    pub dirty_chunk_keys: SmallKeyHashSet<ChunkKey3>,
+}
+
+impl LodChunks {
+    pub fn new(chunk_shape: Point3i) -> Self {
+        Self {
+            chunks: empty_sdf_chunk_hash_map(chunk_shape),
+            dirty_chunk_keys: Default::default(),
+        }
+    }
+
+    pub fn chunk_mins_at_lod(&self, lod: u8) -> impl Iterator<Item = Point3i> + '_ {
+        self.chunks
+            .storage()
+            .keys()
+            .filter(move |k| k.lod == lod)
+            .map(|k| k.minimum)
+    }
+}
+
+/// Downsamples every edited chunk up through all of the levels of detail.
+pub fn lod_downsampler_system(
+    lod_config: Res<LodConfig>,
+    voxel_map: Res<SdfVoxelMap>,
+    dirty_chunks: Res<DirtyChunks>,
+    mut lod_chunks: ResMut<LodChunks>,
+) {
+    lod_chunks.dirty_chunk_keys.clear();
+
+    let indexer = voxel_map.voxels.indexer;
+    let chunk_shape = indexer.chunk_shape();
+    let mut src_chunk_mins: SmallKeyHashSet<Point3i> =
+        dirty_chunks.edited_chunk_mins.iter().cloned().collect();
+    for src_lod in 0..lod_config.num_lods.saturating_sub(1) {
+        let dst_lod = src_lod + 1;
+
+        let mut dst_chunk_mins = SmallKeyHashSet::default();
+        for &src_min in src_chunk_mins.iter() {
+            let src_key = ChunkKey::new(src_lod, src_min);
+            let src_chunk = if src_lod == 0 {
+                voxel_map.copy_chunk_without_caching(src_key)
+            } else {
+                lod_chunks.chunks.storage().get(&src_key).cloned()
+            }
+            // Removed chunks still need to be cleared out of the next level.
+            .unwrap_or_else(|| ambient_sdf_array(indexer.extent_for_chunk_with_min(src_min)));
+
+            let dst_min = chunk_min_containing(half_point(src_min), chunk_shape);
+            let dst_chunk = lod_chunks
+                .chunks
+                .get_mut_chunk_or_insert_with(ChunkKey::new(dst_lod, dst_min), || {
+                    ambient_sdf_array(indexer.extent_for_chunk_with_min(dst_min))
+                });
+            downsample_sdf_chunk(&src_chunk, dst_chunk);
+            dst_chunk_mins.insert(dst_min);
+        }
+
+        for &dst_min in dst_chunk_mins.iter() {
+            let dst_key = ChunkKey::new(dst_lod, dst_min);
+            if lod_chunks
+                .chunks
+                .storage()
+                .get(&dst_key)
+                .map_or(false, sdf_array_is_ambient)
+            {
+                lod_chunks.chunks.storage_mut().remove(&dst_key);
+            }
+
+            // Meshes read the neighboring chunks for padding.
+            let neighborhood = Extent3i::from_min_and_max(
+                dst_min - chunk_shape,
+                dst_min + chunk_shape + chunk_shape - PointN([1; 3]),
+            );
+            for neighbor_min in indexer.chunk_mins_for_extent(&neighborhood) {
+                lod_chunks
+                    .dirty_chunk_keys
+                    .insert(ChunkKey::new(dst_lod, neighbor_min));
+            }
+        }
+
+        src_chunk_mins = dst_chunk_mins;
+    }
+}
+
+/// Downsamples `src` into the part of `dst` that it covers, where `dst` is a chunk at the next LOD.
+///
+/// Each voxel of `dst` gets the mean distance of the 2x2x2 voxels of `src` that it covers and the type of the most solid of them.
+/// Surface nets only depends on the ratios of distances along each edge, so the distances aren't rescaled, and ambient voxels stay
+/// ambient.
+pub fn downsample_sdf_chunk(src: &SdfArray, dst: &mut SdfArray) {
+    let src_extent = *src.extent();
+    let dst_extent =
+        Extent3i::from_min_and_shape(half_point(src_extent.minimum), half_point(src_extent.shape))
+            .intersection(dst.extent());
+
+    dst.for_each_mut(
+        &dst_extent,
+        |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+            let src_min = PointN([2 * p.x(), 2 * p.y(), 2 * p.z()]);
+            let mut dist_sum = 0;
+            let mut most_solid = src.get(src_min);
+            for &offset in Point3i::CUBE_CORNER_OFFSETS.iter() {
+                let (src_type, src_dist) = src.get(src_min + offset);
+                dist_sum += src_dist.0 as i32;
+                if src_dist.0 < (most_solid.1).0 {
+                    most_solid = (src_type, src_dist);
+                }
+            }
+            *v_type = most_solid.0;
+            *v_dist = Sd8((dist_sum / 8) as i8);
+        },
+    );
+}
+
+/// The minimum of the chunk at `levels` LODs above the chunk at `chunk_min` that covers it.
+pub fn ancestor_chunk_min(chunk_min: Point3i, levels: u8, chunk_shape: Point3i) -> Point3i {
+    let scaled_min = PointN([
+        chunk_min.x() >> levels,
+        chunk_min.y() >> levels,
+        chunk_min.z() >> levels,
+    ]);
+
+    chunk_min_containing(scaled_min, chunk_shape)
+}
+
+fn half_point(p: Point3i) -> Point3i {
+    PointN([p.x() >> 1, p.y() >> 1, p.z() >> 1])
+}
+
+fn chunk_min_containing(p: Point3i, chunk_shape: Point3i) -> Point3i {
+    PointN([
+        p.x().div_euclid(chunk_shape.x()) * chunk_shape.x(),
+        p.y().div_euclid(chunk_shape.y()) * chunk_shape.y(),
+        p.z().div_euclid(chunk_shape.z()) * chunk_shape.z(),
+    ])
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::EMPTY_SDF_VOXEL;
+
+    #[test]
+    fn downsampling_fills_the_covered_octant() {
+        let shape = PointN([4; 3]);
+        let src_extent = Extent3i::from_min_and_shape(PointN([4, -4, 0]), shape);
+        let mut src = ambient_sdf_array(src_extent);
+        // A solid slab in the bottom half of the source chunk.
+        src.for_each_mut(
+            &src_extent,
+            |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+                if p.y() < -2 {
+                    *v_type = VoxelType(3);
+                    *v_dist = Sd8(-100);
+                }
+            },
+        );
+
+        let dst_min = chunk_min_containing(half_point(src_extent.minimum), shape);
+        assert_eq!(dst_min, PointN([0, -4, 0]));
+        let dst_extent = Extent3i::from_min_and_shape(dst_min, shape);
+        let mut dst = ambient_sdf_array(dst_extent);
+        downsample_sdf_chunk(&src, &mut dst);
+
+        assert_eq!(dst.get(PointN([2, -2, 0])), (VoxelType(3), Sd8(-100)));
+        assert_eq!(dst.get(PointN([3, -1, 1])), EMPTY_SDF_VOXEL);
+        // Outside of the octant covered by the source chunk.
+        assert_eq!(dst.get(PointN([0, -2, 0])), EMPTY_SDF_VOXEL);
+    }
+
+    #[test]
+    fn ambient_chunks_stay_ambient() {
+        let shape = PointN([4; 3]);
+        let src = ambient_sdf_array(Extent3i::from_min_and_shape(PointN([0; 3]), shape));
+        let mut dst = ambient_sdf_array(Extent3i::from_min_and_shape(PointN([0; 3]), shape));
+        downsample_sdf_chunk(&src, &mut dst);
+
+        assert!(sdf_array_is_ambient(&dst));
+    }
+}
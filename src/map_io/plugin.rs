@@ -1,52 +1,77 @@
 use super::{
     chunk_cache_flusher::chunk_cache_flusher_system,
     chunk_compressor::chunk_compressor_system,
+    chunk_swap::chunk_swapper_system,
     edit_buffer::{double_buffering_system, DirtyChunks},
+    edit_transaction::edit_transaction_system,
     empty_chunk_remover::empty_chunk_remover_system,
-    EditBuffer, EmptyChunks, ThreadLocalVoxelCache,
+    lod_downsampler::lod_downsampler_system,
+    ChunkChangeLog, ChunkRemoved, ChunksEdited, EditBuffer, EditTransaction, EmptyChunks,
+    LodChunks, LodConfig, ThreadLocalVoxelCache,
 };
 
+use crate::SdfVoxelMap;
+
 use bevy::{app::prelude::*, ecs::prelude::*};
-use building_blocks::core::Point3i;
 
 pub use super::chunk_compressor::ChunkCacheConfig;
 
 /// A bevy plugin that provides dynamic read caching and compression for the `SdfVoxelMap` resource.
 ///
-/// This plugin expects the `SdfVoxelMap` resource to already exist before systems are dispatched.
+/// This plugin expects the `SdfVoxelMap` resource to be inserted with `MapIoPlugin::insert_voxel_map` before systems are
+/// dispatched. The map's chunk shape is up to the map, and the other chunked resources follow it.
 ///
 /// This plugin uses thread-local caches for voxel chunks that are decompressed during access. At the end of the frame, these
 /// caches are flushed back into the `SdfVoxelMap`'s global cache.
 ///
 /// If the size of the global chunk cache grows beyond a limit, one of the plugin systems will start compressing the
-/// least-recently-used chunks to save space.
+/// least-recently-used chunks to save space. Similarly, if the compressed chunks grow beyond a byte budget, the oldest of them
+/// are paged out to a swap file. Swapped chunks are faulted back in when they are edited or marked dirty.
 ///
 /// In order to get maximum read parallelism from the voxel map, use the `VoxelEditor`, a `SystemParam` that writes your edits
 /// out of place. The edits will get merged into the `SdfVoxelMap` at the end of the same frame. The edited chunks will also be
-/// marked as "dirty" in the `DirtyChunks` resource, which makes it easier to do post-processing when chunks change.
+/// marked as "dirty" in the `DirtyChunks` resource, which makes it easier to do post-processing when chunks change. Since
+/// `DirtyChunks` only lasts for one frame, changes are also sent as `ChunksEdited` and `ChunkRemoved` events, and they're kept
+/// in the `ChunkChangeLog` for subscribers that don't run every frame. Edits that need to be previewed before they're committed
+/// or aborted go into the `EditTransaction` instead.
+///
+/// Edited chunks are also downsampled into the `LodChunks` resource, up to `LodConfig::num_lods`, so that far away parts of the
+/// map can be rendered at a lower resolution.
 ///
 /// **WARNING**: Cached reads will always be flushed before double-buffered writes. This means if you try to write directly into
 /// the `SdfVoxelMap`, you risk having your changes overwritten by the flush.
 pub struct MapIoPlugin {
-    pub chunk_shape: Point3i,
     pub cache_config: ChunkCacheConfig,
+    pub lod_config: LodConfig,
 }
 
 impl MapIoPlugin {
-    pub fn new(chunk_shape: Point3i, cache_config: ChunkCacheConfig) -> Self {
+    pub fn new(cache_config: ChunkCacheConfig, lod_config: LodConfig) -> Self {
         Self {
-            chunk_shape,
             cache_config,
+            lod_config,
         }
     }
+
+    /// Inserts `voxel_map` along with the resources that need to have the same chunk shape.
+    pub fn insert_voxel_map(commands: &mut Commands, voxel_map: SdfVoxelMap) {
+        let chunk_shape = voxel_map.voxels.chunk_shape();
+        commands.insert_resource(EditBuffer::new(chunk_shape));
+        commands.insert_resource(EditTransaction::new(chunk_shape));
+        commands.insert_resource(LodChunks::new(chunk_shape));
+        commands.insert_resource(voxel_map);
+    }
 }
 
 impl Plugin for MapIoPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.insert_resource(self.cache_config)
-            .insert_resource(EditBuffer::new(self.chunk_shape))
+            .insert_resource(self.lod_config)
             .insert_resource(DirtyChunks::default())
             .insert_resource(EmptyChunks::default())
+            .insert_resource(ChunkChangeLog::default())
+            .add_event::<ChunksEdited>()
+            .add_event::<ChunkRemoved>()
             // Each thread gets its own local chunk cache. The local caches are flushed into the global cache in the
             // chunk_cache_flusher_system.
             .insert_resource(ThreadLocalVoxelCache::new())
@@ -64,6 +89,21 @@ impl Plugin for MapIoPlugin {
                 CoreStage::Last,
                 double_buffering_system.system().label("merge_edits"),
             )
-            .add_system_to_stage(CoreStage::Last, chunk_compressor_system.system());
+            .add_system_to_stage(
+                CoreStage::Last,
+                lod_downsampler_system.system().after("merge_edits"),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                edit_transaction_system.system().after("merge_edits"),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                chunk_compressor_system.system().label("compress_chunks"),
+            )
+            .add_system_to_stage(
+                CoreStage::Last,
+                chunk_swapper_system.system().after("compress_chunks"),
+            );
     }
 }
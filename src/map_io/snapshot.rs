@@ -0,0 +1,134 @@
+use crate::{SdfArray, SdfVoxelPalette};
+
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, SmallKeyHashMap, SmallKeyHashSet},
+};
+
+/// Everything about a snapshot of an `SdfVoxelMap` except for its chunks, which are read out of the map incrementally with
+/// `SdfVoxelMap::take_snapshot_chunks`.
+#[derive(Clone, Debug)]
+pub struct MapSnapshotHeader {
+    pub chunk_shape: Point3i,
+    pub palette: SdfVoxelPalette,
+    pub bounds: Option<Extent3i>,
+    pub num_chunks: usize,
+}
+
+/// The chunk set of an `SdfVoxelMap` at the moment the snapshot was taken. Taking one only copies the chunk keys. Chunks are
+/// copy-on-write: until a chunk has been taken out of the snapshot, the map preserves its old version before overwriting or
+/// removing it.
+pub(crate) struct MapSnapshot {
+    // Sorted so the last key is taken first, which puts the chunks in the same order as `write_map`.
+    order: Vec<ChunkKey3>,
+    pending: SmallKeyHashSet<ChunkKey3>,
+    preserved: SmallKeyHashMap<ChunkKey3, SdfArray>,
+}
+
+impl MapSnapshot {
+    pub fn new(mut chunk_keys: Vec<ChunkKey3>) -> Self {
+        chunk_keys.sort_by_key(|k| std::cmp::Reverse((k.lod, k.minimum.0)));
+        let pending = chunk_keys.iter().cloned().collect();
+
+        Self {
+            order: chunk_keys,
+            pending,
+            preserved: Default::default(),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// True if the chunk at `chunk_key` is part of the snapshot, but its old version hasn't been preserved or taken yet.
+    pub fn needs_preserving(&self, chunk_key: ChunkKey3) -> bool {
+        self.pending.contains(&chunk_key) && !self.preserved.contains_key(&chunk_key)
+    }
+
+    pub fn preserve(&mut self, chunk_key: ChunkKey3, chunk: SdfArray) {
+        self.preserved.insert(chunk_key, chunk);
+    }
+
+    /// Takes the next `max_chunks` keys of the snapshot, along with the old versions of any chunks that were edited since the
+    /// snapshot was taken.
+    pub fn take_next(&mut self, max_chunks: usize) -> Vec<(ChunkKey3, Option<SdfArray>)> {
+        let num_taken = max_chunks.min(self.order.len());
+        let first_taken = self.order.len() - num_taken;
+        let taken: Vec<_> = self.order.drain(first_taken..).rev().collect();
+
+        taken
+            .into_iter()
+            .map(|chunk_key| {
+                self.pending.remove(&chunk_key);
+
+                (chunk_key, self.preserved.remove(&chunk_key))
+            })
+            .collect()
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        empty_compressible_sdf_chunk_map, map_io::EditBuffer, SdfVoxelMap, VoxelType,
+        DEFAULT_CHUNK_COMPRESSION, DEFAULT_CHUNK_SHAPE, EMPTY_SDF_VOXEL,
+    };
+
+    use building_blocks::prelude::*;
+
+    const SOLID: (VoxelType, Sd8) = (VoxelType(1), Sd8::NEG_ONE);
+
+    fn fill(map: &mut SdfVoxelMap, min: Point3i, value: (VoxelType, Sd8)) {
+        let mut buffer = EditBuffer::new(DEFAULT_CHUNK_SHAPE);
+        buffer.edit_voxels_out_of_place(
+            map,
+            Extent3i::from_min_and_shape(min, PointN([1; 3])),
+            |_p, (v_type, v_dist)| {
+                *v_type = value.0;
+                *v_dist = value.1;
+            },
+            false,
+        );
+        buffer.merge_edits(map);
+    }
+
+    #[test]
+    fn snapshot_keeps_the_chunks_from_when_it_was_taken() {
+        let mut map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION),
+            Default::default(),
+        );
+        let edited_min = PointN([0; 3]);
+        let removed_min = PointN([16, 0, 0]);
+        fill(&mut map, edited_min, SOLID);
+        fill(&mut map, removed_min, SOLID);
+
+        let header = map.begin_snapshot().unwrap();
+        assert_eq!(header.num_chunks, 2);
+        assert!(map.begin_snapshot().is_none());
+
+        // Neither of these should be visible in the snapshot, and the new chunk isn't part of it.
+        fill(&mut map, edited_min, EMPTY_SDF_VOXEL);
+        map.remove_chunk(ChunkKey::new(0, removed_min));
+        fill(&mut map, PointN([32, 0, 0]), SOLID);
+
+        let mut chunks = map.take_snapshot_chunks(1);
+        chunks.extend(map.take_snapshot_chunks(10));
+        assert!(map.take_snapshot_chunks(10).is_empty());
+        assert!(map.begin_snapshot().is_some());
+
+        let mins: Vec<_> = chunks.iter().map(|(k, _)| k.minimum).collect();
+        assert_eq!(mins, vec![edited_min, removed_min]);
+        for (_, chunk) in chunks.iter() {
+            assert_eq!(chunk.get(chunk.extent().minimum), SOLID);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{SdfArray, VoxelType};
+
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, SmallKeyHashMap},
+};
+
+/// Chunks whose voxels all have the same value, e.g. solid rock deep underground or open air next to a surface. Each one is
+/// stored as that single value instead of a full array, and it's expanded back into an array whenever it's read or edited.
+///
+/// Chunks are moved here when their edits are merged into the `SdfVoxelMap`, and moved back into the map's array storage as soon
+/// as an edit makes them non-uniform.
+#[derive(Default)]
+pub struct UniformChunks {
+    values: SmallKeyHashMap<ChunkKey3, (VoxelType, Sd8)>,
+}
+
+impl UniformChunks {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn contains(&self, chunk_key: ChunkKey3) -> bool {
+        self.values.contains_key(&chunk_key)
+    }
+
+    pub fn chunk_keys(&self) -> impl Iterator<Item = &ChunkKey3> {
+        self.values.keys()
+    }
+
+    /// The value of every voxel in the chunk at `chunk_key`.
+    pub fn get(&self, chunk_key: ChunkKey3) -> Option<(VoxelType, Sd8)> {
+        self.values.get(&chunk_key).cloned()
+    }
+
+    /// Expands the chunk at `chunk_key` into an array covering `extent`.
+    pub fn read(&self, chunk_key: ChunkKey3, extent: Extent3i) -> Option<SdfArray> {
+        self.get(chunk_key)
+            .map(|value| SdfArray::fill(extent, value))
+    }
+
+    pub fn insert(&mut self, chunk_key: ChunkKey3, value: (VoxelType, Sd8)) {
+        self.values.insert(chunk_key, value);
+    }
+
+    pub fn remove(&mut self, chunk_key: ChunkKey3) {
+        self.values.remove(&chunk_key);
+    }
+}
+
+/// The value of every voxel in `chunk`, if they're all the same.
+pub fn uniform_chunk_value(chunk: &SdfArray) -> Option<(VoxelType, Sd8)> {
+    let first = chunk.get(chunk.extent().minimum);
+    let mut uniform = true;
+    chunk.for_each(chunk.extent(), |_p: Point3i, voxel: (VoxelType, Sd8)| {
+        uniform &= voxel == first;
+    });
+
+    if uniform {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        ambient_sdf_array, empty_compressible_sdf_chunk_map, map_io::EditBuffer, SdfVoxelMap,
+        ThreadLocalVoxelCache, DEFAULT_CHUNK_COMPRESSION, DEFAULT_CHUNK_SHAPE, EMPTY_SDF_VOXEL,
+    };
+
+    const BEDROCK: (VoxelType, Sd8) = (VoxelType(1), Sd8(std::i8::MIN));
+
+    fn fill(map: &SdfVoxelMap, buffer: &mut EditBuffer, extent: Extent3i, value: (VoxelType, Sd8)) {
+        buffer.edit_voxels_out_of_place(
+            map,
+            extent,
+            |_p, (v_type, v_dist)| {
+                *v_type = value.0;
+                *v_dist = value.1;
+            },
+            false,
+        );
+    }
+
+    #[test]
+    fn uniform_chunks_are_stored_compactly_and_expanded_on_read_and_write() {
+        let mut map = SdfVoxelMap::new(
+            empty_compressible_sdf_chunk_map(DEFAULT_CHUNK_SHAPE, DEFAULT_CHUNK_COMPRESSION),
+            Default::default(),
+        );
+        let uniform_key = ChunkKey::new(0, PointN([0; 3]));
+        let mixed_key = ChunkKey::new(0, PointN([16, 0, 0]));
+
+        let mut buffer = EditBuffer::new(DEFAULT_CHUNK_SHAPE);
+        fill(
+            &map,
+            &mut buffer,
+            Extent3i::from_min_and_shape(PointN([0; 3]), DEFAULT_CHUNK_SHAPE),
+            BEDROCK,
+        );
+        fill(
+            &map,
+            &mut buffer,
+            Extent3i::from_min_and_shape(PointN([16, 0, 0]), PointN([1; 3])),
+            BEDROCK,
+        );
+        buffer.merge_edits(&mut map);
+
+        assert!(map.uniform.contains(uniform_key));
+        assert!(map
+            .voxels
+            .storage()
+            .copy_without_caching(uniform_key)
+            .is_none());
+        assert!(!map.uniform.contains(mixed_key));
+        assert_eq!(map.chunk_keys().len(), 2);
+
+        let local_caches = ThreadLocalVoxelCache::new();
+        let cache_tls = local_caches.get();
+        let reader = map.reader(&cache_tls);
+        assert_eq!(
+            reader.get_chunk(uniform_key).unwrap().get(PointN([3; 3])),
+            BEDROCK
+        );
+        let across_extent = Extent3i::from_min_and_shape(PointN([15, 0, 0]), PointN([3, 1, 1]));
+        let mut across_chunks = ambient_sdf_array(across_extent);
+        reader.copy_extent_into(&across_extent, &mut across_chunks);
+        assert_eq!(across_chunks.get(PointN([15, 0, 0])), BEDROCK);
+        assert_eq!(across_chunks.get(PointN([16, 0, 0])), BEDROCK);
+        assert_eq!(across_chunks.get(PointN([17, 0, 0])), EMPTY_SDF_VOXEL);
+
+        // Digging into the uniform chunk turns it back into an array.
+        let mut buffer = EditBuffer::new(DEFAULT_CHUNK_SHAPE);
+        fill(
+            &map,
+            &mut buffer,
+            Extent3i::from_min_and_shape(PointN([1; 3]), PointN([1; 3])),
+            EMPTY_SDF_VOXEL,
+        );
+        buffer.merge_edits(&mut map);
+
+        assert!(!map.uniform.contains(uniform_key));
+        let chunk = map.copy_chunk_without_caching(uniform_key).unwrap();
+        assert_eq!(chunk.get(PointN([1; 3])), EMPTY_SDF_VOXEL);
+        assert_eq!(chunk.get(PointN([3; 3])), BEDROCK);
+    }
+}
@@ -0,0 +1,5 @@
+mod gltf;
+mod plugin;
+
+pub use gltf::{material_weights_from_counts, write_gltf, ExportMesh, MATERIAL_WEIGHTS_ATTRIBUTE};
+pub use plugin::{export_meshes, MeshExportEvents, MeshExportPlugin};
@@ -0,0 +1,341 @@
+use serde_json::{json, Value};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+/// The name of the custom vertex attribute that holds material weights. Each vertex has 4 normalized `u8` weights, one per
+/// material layer, summing to roughly 1.
+pub const MATERIAL_WEIGHTS_ATTRIBUTE: &str = "_MATERIAL_WEIGHTS";
+
+/// Triangle mesh geometry in world coordinates, ready to be written to glTF.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportMesh {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub material_weights: Vec<[u8; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl ExportMesh {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Appends the geometry of `other` to `self`.
+    pub fn append(&mut self, other: &ExportMesh) {
+        let index_offset = self.positions.len() as u32;
+        self.positions.extend_from_slice(&other.positions);
+        self.normals.extend_from_slice(&other.normals);
+        self.material_weights
+            .extend_from_slice(&other.material_weights);
+        self.indices
+            .extend(other.indices.iter().map(|i| i + index_offset));
+    }
+}
+
+/// Converts the per-vertex counts of adjacent materials (as produced for rendering) into normalized weights.
+pub fn material_weights_from_counts(counts: [u8; 4]) -> [u8; 4] {
+    let total: u32 = counts.iter().map(|&c| c as u32).sum();
+    if total == 0 {
+        return [0; 4];
+    }
+    let mut weights = [0; 4];
+    for (w, &c) in weights.iter_mut().zip(counts.iter()) {
+        *w = ((c as u32 * 255 + total / 2) / total) as u8;
+    }
+
+    weights
+}
+
+/// glTF requires unit-length normals, but surface nets gives us raw gradients. Degenerate normals point up.
+fn unit_normal([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    if length > f32::EPSILON {
+        [x / length, y / length, z / length]
+    } else {
+        [0.0, 1.0, 0.0]
+    }
+}
+
+/// Writes `meshes` to `path`, with one node per mesh. A `.glb` extension writes a single binary file. Any other extension writes
+/// JSON, with the geometry in a `.bin` file next to it.
+pub fn write_gltf(path: impl AsRef<Path>, meshes: &[ExportMesh]) -> io::Result<()> {
+    let path = path.as_ref();
+    let is_binary = path
+        .extension()
+        .map_or(false, |e| e.eq_ignore_ascii_case("glb"));
+
+    let (buffer, mut document) = build_document(meshes);
+
+    if is_binary {
+        document["buffers"] = json!([{ "byteLength": buffer.len() }]);
+        let mut writer = BufWriter::new(File::create(path)?);
+        write_glb(&mut writer, &document, &buffer)?;
+        writer.flush()
+    } else {
+        let bin_path = path.with_extension("bin");
+        let bin_name = bin_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file name"))?;
+        document["buffers"] = json!([{ "byteLength": buffer.len(), "uri": bin_name }]);
+        std::fs::write(&bin_path, &buffer)?;
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &document)?;
+        writer.flush()
+    }
+}
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const UNSIGNED_BYTE: u32 = 5121;
+const UNSIGNED_INT: u32 = 5125;
+const FLOAT: u32 = 5126;
+const TRIANGLES: u32 = 4;
+
+/// Returns the binary buffer and the JSON document, minus the "buffers" array.
+fn build_document(meshes: &[ExportMesh]) -> (Vec<u8>, Value) {
+    let mut buffer = Vec::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut gltf_meshes = Vec::new();
+    let mut nodes = Vec::new();
+
+    // Every component we write is 4-byte aligned, so the views never need padding between them.
+    let mut add_view = |bytes: &[u8], target: u32, buffer: &mut Vec<u8>| {
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": buffer.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        buffer.extend_from_slice(bytes);
+
+        buffer_views.len() - 1
+    };
+
+    for mesh in meshes.iter().filter(|m| !m.is_empty()) {
+        assert_eq!(mesh.positions.len(), mesh.normals.len());
+        assert_eq!(mesh.positions.len(), mesh.material_weights.len());
+
+        let count = mesh.positions.len();
+        let (min, max) = bounds(&mesh.positions);
+
+        let positions = add_view(&f32x3_bytes(&mesh.positions), ARRAY_BUFFER, &mut buffer);
+        accessors.push(json!({
+            "bufferView": positions,
+            "componentType": FLOAT,
+            "count": count,
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        let positions = accessors.len() - 1;
+
+        let normals: Vec<[f32; 3]> = mesh.normals.iter().map(|&n| unit_normal(n)).collect();
+        let normals = add_view(&f32x3_bytes(&normals), ARRAY_BUFFER, &mut buffer);
+        accessors.push(json!({
+            "bufferView": normals,
+            "componentType": FLOAT,
+            "count": count,
+            "type": "VEC3",
+        }));
+        let normals = accessors.len() - 1;
+
+        let weight_bytes: Vec<u8> = mesh.material_weights.iter().flatten().cloned().collect();
+        let weights = add_view(&weight_bytes, ARRAY_BUFFER, &mut buffer);
+        accessors.push(json!({
+            "bufferView": weights,
+            "componentType": UNSIGNED_BYTE,
+            "normalized": true,
+            "count": count,
+            "type": "VEC4",
+        }));
+        let weights = accessors.len() - 1;
+
+        let index_bytes: Vec<u8> = mesh.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let indices = add_view(&index_bytes, ELEMENT_ARRAY_BUFFER, &mut buffer);
+        accessors.push(json!({
+            "bufferView": indices,
+            "componentType": UNSIGNED_INT,
+            "count": mesh.indices.len(),
+            "type": "SCALAR",
+        }));
+        let indices = accessors.len() - 1;
+
+        gltf_meshes.push(json!({
+            "name": mesh.name,
+            "primitives": [{
+                "attributes": {
+                    "POSITION": positions,
+                    "NORMAL": normals,
+                    MATERIAL_WEIGHTS_ATTRIBUTE: weights,
+                },
+                "indices": indices,
+                "mode": TRIANGLES,
+            }],
+        }));
+        nodes.push(json!({
+            "name": mesh.name,
+            "mesh": gltf_meshes.len() - 1,
+        }));
+    }
+
+    let node_indices: Vec<usize> = (0..nodes.len()).collect();
+    let document = json!({
+        "asset": {
+            "version": "2.0",
+            "generator": "building-blocks-editor",
+        },
+        "scene": 0,
+        "scenes": [{ "nodes": node_indices }],
+        "nodes": nodes,
+        "meshes": gltf_meshes,
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+    });
+
+    (buffer, document)
+}
+
+fn write_glb(writer: &mut impl Write, document: &Value, buffer: &[u8]) -> io::Result<()> {
+    let mut json_bytes = serde_json::to_vec(document)?;
+    // Chunks must be 4-byte aligned. JSON is padded with spaces and binary with zeros.
+    while json_bytes.len() % 4 != 0 {
+        json_bytes.push(b' ');
+    }
+    let bin_padding = (4 - buffer.len() % 4) % 4;
+    let bin_len = buffer.len() + bin_padding;
+
+    let total_len = 12 + 8 + json_bytes.len() + 8 + bin_len;
+
+    writer.write_all(b"glTF")?;
+    writer.write_all(&2u32.to_le_bytes())?;
+    writer.write_all(&(total_len as u32).to_le_bytes())?;
+
+    writer.write_all(&(json_bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(b"JSON")?;
+    writer.write_all(&json_bytes)?;
+
+    writer.write_all(&(bin_len as u32).to_le_bytes())?;
+    writer.write_all(b"BIN\0")?;
+    writer.write_all(buffer)?;
+    writer.write_all(&[0; 3][..bin_padding])?;
+
+    Ok(())
+}
+
+fn f32x3_bytes(values: &[[f32; 3]]) -> Vec<u8> {
+    values
+        .iter()
+        .flatten()
+        .flat_map(|x| x.to_le_bytes())
+        .collect()
+}
+
+fn bounds(positions: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in positions.iter() {
+        for (i, &x) in p.iter().enumerate() {
+            min[i] = min[i].min(x);
+            max[i] = max[i].max(x);
+        }
+    }
+
+    (min, max)
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(name: &str) -> ExportMesh {
+        ExportMesh {
+            name: name.to_string(),
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            normals: vec![[0.0, 0.0, 1.0]; 3],
+            material_weights: vec![[255, 0, 0, 0]; 3],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    #[test]
+    fn glb_chunks_are_aligned_and_sized() {
+        let (buffer, mut document) = build_document(&[triangle("a")]);
+        document["buffers"] = json!([{ "byteLength": buffer.len() }]);
+        let mut bytes = Vec::new();
+        write_glb(&mut bytes, &document, &buffer).unwrap();
+
+        assert_eq!(&bytes[0..4], b"glTF");
+        let total_len = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+        assert_eq!(total_len, bytes.len());
+        let json_len = u32::from_le_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]) as usize;
+        assert_eq!(json_len % 4, 0);
+        assert_eq!(&bytes[16..20], b"JSON");
+
+        let parsed: Value = serde_json::from_slice(&bytes[20..20 + json_len]).unwrap();
+        assert_eq!(parsed["accessors"][0]["max"], json!([1.0, 1.0, 0.0]));
+        assert_eq!(
+            parsed["meshes"][0]["primitives"][0]["attributes"][MATERIAL_WEIGHTS_ATTRIBUTE],
+            json!(2)
+        );
+    }
+
+    #[test]
+    fn normals_are_written_with_unit_length() {
+        let mut mesh = triangle("a");
+        mesh.normals = vec![[0.0, 0.0, 2.0], [0.0; 3], [3.0, 4.0, 0.0]];
+        let (buffer, document) = build_document(&[mesh]);
+
+        let normals = document["meshes"][0]["primitives"][0]["attributes"]["NORMAL"]
+            .as_u64()
+            .unwrap() as usize;
+        let view = document["accessors"][normals]["bufferView"]
+            .as_u64()
+            .unwrap() as usize;
+        let offset = document["bufferViews"][view]["byteOffset"]
+            .as_u64()
+            .unwrap() as usize;
+        let normals: Vec<f32> = buffer[offset..offset + 36]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(normals, vec![0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.6, 0.8, 0.0]);
+    }
+
+    #[test]
+    fn appending_offsets_indices() {
+        let mut merged = ExportMesh::new("merged".to_string());
+        merged.append(&triangle("a"));
+        merged.append(&triangle("b"));
+
+        assert_eq!(merged.positions.len(), 6);
+        assert_eq!(merged.indices, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn weights_are_normalized() {
+        assert_eq!(material_weights_from_counts([4, 4, 0, 0]), [128, 128, 0, 0]);
+        assert_eq!(material_weights_from_counts([0, 0, 8, 0]), [0, 0, 255, 0]);
+        assert_eq!(material_weights_from_counts([0; 4]), [0; 4]);
+    }
+}
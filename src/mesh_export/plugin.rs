@@ -0,0 +1,207 @@
+use super::gltf::{material_weights_from_counts, write_gltf, ExportMesh};
+
+use crate::{
+    edit_tools::SelectionState,
+    voxel_renderer::{mesh_padded_chunk, MeshBuffers},
+    Config, SdfVoxelMap, StatePlugin, ThreadLocalVoxelCache,
+};
+
+use bevy::{app::prelude::*, ecs::prelude::*, input::prelude::*};
+use building_blocks::{
+    mesh::{padded_surface_nets_chunk_extent, PosNormMesh},
+    prelude::*,
+    storage::ChunkKey3,
+};
+
+/// Exports meshes of the `SdfVoxelMap` to the glTF file given by `Config::export`. Depends on the `MapIoPlugin` and
+/// `EditToolsPlugin`.
+pub struct MeshExportPlugin;
+
+impl MeshExportPlugin {
+    pub fn register_events(app: &mut AppBuilder) {
+        app.add_event::<MeshExportEvents>();
+    }
+}
+
+impl StatePlugin for MeshExportPlugin {
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(mesh_export_default_input_map.system())
+            .with_system(mesh_export_system.system())
+    }
+}
+
+pub enum MeshExportEvents {
+    ExportMap,
+    ExportSelection,
+}
+
+pub fn mesh_export_default_input_map(
+    mut events: EventWriter<MeshExportEvents>,
+    keyboard: Res<Input<KeyCode>>,
+) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    let shift = keyboard.pressed(KeyCode::LShift) || keyboard.pressed(KeyCode::RShift);
+    if !ctrl || !keyboard.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    if shift {
+        events.send(MeshExportEvents::ExportSelection);
+    } else {
+        events.send(MeshExportEvents::ExportMap);
+    }
+}
+
+pub fn mesh_export_system(
+    config: Res<Config>,
+    voxel_map: Res<SdfVoxelMap>,
+    local_caches: Res<ThreadLocalVoxelCache>,
+    selection: Option<Res<SelectionState>>,
+    mut events: EventReader<MeshExportEvents>,
+) {
+    for event in events.iter() {
+        let clip_extent = match event {
+            MeshExportEvents::ExportMap => None,
+            MeshExportEvents::ExportSelection => match selection.as_deref() {
+                Some(SelectionState::SelectionReady { quad_extent, .. }) => {
+                    // The surface passes through the voxels on either side of the selected faces.
+                    Some(quad_extent.padded(1))
+                }
+                Some(SelectionState::BoxReady { extent }) => Some(*extent),
+                _ => {
+                    println!("Nothing is selected for export");
+                    continue;
+                }
+            },
+        };
+
+        let meshes = export_meshes(
+            &*voxel_map,
+            &*local_caches,
+            clip_extent,
+            config.export.merge_chunks,
+        );
+        match write_gltf(&config.export.path, &meshes) {
+            Ok(()) => println!("Exported {} meshes to {}", meshes.len(), config.export.path),
+            Err(e) => println!("Failed to export to {}: {}", config.export.path, e),
+        }
+    }
+}
+
+/// Generates a mesh for every chunk that intersects `clip_extent` (or every chunk if there is none). Triangles outside of
+/// `clip_extent` are dropped. Finite maps are also clipped to their world bounds. If `merge_chunks`, all of the chunk meshes are
+/// merged into one.
+pub fn export_meshes(
+    voxel_map: &SdfVoxelMap,
+    local_caches: &ThreadLocalVoxelCache,
+    clip_extent: Option<Extent3i>,
+    merge_chunks: bool,
+) -> Vec<ExportMesh> {
+    let clip_extent = match (clip_extent, voxel_map.bounds) {
+        (Some(clip), Some(bounds)) => Some(clip.intersection(&bounds)),
+        (clip, bounds) => clip.or(bounds),
+    };
+    let cache_tls = local_caches.get();
+    let reader = voxel_map.reader(&cache_tls);
+    let indexer = voxel_map.voxels.indexer;
+
+    let mut chunk_keys = voxel_map.chunk_keys();
+    chunk_keys.sort_by_key(|k| k.minimum.0);
+
+    let mut mesh_buffers = MeshBuffers::new(indexer.chunk_shape());
+    let mut meshes = Vec::new();
+    for chunk_key in chunk_keys.into_iter() {
+        let chunk_extent = indexer.extent_for_chunk_with_min(chunk_key.minimum);
+        if let Some(clip) = clip_extent {
+            if chunk_extent.intersection(&clip).is_empty() {
+                continue;
+            }
+        }
+
+        let padded_chunk_extent = padded_surface_nets_chunk_extent(&chunk_extent);
+        let MeshBuffers {
+            padded_chunk,
+            surface_nets_buffer,
+        } = &mut mesh_buffers;
+        padded_chunk.set_minimum(padded_chunk_extent.minimum);
+        reader.copy_extent_into(&padded_chunk_extent, padded_chunk);
+
+        if let Some((mesh, material_counts)) =
+            mesh_padded_chunk(voxel_map, padded_chunk, 1.0, surface_nets_buffer)
+        {
+            let mesh = export_mesh(chunk_key, mesh, material_counts);
+            let mesh = match clip_extent {
+                Some(clip) => clip_mesh(&mesh, &clip),
+                None => mesh,
+            };
+            if !mesh.is_empty() {
+                meshes.push(mesh);
+            }
+        }
+    }
+
+    if merge_chunks {
+        let mut merged = ExportMesh::new("map".to_string());
+        for mesh in meshes.iter() {
+            merged.append(mesh);
+        }
+        meshes = vec![merged];
+    }
+
+    meshes
+}
+
+fn export_mesh(
+    chunk_key: ChunkKey3,
+    mesh: PosNormMesh,
+    material_counts: Vec<[u8; 4]>,
+) -> ExportMesh {
+    let PointN([x, y, z]) = chunk_key.minimum;
+
+    ExportMesh {
+        name: format!("chunk_{}_{}_{}", x, y, z),
+        positions: mesh.positions,
+        normals: mesh.normals,
+        material_weights: material_counts
+            .into_iter()
+            .map(material_weights_from_counts)
+            .collect(),
+        indices: mesh.indices,
+    }
+}
+
+/// Keeps only the triangles whose centroids are inside of `extent`.
+fn clip_mesh(mesh: &ExportMesh, extent: &Extent3i) -> ExportMesh {
+    let mut clipped = ExportMesh::new(mesh.name.clone());
+    let mut new_indices = vec![None; mesh.positions.len()];
+    for triangle in mesh.indices.chunks(3) {
+        let mut centroid = [0.0; 3];
+        for &i in triangle.iter() {
+            for (c, x) in centroid.iter_mut().zip(mesh.positions[i as usize].iter()) {
+                *c += x / 3.0;
+            }
+        }
+        let centroid_voxel = PointN([
+            centroid[0].floor() as i32,
+            centroid[1].floor() as i32,
+            centroid[2].floor() as i32,
+        ]);
+        if !extent.contains(centroid_voxel) {
+            continue;
+        }
+
+        for &i in triangle.iter() {
+            let i = i as usize;
+            let new_index = *new_indices[i].get_or_insert_with(|| {
+                clipped.positions.push(mesh.positions[i]);
+                clipped.normals.push(mesh.normals[i]);
+                clipped.material_weights.push(mesh.material_weights[i]);
+
+                clipped.positions.len() as u32 - 1
+            });
+            clipped.indices.push(new_index);
+        }
+    }
+
+    clipped
+}
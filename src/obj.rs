@@ -0,0 +1,7 @@
+mod format;
+mod plugin;
+mod voxelize;
+
+pub use format::{read_obj, ObjError, TriangleMesh};
+pub use plugin::{ObjEvents, ObjPlugin};
+pub use voxelize::{voxelize_mesh, CsgOperation};
@@ -0,0 +1,156 @@
+use std::io::{self, BufRead};
+
+/// A triangle mesh read from an OBJ file. Only vertex positions and faces are kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TriangleMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl TriangleMesh {
+    /// Scales every position by `scale` and then adds `offset`.
+    pub fn transform(&mut self, scale: f32, offset: [f32; 3]) {
+        for p in self.positions.iter_mut() {
+            for (x, o) in p.iter_mut().zip(offset.iter()) {
+                *x = *x * scale + o;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    NoTriangles,
+    UnknownVoxelType(String),
+}
+
+impl std::fmt::Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "{}", e),
+            ObjError::Parse { line, message } => write!(f, "Line {}: {}", line, message),
+            ObjError::NoTriangles => write!(f, "The mesh has no faces"),
+            ObjError::UnknownVoxelType(t) => write!(f, "Unknown voxel type {}", t),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<io::Error> for ObjError {
+    fn from(e: io::Error) -> Self {
+        ObjError::Io(e)
+    }
+}
+
+/// Reads the `v` and `f` statements of an OBJ file. Polygons are triangulated as fans, and everything else (normals, texture
+/// coordinates, groups, materials) is ignored.
+pub fn read_obj(reader: impl BufRead) -> Result<TriangleMesh, ObjError> {
+    let mut mesh = TriangleMesh::default();
+    for (line_index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = line_index + 1;
+        let parse_error = |message: String| ObjError::Parse {
+            line: line_number,
+            message,
+        };
+
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                let mut p = [0.0; 3];
+                for x in p.iter_mut() {
+                    let word = words
+                        .next()
+                        .ok_or_else(|| parse_error("Vertex needs 3 coordinates".to_string()))?;
+                    *x = word
+                        .parse()
+                        .map_err(|_| parse_error(format!("Bad coordinate {}", word)))?;
+                }
+                mesh.positions.push(p);
+            }
+            Some("f") => {
+                let num_positions = mesh.positions.len();
+                let polygon = words
+                    .map(|word| {
+                        // Only the position index matters, e.g. "3/1/2" or "3//2".
+                        let index = word.split('/').next().unwrap_or(word);
+                        let index: i64 = index
+                            .parse()
+                            .map_err(|_| parse_error(format!("Bad vertex index {}", word)))?;
+                        // Indices are 1-based, and negative indices are relative to the end.
+                        let resolved = if index < 0 {
+                            num_positions as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if resolved < 0 || resolved >= num_positions as i64 {
+                            return Err(parse_error(format!(
+                                "Vertex index {} is out of range",
+                                word
+                            )));
+                        }
+
+                        Ok(resolved as u32)
+                    })
+                    .collect::<Result<Vec<u32>, ObjError>>()?;
+                if polygon.len() < 3 {
+                    return Err(parse_error("Face needs at least 3 vertices".to_string()));
+                }
+                for edge in polygon[1..].windows(2) {
+                    mesh.triangles.push([polygon[0], edge[0], edge[1]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if mesh.triangles.is_empty() {
+        return Err(ObjError::NoTriangles);
+    }
+
+    Ok(mesh)
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_polygons_with_attributes_and_relative_indices() {
+        let obj = "
+            # A quad and a triangle.
+            o thing
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vn 0 0 1
+            f 1/1/1 2/2/1 3/3/1 4/4/1
+            f -3//1 -2//1 -1//1
+        ";
+        let mesh = read_obj(obj.as_bytes()).unwrap();
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3], [1, 2, 3]]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_indices() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n";
+
+        assert!(matches!(
+            read_obj(obj.as_bytes()),
+            Err(ObjError::Parse { line: 4, .. })
+        ));
+    }
+}
@@ -0,0 +1,85 @@
+use super::{read_obj, voxelize_mesh, ObjError};
+
+use crate::{Config, ObjConfig, PaletteAsset, PaletteHandle, StatePlugin, VoxelEditor};
+
+use bevy::{app::prelude::*, asset::prelude::*, ecs::prelude::*, input::prelude::*};
+use building_blocks::prelude::*;
+use std::{fs::File, io::BufReader};
+
+/// Voxelizes the OBJ mesh given by `Config::obj` into the map. Depends on the `MapIoPlugin`.
+pub struct ObjPlugin;
+
+impl ObjPlugin {
+    pub fn register_events(app: &mut AppBuilder) {
+        app.add_event::<ObjEvents>();
+    }
+}
+
+impl StatePlugin for ObjPlugin {
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(obj_default_input_map.system())
+            .with_system(obj_system.system())
+    }
+}
+
+pub enum ObjEvents {
+    Import,
+}
+
+pub fn obj_default_input_map(mut events: EventWriter<ObjEvents>, keyboard: Res<Input<KeyCode>>) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    if ctrl && keyboard.just_pressed(KeyCode::M) {
+        events.send(ObjEvents::Import);
+    }
+}
+
+pub fn obj_system(
+    config: Res<Config>,
+    palette_handle: Res<PaletteHandle>,
+    palettes: Res<Assets<PaletteAsset>>,
+    mut voxel_editor: VoxelEditor,
+    mut events: EventReader<ObjEvents>,
+) {
+    let palette = if let Some(p) = palettes.get(&palette_handle.0) {
+        p
+    } else {
+        return;
+    };
+
+    for event in events.iter() {
+        let obj = &config.obj;
+        match event {
+            ObjEvents::Import => match import_obj(obj, palette, &mut voxel_editor) {
+                Ok(extent) => println!(
+                    "Imported {} into {:?} with {:?}",
+                    obj.path, extent, obj.operation
+                ),
+                Err(e) => println!("Failed to import {}: {}", obj.path, e),
+            },
+        }
+    }
+}
+
+/// Voxelizes the configured mesh and combines it with the map using the configured operation.
+fn import_obj(
+    obj: &ObjConfig,
+    palette: &PaletteAsset,
+    voxel_editor: &mut VoxelEditor,
+) -> Result<Extent3i, ObjError> {
+    let voxel_type = palette
+        .voxel_type_by_name(&obj.voxel_type)
+        .ok_or_else(|| ObjError::UnknownVoxelType(obj.voxel_type.clone()))?;
+
+    let mut mesh = read_obj(BufReader::new(File::open(&obj.path)?))?;
+    let [x, y, z] = obj.origin;
+    mesh.transform(obj.scale, [x as f32, y as f32, z as f32]);
+
+    let sdf = voxelize_mesh(&mesh, voxel_type);
+    let extent = *sdf.extent();
+    let operation = obj.operation;
+    voxel_editor.edit_extent_and_touch_neighbors(extent, |p, voxel| {
+        operation.apply(voxel, sdf.get(p));
+    });
+
+    Ok(extent)
+}
@@ -0,0 +1,318 @@
+use super::TriangleMesh;
+
+use crate::{ambient_sdf_array, voxel::EMPTY_VOXEL_TYPE, SdfArray, VoxelType};
+
+use bevy::math::prelude::*;
+use building_blocks::{prelude::*, storage::Sd8};
+use serde::Deserialize;
+
+/// How an imported shape is combined with the voxels already in the map.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum CsgOperation {
+    /// Add the shape to the map.
+    Union,
+    /// Carve the shape out of the map.
+    Subtract,
+    /// Overwrite every voxel in the shape's bounds.
+    Replace,
+}
+
+impl Default for CsgOperation {
+    fn default() -> Self {
+        CsgOperation::Union
+    }
+}
+
+impl CsgOperation {
+    /// Combines the existing voxel (`dst_type`, `dst_dist`) with a voxel of the shape. Like the terraformer, voxels that end up
+    /// solid get the shape's type and voxels that end up empty get the empty type.
+    pub fn apply(
+        self,
+        (dst_type, dst_dist): (&mut VoxelType, &mut Sd8),
+        (src_type, src_dist): (VoxelType, Sd8),
+    ) {
+        match self {
+            CsgOperation::Union => {
+                if src_dist.0 < dst_dist.0 {
+                    *dst_dist = src_dist;
+                    if src_dist.0 < 0 {
+                        *dst_type = src_type;
+                    }
+                }
+            }
+            CsgOperation::Subtract => {
+                let carved = src_dist.0.saturating_neg();
+                if carved > dst_dist.0 {
+                    dst_dist.0 = carved;
+                    if carved >= 0 {
+                        *dst_type = EMPTY_VOXEL_TYPE;
+                    }
+                }
+            }
+            CsgOperation::Replace => {
+                *dst_dist = src_dist;
+                *dst_type = if src_dist.0 < 0 {
+                    src_type
+                } else {
+                    EMPTY_VOXEL_TYPE
+                };
+            }
+        }
+    }
+}
+
+/// Distances are only computed exactly this close to the surface. `Sd8` saturates at 1 voxel anyway.
+const DISTANCE_BAND: f32 = 2.0;
+
+/// Computes the signed distance from every voxel near `mesh` to its surface, in the same coordinates as the mesh positions. The
+/// mesh must be watertight for the sign to be correct. Voxels inside of the mesh get `voxel_type`.
+pub fn voxelize_mesh(mesh: &TriangleMesh, voxel_type: VoxelType) -> SdfArray {
+    let triangles: Vec<[Vec3; 3]> = mesh
+        .triangles
+        .iter()
+        .map(|t| {
+            [
+                Vec3::from(mesh.positions[t[0] as usize]),
+                Vec3::from(mesh.positions[t[1] as usize]),
+                Vec3::from(mesh.positions[t[2] as usize]),
+            ]
+        })
+        .collect();
+
+    let extent = mesh_extent(mesh).padded(DISTANCE_BAND as i32);
+    let shape = extent.shape;
+    let local_index = |p: Point3i| {
+        let l = p - extent.minimum;
+
+        (l.x() + shape.x() * (l.y() + shape.y() * l.z())) as usize
+    };
+
+    // Unsigned distances in a narrow band around each triangle.
+    let mut distances = vec![DISTANCE_BAND; shape.volume() as usize];
+    for tri in triangles.iter() {
+        let tri_extent = triangle_extent(tri)
+            .padded(DISTANCE_BAND as i32)
+            .intersection(&extent);
+        for z in tri_extent.minimum.z()..tri_extent.least_upper_bound().z() {
+            for y in tri_extent.minimum.y()..tri_extent.least_upper_bound().y() {
+                for x in tri_extent.minimum.x()..tri_extent.least_upper_bound().x() {
+                    let p = PointN([x, y, z]);
+                    let d = point_triangle_distance(Vec3::new(x as f32, y as f32, z as f32), tri);
+                    let dist = &mut distances[local_index(p)];
+                    *dist = dist.min(d);
+                }
+            }
+        }
+    }
+
+    // The sign comes from the parity of crossings along a ray in +Y through each column of voxels.
+    let mut crossings = vec![Vec::new(); (shape.x() * shape.z()) as usize];
+    let column_index =
+        |x: i32, z: i32| ((x - extent.minimum.x()) + shape.x() * (z - extent.minimum.z())) as usize;
+    for tri in triangles.iter() {
+        let tri_extent = triangle_extent(tri).intersection(&extent);
+        for z in tri_extent.minimum.z()..tri_extent.least_upper_bound().z() {
+            for x in tri_extent.minimum.x()..tri_extent.least_upper_bound().x() {
+                // Nudge the ray off of the lattice so it doesn't pass exactly through shared edges and vertices.
+                if let Some(y) = column_crossing(x as f32 + 1.3e-4, z as f32 + 2.9e-4, tri) {
+                    crossings[column_index(x, z)].push(y);
+                }
+            }
+        }
+    }
+    for column in crossings.iter_mut() {
+        column.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    }
+
+    let mut sdf = ambient_sdf_array(extent);
+    sdf.for_each_mut(
+        &extent,
+        |p: Point3i, (v_type, v_dist): (&mut VoxelType, &mut Sd8)| {
+            let column = &crossings[column_index(p.x(), p.z())];
+            let num_below = column.iter().take_while(|&&y| y < p.y() as f32).count();
+            let inside = num_below % 2 == 1;
+
+            let dist = distances[local_index(p)].min(1.0);
+            if inside {
+                *v_type = voxel_type;
+                *v_dist = Sd8::from(-dist);
+            } else {
+                *v_type = EMPTY_VOXEL_TYPE;
+                *v_dist = Sd8::from(dist);
+            }
+        },
+    );
+
+    sdf
+}
+
+fn mesh_extent(mesh: &TriangleMesh) -> Extent3i {
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for &p in mesh.positions.iter() {
+        min = min.min(Vec3::from(p));
+        max = max.max(Vec3::from(p));
+    }
+
+    float_bounds_extent(min, max)
+}
+
+fn triangle_extent(tri: &[Vec3; 3]) -> Extent3i {
+    let min = tri[0].min(tri[1]).min(tri[2]);
+    let max = tri[0].max(tri[1]).max(tri[2]);
+
+    float_bounds_extent(min, max)
+}
+
+/// The smallest extent containing every integer point in the box from `min` to `max`.
+fn float_bounds_extent(min: Vec3, max: Vec3) -> Extent3i {
+    let min = PointN([
+        min.x.floor() as i32,
+        min.y.floor() as i32,
+        min.z.floor() as i32,
+    ]);
+    let max = PointN([
+        max.x.ceil() as i32,
+        max.y.ceil() as i32,
+        max.z.ceil() as i32,
+    ]);
+
+    Extent3i::from_min_and_max(min, max)
+}
+
+/// If the vertical line at `(x, z)` passes through the triangle, returns the height where it does.
+fn column_crossing(x: f32, z: f32, tri: &[Vec3; 3]) -> Option<f32> {
+    let [a, b, c] = *tri;
+    let det = (b.z - c.z) * (a.x - c.x) + (c.x - b.x) * (a.z - c.z);
+    if det.abs() < f32::EPSILON {
+        // The triangle is vertical, so the line can only graze it.
+        return None;
+    }
+    let u = ((b.z - c.z) * (x - c.x) + (c.x - b.x) * (z - c.z)) / det;
+    let v = ((c.z - a.z) * (x - c.x) + (a.x - c.x) * (z - c.z)) / det;
+    let w = 1.0 - u - v;
+    if u < 0.0 || v < 0.0 || w < 0.0 {
+        return None;
+    }
+
+    Some(u * a.y + v * b.y + w * c.y)
+}
+
+/// The distance from `p` to the closest point on the triangle. From "Real-Time Collision Detection" by Christer Ericson.
+fn point_triangle_distance(p: Vec3, tri: &[Vec3; 3]) -> f32 {
+    let [a, b, c] = *tri;
+    let ab = b - a;
+    let ac = c - a;
+
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return p.distance(a);
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return p.distance(b);
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let v = d1 / (d1 - d3);
+        return p.distance(a + v * ab);
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return p.distance(c);
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let w = d2 / (d2 - d6);
+        return p.distance(a + w * ac);
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        let w = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return p.distance(b + w * (c - b));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+
+    p.distance(a + ab * v + ac * w)
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::obj::read_obj;
+
+    const CUBE_OBJ: &str = "
+        v 0 0 0
+        v 8 0 0
+        v 8 8 0
+        v 0 8 0
+        v 0 0 8
+        v 8 0 8
+        v 8 8 8
+        v 0 8 8
+        f 1 4 3 2
+        f 5 6 7 8
+        f 1 2 6 5
+        f 4 8 7 3
+        f 1 5 8 4
+        f 2 3 7 6
+    ";
+
+    #[test]
+    fn cube_is_negative_inside_and_positive_outside() {
+        let mut mesh = read_obj(CUBE_OBJ.as_bytes()).unwrap();
+        mesh.transform(1.0, [0.5, 0.5, 0.5]);
+        let sdf = voxelize_mesh(&mesh, VoxelType(3));
+
+        assert_eq!(sdf.get(PointN([4, 4, 4])), (VoxelType(3), Sd8::NEG_ONE));
+        assert_eq!(sdf.get(PointN([1, 4, 4])), (VoxelType(3), Sd8::from(-0.5)));
+        assert_eq!(
+            sdf.get(PointN([0, 4, 4])),
+            (EMPTY_VOXEL_TYPE, Sd8::from(0.5))
+        );
+        assert_eq!(sdf.get(PointN([4, 10, 4])), (EMPTY_VOXEL_TYPE, Sd8::ONE));
+    }
+
+    #[test]
+    fn csg_operations_follow_the_terraformer_rules() {
+        let solid = (VoxelType(1), Sd8::from(-0.5));
+        let empty = (EMPTY_VOXEL_TYPE, Sd8::from(0.5));
+        let shape = (VoxelType(2), Sd8::from(-0.25));
+
+        let apply = |op: CsgOperation, (mut t, mut d): (VoxelType, Sd8)| {
+            op.apply((&mut t, &mut d), shape);
+            (t, d)
+        };
+
+        assert_eq!(apply(CsgOperation::Union, solid), solid);
+        assert_eq!(apply(CsgOperation::Union, empty), shape);
+        assert_eq!(
+            apply(CsgOperation::Subtract, solid),
+            (EMPTY_VOXEL_TYPE, Sd8::from(0.25))
+        );
+        assert_eq!(apply(CsgOperation::Subtract, empty), empty);
+        assert_eq!(apply(CsgOperation::Replace, solid), shape);
+    }
+}
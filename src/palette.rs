@@ -0,0 +1,342 @@
+use crate::{voxel::EMPTY_VOXEL_TYPE, SdfVoxelPalette, VoxelMaterial, VoxelType, VoxelTypeInfo};
+
+use bevy::{
+    asset::{AssetLoader, Handle, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    render::texture::{Extent3d, Texture},
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+/// The material layers and voxel types of a map, as authored in a `.palette.ron` asset.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "0c0a9a3e-6b9e-4a57-9f0d-2b7f6c3f8d41"]
+pub struct PaletteAsset {
+    /// Base color textures for the material layers. Each image may contain several layers stacked vertically, and layers are
+    /// numbered in the order they appear across all of the images.
+    pub base_color_textures: Vec<String>,
+    /// The names of the material layers, in layer order.
+    pub material_layers: Vec<String>,
+    /// The voxel types, where the index of each entry is its `VoxelType`.
+    pub voxel_types: Vec<VoxelTypeDef>,
+    /// The voxel types given to imported terrain, ordered from lowest to highest.
+    #[serde(default)]
+    pub height_bands: Vec<HeightBand>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoxelTypeDef {
+    pub name: String,
+    pub is_empty: bool,
+    /// The name of a material layer. Must be given for non-empty types.
+    #[serde(default)]
+    pub material: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeightBand {
+    /// The top of the band, as a fraction of the terrain's full height. Anything above the last band uses the last band.
+    pub max_height: f32,
+    /// The name of a non-empty voxel type.
+    pub voxel_type: String,
+}
+
+/// The vertex format only has room for this many material weights.
+pub const MAX_MATERIAL_LAYERS: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub enum PaletteError {
+    NoTextures,
+    NoMaterialLayers,
+    TooManyMaterialLayers(usize),
+    TooManyVoxelTypes(usize),
+    FirstTypeNotEmpty,
+    EmptyTypeHasMaterial(String),
+    MissingMaterial(String),
+    UnknownMaterial {
+        voxel_type: String,
+        material: String,
+    },
+    MismatchedTextures,
+    TextureNotDivisibleIntoLayers {
+        height: u32,
+        num_layers: usize,
+    },
+    UnknownHeightBandType(String),
+    EmptyHeightBandType(String),
+    UnsortedHeightBands,
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteError::NoTextures => write!(f, "No base color textures"),
+            PaletteError::NoMaterialLayers => write!(f, "No material layers"),
+            PaletteError::TooManyMaterialLayers(n) => write!(
+                f,
+                "{} material layers, but at most {} are supported",
+                n, MAX_MATERIAL_LAYERS
+            ),
+            PaletteError::TooManyVoxelTypes(n) => {
+                write!(f, "{} voxel types, but at most 256 are supported", n)
+            }
+            PaletteError::FirstTypeNotEmpty => {
+                write!(f, "The first voxel type must be empty")
+            }
+            PaletteError::EmptyTypeHasMaterial(t) => {
+                write!(f, "Empty voxel type {} should not have a material", t)
+            }
+            PaletteError::MissingMaterial(t) => {
+                write!(f, "Non-empty voxel type {} needs a material", t)
+            }
+            PaletteError::UnknownMaterial {
+                voxel_type,
+                material,
+            } => write!(
+                f,
+                "Voxel type {} uses unknown material layer {}",
+                voxel_type, material
+            ),
+            PaletteError::MismatchedTextures => write!(
+                f,
+                "Base color textures must all have the same width and format"
+            ),
+            PaletteError::TextureNotDivisibleIntoLayers { height, num_layers } => write!(
+                f,
+                "Stacked texture height {} can't be divided into {} layers",
+                height, num_layers
+            ),
+            PaletteError::UnknownHeightBandType(t) => {
+                write!(f, "Height band uses unknown voxel type {}", t)
+            }
+            PaletteError::EmptyHeightBandType(t) => {
+                write!(f, "Height band uses empty voxel type {}", t)
+            }
+            PaletteError::UnsortedHeightBands => {
+                write!(f, "Height bands must be sorted by max_height")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+impl PaletteAsset {
+    /// Checks the palette for consistency and converts it into the runtime palette.
+    pub fn voxel_palette(&self) -> Result<SdfVoxelPalette, PaletteError> {
+        if self.base_color_textures.is_empty() {
+            return Err(PaletteError::NoTextures);
+        }
+        if self.material_layers.is_empty() {
+            return Err(PaletteError::NoMaterialLayers);
+        }
+        if self.material_layers.len() > MAX_MATERIAL_LAYERS {
+            return Err(PaletteError::TooManyMaterialLayers(
+                self.material_layers.len(),
+            ));
+        }
+        if self.voxel_types.len() > 256 {
+            return Err(PaletteError::TooManyVoxelTypes(self.voxel_types.len()));
+        }
+        if !self
+            .voxel_types
+            .get(EMPTY_VOXEL_TYPE.0 as usize)
+            .map_or(false, |t| t.is_empty)
+        {
+            return Err(PaletteError::FirstTypeNotEmpty);
+        }
+
+        let mut infos = Vec::with_capacity(self.voxel_types.len());
+        for def in self.voxel_types.iter() {
+            let material = match (def.is_empty, def.material.as_ref()) {
+                (true, None) => VoxelMaterial::NULL,
+                (true, Some(_)) => {
+                    return Err(PaletteError::EmptyTypeHasMaterial(def.name.clone()))
+                }
+                (false, None) => return Err(PaletteError::MissingMaterial(def.name.clone())),
+                (false, Some(material)) => {
+                    let layer = self
+                        .material_layers
+                        .iter()
+                        .position(|l| l == material)
+                        .ok_or_else(|| PaletteError::UnknownMaterial {
+                            voxel_type: def.name.clone(),
+                            material: material.clone(),
+                        })?;
+                    VoxelMaterial(layer as u8)
+                }
+            };
+            infos.push(VoxelTypeInfo {
+                is_empty: def.is_empty,
+                material,
+            });
+        }
+
+        self.height_bands()?;
+
+        Ok(SdfVoxelPalette { infos })
+    }
+
+    /// Resolves the `height_bands` into `(max_height, voxel_type)` pairs.
+    pub fn height_bands(&self) -> Result<Vec<(f32, VoxelType)>, PaletteError> {
+        let mut bands = Vec::with_capacity(self.height_bands.len());
+        for band in self.height_bands.iter() {
+            let voxel_type = self
+                .voxel_type_by_name(&band.voxel_type)
+                .ok_or_else(|| PaletteError::UnknownHeightBandType(band.voxel_type.clone()))?;
+            if self.voxel_types[voxel_type.0 as usize].is_empty {
+                return Err(PaletteError::EmptyHeightBandType(band.voxel_type.clone()));
+            }
+            if bands
+                .last()
+                .map_or(false, |(prev_max, _)| band.max_height < *prev_max)
+            {
+                return Err(PaletteError::UnsortedHeightBands);
+            }
+            bands.push((band.max_height, voxel_type));
+        }
+
+        Ok(bands)
+    }
+
+    /// Finds the voxel type with the given `name`.
+    pub fn voxel_type_by_name(&self, name: &str) -> Option<VoxelType> {
+        self.voxel_types
+            .iter()
+            .position(|t| t.name == name)
+            .map(|i| VoxelType(i as u8))
+    }
+
+    /// Stacks the loaded `base_color_textures` (in order) into a single texture array with one layer per material.
+    pub fn build_materials_texture(&self, textures: &[&Texture]) -> Result<Texture, PaletteError> {
+        let first = textures.first().ok_or(PaletteError::NoTextures)?;
+        if textures
+            .iter()
+            .any(|t| t.size.width != first.size.width || t.format != first.format)
+        {
+            return Err(PaletteError::MismatchedTextures);
+        }
+
+        let height: u32 = textures.iter().map(|t| t.size.height).sum();
+        let num_layers = self.material_layers.len();
+        if height % num_layers as u32 != 0 {
+            return Err(PaletteError::TextureNotDivisibleIntoLayers { height, num_layers });
+        }
+
+        let mut data = Vec::with_capacity(textures.iter().map(|t| t.data.len()).sum());
+        for texture in textures.iter() {
+            data.extend_from_slice(&texture.data);
+        }
+        let mut stacked = Texture::new(
+            Extent3d::new(first.size.width, height, 1),
+            first.dimension,
+            data,
+            first.format,
+        );
+        stacked.reinterpret_stacked_2d_as_array(num_layers as u32);
+
+        Ok(stacked)
+    }
+}
+
+/// The palette asset that the editor loaded on startup.
+pub struct PaletteHandle(pub Handle<PaletteAsset>);
+
+#[derive(Default)]
+pub struct PaletteAssetLoader;
+
+impl AssetLoader for PaletteAssetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            let palette: PaletteAsset = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(palette));
+
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["palette.ron"]
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_PALETTE: &str = r#"(
+        base_color_textures: ["base_color.png"],
+        material_layers: ["grass", "rock"],
+        voxel_types: [
+            (name: "air", is_empty: true),
+            (name: "rock", is_empty: false, material: Some("rock")),
+            (name: "grass", is_empty: false, material: Some("grass")),
+        ],
+        height_bands: [
+            (max_height: 0.5, voxel_type: "grass"),
+            (max_height: 1.0, voxel_type: "rock"),
+        ],
+    )"#;
+
+    #[test]
+    fn converts_valid_palette() {
+        let asset: PaletteAsset = ron::de::from_str(TEST_PALETTE).unwrap();
+        let palette = asset.voxel_palette().unwrap();
+
+        assert_eq!(
+            palette.infos,
+            vec![
+                VoxelTypeInfo {
+                    is_empty: true,
+                    material: VoxelMaterial::NULL,
+                },
+                VoxelTypeInfo {
+                    is_empty: false,
+                    material: VoxelMaterial(1),
+                },
+                VoxelTypeInfo {
+                    is_empty: false,
+                    material: VoxelMaterial(0),
+                },
+            ]
+        );
+        assert_eq!(asset.voxel_type_by_name("grass"), Some(VoxelType(2)));
+        assert_eq!(
+            asset.height_bands(),
+            Ok(vec![(0.5, VoxelType(2)), (1.0, VoxelType(1))])
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_material() {
+        let mut asset: PaletteAsset = ron::de::from_str(TEST_PALETTE).unwrap();
+        asset.voxel_types[1].material = Some("lava".to_string());
+
+        assert_eq!(
+            asset.voxel_palette(),
+            Err(PaletteError::UnknownMaterial {
+                voxel_type: "rock".to_string(),
+                material: "lava".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_non_empty_first_type() {
+        let mut asset: PaletteAsset = ron::de::from_str(TEST_PALETTE).unwrap();
+        asset.voxel_types.swap(0, 1);
+
+        assert_eq!(asset.voxel_palette(), Err(PaletteError::FirstTypeNotEmpty));
+    }
+}
@@ -6,10 +6,15 @@ pub use plugin::VoxelPickingPlugin;
 pub use ray_impact::VoxelCursorRayImpact;
 pub use voxel_cursor::VoxelCursor;
 
+use crate::geometry::{point3i_serde, signed_axis3_serde};
+
 use building_blocks::core::{Point3i, SignedAxis3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct VoxelFace {
+    #[serde(with = "point3i_serde")]
     pub point: Point3i,
+    #[serde(with = "signed_axis3_serde")]
     pub normal: SignedAxis3,
 }
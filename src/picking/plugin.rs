@@ -8,7 +8,8 @@ use super::{
 
 use bevy::ecs::prelude::*;
 
-/// Manages the `VoxelCursorRayImpact` and `VoxelCursorStates` resources.
+/// Manages the `VoxelCursorRayImpact` and `VoxelCursorStates` resources. The impact is updated after the `CursorRay` and the
+/// `VoxelBVT`.
 pub struct VoxelPickingPlugin;
 
 impl VoxelPickingPlugin {
@@ -24,7 +25,19 @@ impl StatePlugin for VoxelPickingPlugin {
     }
 
     fn add_update_systems(set: SystemSet) -> SystemSet {
-        set.with_system(voxel_cursor_impact_system.system())
-            .with_system(voxel_clicking_system.system())
+        set.with_system(
+            voxel_cursor_impact_system
+                .system()
+                .label("voxel_picking")
+                .label("voxel_cursor_impact")
+                .after("cursor_ray")
+                .after("voxel_bvt"),
+        )
+        .with_system(
+            voxel_clicking_system
+                .system()
+                .label("voxel_picking")
+                .after("voxel_cursor_impact"),
+        )
     }
 }
@@ -1,5 +1,5 @@
 use super::VoxelFace;
-use crate::{CursorRay, VoxelBVT};
+use crate::{geometry::ray_box_intersection, CursorRay, SdfVoxelMap, VoxelBVT};
 
 use bevy::ecs::prelude::*;
 use building_blocks::{
@@ -33,9 +33,11 @@ impl VoxelCursorRayImpact {
     }
 }
 
-/// Each frame, a ray is cast at the `VoxelBVT`, and the resulting impact is stored.
+/// Each frame, a ray is cast at the `VoxelBVT`, and the resulting impact is stored. Voxels outside of the map's world bounds
+/// can't be hit.
 pub fn voxel_cursor_impact_system(
     bvt: Res<VoxelBVT>,
+    voxel_map: Res<SdfVoxelMap>,
     cursor_ray: Res<CursorRay>,
     mut voxel_cursor_impact: ResMut<VoxelCursorRayImpact>,
 ) {
@@ -43,9 +45,21 @@ pub fn voxel_cursor_impact_system(
     voxel_cursor_impact.normal = None;
 
     if let CursorRay(Some(ray)) = *cursor_ray {
-        if let Some(impact) =
-            cast_ray_at_voxels(&*bvt, NCRay::from(ray), std::f32::INFINITY, |_| true)
-        {
+        let bounds = voxel_map.bounds;
+        let max_toi = if let Some(bounds) = bounds {
+            let min = Point3f::from(bounds.minimum).into();
+            let max = Point3f::from(bounds.least_upper_bound()).into();
+            match ray_box_intersection(&ray, min, max) {
+                Some((_enter, exit)) => exit,
+                None => return,
+            }
+        } else {
+            std::f32::INFINITY
+        };
+
+        if let Some(impact) = cast_ray_at_voxels(&*bvt, NCRay::from(ray), max_toi, |p| {
+            bounds.map_or(true, |b| b.contains(p))
+        }) {
             let normal = Point3f::from(impact.impact.normal.normalize())
                 .round()
                 .in_voxel();
@@ -1,14 +1,18 @@
 use crate::{
-    create_camera_entity, empty_compressible_sdf_chunk_map,
+    create_camera_entity, discard_autosave, empty_compressible_sdf_chunk_map, journal_path,
+    open_map_reader, recover_map,
     voxel_renderer::{ArrayMaterial, MeshGeneratorPlugin, MeshMaterial, VoxelRenderPlugin},
-    BVTPlugin, CameraConfig, CameraPlugin, ChunkCacheConfig, Config, CursorPositionPlugin,
-    EditToolsPlugin, ImmediateModePlugin, MapIoPlugin, SdfVoxelMap, SdfVoxelPalette, VoxelEditor,
-    VoxelMaterial, VoxelPickingPlugin, VoxelTypeInfo, CHUNK_SHAPE,
+    BVTPlugin, CameraConfig, CameraPlugin, Config, CursorPositionPlugin, EditJournal,
+    EditToolsPlugin, HeightmapPlugin, ImmediateModePlugin, MapFileError, MapFilePlugin,
+    MapFileReader, MapIoPlugin, MapSnapshotHeader, MeshExportPlugin, ObjPlugin, PaletteAsset,
+    PaletteAssetLoader, PaletteHandle, RecoverMap, SdfArray, SdfVoxelMap, SdfVoxelPalette,
+    SessionRecorderPlugin, SessionReplayPlugin, SmartTileRuleSet, VoxPlugin, VoxelEditor,
+    VoxelPickingPlugin, VoxelType, WorldBoundsPlugin,
 };
 
 use bevy::{
-    app::{prelude::*, PluginGroupBuilder},
-    asset::{prelude::*, AssetPlugin},
+    app::{prelude::*, AppExit, PluginGroupBuilder},
+    asset::{prelude::*, AddAsset, AssetPlugin},
     core::CorePlugin,
     ecs::prelude::*,
     input::InputPlugin,
@@ -20,6 +24,8 @@ use bevy::{
     window::WindowPlugin,
     winit::WinitPlugin,
 };
+use building_blocks::{prelude::*, storage::ChunkKey3};
+use std::{fs::File, io::BufReader, path::Path};
 
 /// The first-party plugins that we need from Bevy.
 pub struct BevyPlugins {
@@ -50,7 +56,15 @@ impl PluginGroup for BevyPlugins {
     }
 }
 
-pub struct EditorPlugin;
+pub struct EditorPlugin {
+    config: Config,
+}
+
+impl EditorPlugin {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+}
 
 impl Plugin for EditorPlugin {
     fn build(&self, app: &mut AppBuilder) {
@@ -59,25 +73,50 @@ impl Plugin for EditorPlugin {
             .add_plugin(CursorPositionPlugin)
             .add_plugin(ImmediateModePlugin)
             .add_plugin(VoxelRenderPlugin)
+            .add_asset::<PaletteAsset>()
+            .init_asset_loader::<PaletteAssetLoader>()
             // This plugin should run systems in the LAST stage.
-            .add_plugin(MapIoPlugin::new(CHUNK_SHAPE, ChunkCacheConfig::default()))
+            .add_plugin(MapIoPlugin::new(self.config.chunk_cache, self.config.lod))
             .add_plugin(CameraPlugin);
 
         // Register events that edit tools produce & consume
         EditToolsPlugin::register_events(app);
+        MapFilePlugin::register_events(app);
+        MeshExportPlugin::register_events(app);
+        HeightmapPlugin::register_events(app);
+        VoxPlugin::register_events(app);
+        ObjPlugin::register_events(app);
 
         // Editor scheduling.
-        add_editor_schedule(app);
+        add_editor_schedule(app, &self.config);
     }
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 enum EditorState {
     Loading,
+    LoadingMap,
     Editing,
+    /// The map couldn't be loaded. Nothing gets edited or saved in this state, so the partially loaded map can't clobber the file
+    /// it came from.
+    Failed,
 }
 
-fn add_editor_schedule(app: &mut AppBuilder) {
+fn add_editor_schedule(app: &mut AppBuilder, config: &Config) {
+    let mut enter_editing = PluginAdder(SystemSet::on_enter(EditorState::Editing))
+        .enter_with_plugin::<EditToolsPlugin>()
+        .enter_with_plugin::<SessionRecorderPlugin>()
+        .enter_with_plugin::<WorldBoundsPlugin>();
+    let mut update_editing = PluginAdder(SystemSet::on_update(EditorState::Editing))
+        .update_with_plugin::<MeshGeneratorPlugin>();
+    // A replayed session takes the place of the edit tools' input maps.
+    if config.session.replay_path.is_some() {
+        enter_editing = enter_editing.enter_with_plugin::<SessionReplayPlugin>();
+        update_editing = update_editing.update_with_plugin::<SessionReplayPlugin>();
+    } else {
+        update_editing = update_editing.update_with_plugin::<EditToolsPlugin>();
+    }
+
     app.add_state(EditorState::Loading)
         // Load assets.
         .add_system_set(
@@ -86,101 +125,370 @@ fn add_editor_schedule(app: &mut AppBuilder) {
         .add_system_set(
             SystemSet::on_update(EditorState::Loading).with_system(wait_for_assets_loaded.system()),
         )
+        // Load the map's chunks. The BVT is built as they come in, since they're only dirty for one frame.
+        .add_system_set(
+            SystemSet::on_enter(EditorState::LoadingMap)
+                .with_system(BVTPlugin::initialize.system())
+                .with_system(start_loading_map.system()),
+        )
+        .add_system_set(
+            PluginAdder(SystemSet::on_update(EditorState::LoadingMap))
+                .update_with_plugin::<BVTPlugin>()
+                .finish()
+                .with_system(load_map_chunks.system()),
+        )
+        .add_system_set(
+            SystemSet::on_enter(EditorState::Failed).with_system(exit_after_failing.system()),
+        )
         // Initialize editor systems.
         .add_system_set(
-            PluginAdder(SystemSet::on_enter(EditorState::Editing))
-                .enter_with_plugin::<EditToolsPlugin>()
+            enter_editing
                 .finish()
                 .with_system(VoxelPickingPlugin::initialize.system())
                 .with_system(MeshGeneratorPlugin::initialize.system())
-                .with_system(BVTPlugin::initialize.system())
                 .with_system(initialize_editor.system()),
         )
         // Update editor systems.
         .add_system_set(
-            PluginAdder(SystemSet::on_update(EditorState::Editing))
-                .update_with_plugin::<MeshGeneratorPlugin>()
-                .update_with_plugin::<EditToolsPlugin>()
+            update_editing
+                .update_with_plugin::<SessionRecorderPlugin>()
+                .update_with_plugin::<WorldBoundsPlugin>()
                 .update_with_plugin::<BVTPlugin>()
                 .update_with_plugin::<VoxelPickingPlugin>()
+                .update_with_plugin::<MapFilePlugin>()
+                .update_with_plugin::<MeshExportPlugin>()
+                .update_with_plugin::<HeightmapPlugin>()
+                .update_with_plugin::<VoxPlugin>()
+                .update_with_plugin::<ObjPlugin>()
                 .finish(),
         );
 }
 
-struct LoadingTexture(Handle<Texture>);
-
-fn start_loading(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands
-        // TODO: load voxel map from file
-        .insert_resource(SdfVoxelMap {
-            voxels: empty_compressible_sdf_chunk_map(CHUNK_SHAPE),
-            palette: SdfVoxelPalette {
-                infos: vec![
-                    VoxelTypeInfo {
-                        is_empty: true,
-                        material: VoxelMaterial::NULL,
-                    },
-                    VoxelTypeInfo {
-                        is_empty: false,
-                        material: VoxelMaterial(0),
-                    },
-                    VoxelTypeInfo {
-                        is_empty: false,
-                        material: VoxelMaterial(1),
-                    },
-                    VoxelTypeInfo {
-                        is_empty: false,
-                        material: VoxelMaterial(2),
-                    },
-                    VoxelTypeInfo {
-                        is_empty: false,
-                        material: VoxelMaterial(3),
-                    },
-                ],
+struct LoadingPalette {
+    palette: Handle<PaletteAsset>,
+    textures: Vec<Handle<Texture>>,
+}
+
+/// The number of chunks that get inserted into the map each frame while it loads. In between, the chunks that don't fit in the
+/// cache are compressed or swapped out like any others.
+const LOAD_CHUNKS_PER_FRAME: usize = 256;
+
+/// The map being loaded. Its chunks are inserted with the `VoxelEditor` a few per frame, so they will be meshed like any other
+/// edit, without ever decompressing the whole map at once.
+struct LoadingMap {
+    chunks: LoadingChunks,
+    /// Recovered chunks get journaled again when they're inserted, so the new journal still has every unsaved edit. Otherwise
+    /// the chunks are already on disk.
+    recover: bool,
+    num_loaded: usize,
+}
+
+enum LoadingChunks {
+    File(MapFileReader<BufReader<File>>),
+    /// Recovered chunks are already in memory, since the journal had to be replayed over them.
+    Recovered(std::vec::IntoIter<(ChunkKey3, SdfArray)>),
+    New,
+}
+
+impl LoadingChunks {
+    fn read_chunk(&mut self) -> Result<Option<(ChunkKey3, SdfArray)>, MapFileError> {
+        match self {
+            LoadingChunks::File(reader) => reader.read_chunk(),
+            LoadingChunks::Recovered(chunks) => Ok(chunks.next()),
+            LoadingChunks::New => Ok(None),
+        }
+    }
+}
+
+/// Opens the map file, or recovers it, so its chunks can be loaded. Returns the error message if that fails.
+fn open_map_chunks(
+    config: &Config,
+    recover: bool,
+) -> Result<(MapSnapshotHeader, LoadingChunks), String> {
+    if recover {
+        // Don't fall back to the saved map here, since the next autosave would clobber the edits we failed to recover.
+        let map_file = recover_map(&config.map_file)
+            .map_err(|e| format!("Failed to recover map {}: {}", config.map_file, e))?;
+        println!("Recovered map {}", config.map_file);
+
+        return Ok((
+            MapSnapshotHeader {
+                chunk_shape: map_file.chunk_shape,
+                palette: map_file.palette,
+                bounds: map_file.bounds,
+                num_chunks: map_file.chunks.len(),
             },
-        });
-    commands.insert_resource(LoadingTexture(
-        asset_server.load("grass_rock_snow_dirt/base_color.png"),
-    ));
+            LoadingChunks::Recovered(map_file.chunks.into_iter()),
+        ));
+    }
+
+    if let Err(e) = discard_autosave(&config.map_file) {
+        println!(
+            "WARNING: Failed to delete autosave of {}: {}",
+            config.map_file, e
+        );
+    }
+
+    if Path::new(&config.map_file).exists() {
+        // Don't fall back to an empty map here, since saving would clobber the file we failed to read.
+        let reader = open_map_reader(&config.map_file)
+            .map_err(|e| format!("Failed to open map {}: {}", config.map_file, e))?;
+        println!("Opened map {}", config.map_file);
+
+        Ok((reader.header.clone(), LoadingChunks::File(reader)))
+    } else {
+        println!("Creating new map {}", config.map_file);
+
+        Ok((new_map_header(config), LoadingChunks::New))
+    }
 }
 
+fn new_map_header(config: &Config) -> MapSnapshotHeader {
+    MapSnapshotHeader {
+        chunk_shape: PointN(config.chunk_shape),
+        palette: SdfVoxelPalette::default(),
+        bounds: config.world_bounds.map(|b| b.extent()),
+        num_chunks: 0,
+    }
+}
+
+/// Prints why the map couldn't be loaded and gives up on it.
+fn fail_loading(state: &mut State<EditorState>, message: String) {
+    println!("{}", message);
+    state.set(EditorState::Failed).unwrap();
+}
+
+fn exit_after_failing(mut exit: EventWriter<AppExit>) {
+    exit.send(AppExit);
+}
+
+fn start_loading(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    recover: Option<Res<RecoverMap>>,
+    mut state: ResMut<State<EditorState>>,
+) {
+    let recover = recover.map_or(false, |r| r.0);
+    // The map resources are inserted even if this fails, since the map IO systems always run.
+    let (header, chunks) = open_map_chunks(&config, recover).unwrap_or_else(|message| {
+        fail_loading(&mut state, message);
+
+        (new_map_header(&config), LoadingChunks::New)
+    });
+
+    if header.chunk_shape != PointN(config.chunk_shape) {
+        println!(
+            "Map {} has chunk shape {:?}; using it instead of {:?}",
+            config.map_file, header.chunk_shape.0, config.chunk_shape
+        );
+    }
+
+    // The palette gets replaced by the palette asset once it's loaded.
+    let mut voxel_map = SdfVoxelMap::new(
+        empty_compressible_sdf_chunk_map(header.chunk_shape, config.chunk_cache.compression()),
+        header.palette,
+    );
+    voxel_map.bounds = header.bounds;
+    MapIoPlugin::insert_voxel_map(&mut commands, voxel_map);
+    commands.insert_resource(LoadingMap {
+        chunks,
+        recover,
+        num_loaded: 0,
+    });
+    commands.insert_resource(LoadingPalette {
+        palette: asset_server.load(config.palette.as_str()),
+        textures: Vec::new(),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
 fn wait_for_assets_loaded(
     mut commands: Commands,
-    loading_texture: Res<LoadingTexture>,
+    asset_server: Res<AssetServer>,
+    config: Res<Config>,
+    palettes: Res<Assets<PaletteAsset>>,
+    mut loading: ResMut<LoadingPalette>,
+    mut voxel_map: ResMut<SdfVoxelMap>,
     mut array_materials: ResMut<Assets<ArrayMaterial>>,
     mut textures: ResMut<Assets<Texture>>,
     mut state: ResMut<State<EditorState>>,
 ) {
-    if let Some(texture) = textures.get_mut(&loading_texture.0) {
-        println!("Done loading mesh texture");
-        prepare_materials_texture(texture);
-        let mut material = ArrayMaterial::from(loading_texture.0.clone());
-        material.roughness = 0.8;
-        material.reflectance = 0.2;
-        commands.insert_resource(MeshMaterial(array_materials.add(material)));
-        state.set(EditorState::Editing).unwrap();
+    let palette_asset = if let Some(p) = palettes.get(&loading.palette) {
+        p
+    } else {
+        return;
+    };
+
+    if loading.textures.is_empty() {
+        println!("Done loading palette {}", config.palette);
+        let palette = palette_asset
+            .voxel_palette()
+            .unwrap_or_else(|e| panic!("Invalid palette {}: {}", config.palette, e));
+        if !voxel_map.palette.infos.is_empty() && voxel_map.palette != palette {
+            println!(
+                "WARNING: The palette saved in {} differs from {}; using {}",
+                config.map_file, config.palette, config.palette
+            );
+        }
+        voxel_map.palette = palette;
+
+        match SmartTileRuleSet::read_file(&config.smart_tile_rules)
+            .and_then(|rules| rules.resolve(palette_asset))
+        {
+            Ok(rules) => commands.insert_resource(rules),
+            Err(e) => println!(
+                "WARNING: Failed to load smart tile rules {}; smart tiling is disabled: {}",
+                config.smart_tile_rules, e
+            ),
+        }
+
+        // Validation guarantees there is at least one texture, so we won't get here again.
+        loading.textures = palette_asset
+            .base_color_textures
+            .iter()
+            .map(|path| asset_server.load(path.as_str()))
+            .collect();
     }
+
+    let layer_textures: Option<Vec<&Texture>> =
+        loading.textures.iter().map(|h| textures.get(h)).collect();
+    let layer_textures = if let Some(t) = layer_textures {
+        t
+    } else {
+        return;
+    };
+
+    println!("Done loading mesh textures");
+    let mut materials_texture = palette_asset
+        .build_materials_texture(&layer_textures)
+        .unwrap_or_else(|e| panic!("Invalid palette {}: {}", config.palette, e));
+    prepare_materials_texture(&mut materials_texture);
+
+    let mut material = ArrayMaterial::from(textures.add(materials_texture));
+    material.roughness = 0.8;
+    material.reflectance = 0.2;
+    commands.insert_resource(MeshMaterial(array_materials.add(material)));
+    commands.insert_resource(PaletteHandle(loading.palette.clone()));
+    commands.remove_resource::<LoadingPalette>();
+    state.set(EditorState::LoadingMap).unwrap();
 }
 
 fn prepare_materials_texture(texture: &mut Texture) {
-    let num_layers = 4;
-    texture.reinterpret_stacked_2d_as_array(num_layers);
     texture.sampler.address_mode_u = AddressMode::Repeat;
     texture.sampler.address_mode_v = AddressMode::Repeat;
 }
 
-use crate::VoxelType;
-use building_blocks::prelude::*;
+/// Recovered chunks must be checked against the palette before the journal is replaced, or the unsaved edits would be lost.
+fn start_loading_map(
+    mut commands: Commands,
+    config: Res<Config>,
+    voxel_map: Res<SdfVoxelMap>,
+    loading: Res<LoadingMap>,
+    mut state: ResMut<State<EditorState>>,
+) {
+    if let LoadingChunks::Recovered(chunks) = &loading.chunks {
+        if let Some(voxel_type) = voxel_map
+            .palette
+            .find_undefined_voxel_type(chunks.as_slice().iter().map(|(_, chunk)| chunk))
+        {
+            fail_loading(
+                &mut state,
+                format!(
+                    "Map {} uses voxel type {}, which is not in {}",
+                    config.map_file, voxel_type.0, config.palette
+                ),
+            );
+            return;
+        }
+    }
 
-fn initialize_editor(mut commands: Commands, mut voxel_editor: VoxelEditor, config: Res<Config>) {
-    // TODO: remove this once we can create voxels out of thin air
-    println!("Initializing voxels");
-    let write_extent = Extent3i::from_min_and_shape(PointN([0, 0, 0]), PointN([64, 64, 64]));
-    voxel_editor.edit_extent_and_touch_neighbors(write_extent, |_p, (voxel_type, dist)| {
-        *voxel_type = VoxelType(2);
-        *dist = Sd8::from(-10.0);
-    });
+    match EditJournal::create(
+        journal_path(&config.map_file),
+        voxel_map.voxels.chunk_shape(),
+        config.chunk_cache.compression(),
+    ) {
+        Ok(journal) => commands.insert_resource(journal),
+        Err(e) => println!(
+            "WARNING: Failed to create edit journal for {}; edits won't be recoverable after a crash: {}",
+            config.map_file, e
+        ),
+    }
+}
+
+fn load_map_chunks(
+    mut commands: Commands,
+    config: Res<Config>,
+    mut loading: ResMut<LoadingMap>,
+    mut voxel_editor: VoxelEditor,
+    journal: Option<ResMut<EditJournal>>,
+    mut state: ResMut<State<EditorState>>,
+) {
+    let mut num_inserted = 0;
+    let mut done = false;
+    while num_inserted < LOAD_CHUNKS_PER_FRAME {
+        let (chunk_key, chunk) = match loading.chunks.read_chunk() {
+            Ok(Some(c)) => c,
+            Ok(None) => {
+                done = true;
+                break;
+            }
+            // Don't fall back to a partial map here, since saving would clobber the file we failed to read.
+            Err(e) => {
+                fail_loading(
+                    &mut state,
+                    format!("Failed to open map {}: {}", config.map_file, e),
+                );
+                return;
+            }
+        };
+        // Voxels of undefined types would be drawn as empty and then lost on the next save.
+        if let Some(voxel_type) = voxel_editor
+            .map
+            .palette
+            .find_undefined_voxel_type(std::iter::once(&chunk))
+        {
+            fail_loading(
+                &mut state,
+                format!(
+                    "Map {} uses voxel type {}, which is not in {}",
+                    config.map_file, voxel_type.0, config.palette
+                ),
+            );
+            return;
+        }
+        // Nothing has been meshed yet, so the neighbors don't need to be touched.
+        voxel_editor.insert_chunk(chunk_key.minimum, chunk);
+        num_inserted += 1;
+    }
+    loading.num_loaded += num_inserted;
+
+    if num_inserted > 0 && !loading.recover {
+        if let Some(mut journal) = journal {
+            journal.skip_next_merge();
+        }
+    }
+
+    if !done {
+        return;
+    }
+
+    if loading.num_loaded == 0 {
+        // TODO: remove this once we can create voxels out of thin air
+        println!("Initializing voxels");
+        let write_extent = Extent3i::from_min_and_shape(PointN([0, 0, 0]), PointN([64, 64, 64]));
+        voxel_editor.edit_extent_and_touch_neighbors(write_extent, |_p, (voxel_type, dist)| {
+            *voxel_type = VoxelType(2);
+            *dist = Sd8::from(-10.0);
+        });
+    } else {
+        println!("Loaded {} chunks", loading.num_loaded);
+    }
+    commands.remove_resource::<LoadingMap>();
+    state.set(EditorState::Editing).unwrap();
+}
 
+fn initialize_editor(mut commands: Commands, config: Res<Config>) {
     create_lights(&mut commands);
     initialize_camera(&mut commands, config.camera);
 }
@@ -0,0 +1,8 @@
+mod log;
+mod plugin;
+
+pub use log::{
+    open_session, read_session, SessionError, SessionEvent, SessionFrame, SessionHeader,
+    SessionWriter, SESSION_VERSION,
+};
+pub use plugin::{SessionRecorderPlugin, SessionReplayPlugin};
@@ -0,0 +1,218 @@
+use crate::{
+    geometry::Ray3, ClipboardEvents, CursorRay, DragFaceEvents, MaterialPainterEvents,
+    SelectionEvents, SlopeEvents, SmartTileEvents, TerraformerEvents, TileEvents, ToolSwitchEvents,
+    UndoEvents,
+};
+
+use bevy::math::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+pub const SESSION_VERSION: u32 = 1;
+
+/// The first line of a session log.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SessionHeader {
+    pub version: u32,
+    /// The map that was open when recording started. Replays only match the recording when they start from the same map.
+    pub map_file: String,
+}
+
+/// An edit tool event, as it was sent by one of the input maps.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum SessionEvent {
+    Terraformer(TerraformerEvents),
+    DragFace(DragFaceEvents),
+    Selection(SelectionEvents),
+    Clipboard(ClipboardEvents),
+    MaterialPainter(MaterialPainterEvents),
+    Tile(TileEvents),
+    Slope(SlopeEvents),
+    SmartTiles(SmartTileEvents),
+    ToolSwitch(ToolSwitchEvents),
+    Undo(UndoEvents),
+}
+
+/// Everything that the edit tools depend on during a single frame.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct SessionFrame {
+    /// Seconds since recording started. Replays go frame by frame and ignore this.
+    pub time_secs: f64,
+    /// The origin and direction of the `CursorRay`.
+    pub cursor_ray: Option<[[f32; 3]; 2]>,
+    pub events: Vec<SessionEvent>,
+}
+
+impl SessionFrame {
+    pub fn new(time_secs: f64, cursor_ray: CursorRay) -> Self {
+        Self {
+            time_secs,
+            cursor_ray: cursor_ray
+                .0
+                .map(|ray| [ray.origin.into(), ray.direction.into()]),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn cursor_ray(&self) -> CursorRay {
+        CursorRay(
+            self.cursor_ray
+                .map(|[origin, direction]| Ray3::new(Vec3::from(origin), Vec3::from(direction))),
+        )
+    }
+}
+
+/// Writes a session log with one JSON value per line: the `SessionHeader`, followed by a `SessionFrame` for every frame.
+pub struct SessionWriter<W> {
+    writer: W,
+}
+
+impl SessionWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, header: &SessionHeader) -> Result<Self, SessionError> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> SessionWriter<W> {
+    pub fn new(writer: W, header: &SessionHeader) -> Result<Self, SessionError> {
+        let mut session_writer = Self { writer };
+        session_writer.write_line(header)?;
+
+        Ok(session_writer)
+    }
+
+    /// Each frame is flushed as soon as it's written, so a log ends at the last frame before a crash.
+    pub fn write_frame(&mut self, frame: &SessionFrame) -> Result<(), SessionError> {
+        self.write_line(frame)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> Result<(), SessionError> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+pub fn read_session(
+    reader: impl BufRead,
+) -> Result<(SessionHeader, Vec<SessionFrame>), SessionError> {
+    let mut lines = reader.lines();
+    let header: SessionHeader = match lines.next() {
+        Some(line) => serde_json::from_str(&line?)?,
+        None => return Err(SessionError::MissingHeader),
+    };
+    if header.version != SESSION_VERSION {
+        return Err(SessionError::UnsupportedVersion(header.version));
+    }
+
+    let mut frames = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        frames.push(serde_json::from_str(&line)?);
+    }
+
+    Ok((header, frames))
+}
+
+pub fn open_session(
+    path: impl AsRef<Path>,
+) -> Result<(SessionHeader, Vec<SessionFrame>), SessionError> {
+    read_session(BufReader::new(File::open(path)?))
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    MissingHeader,
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::Io(e) => write!(f, "I/O error: {}", e),
+            SessionError::Json(e) => write!(f, "Failed to (de)serialize session: {}", e),
+            SessionError::MissingHeader => write!(f, "Session log is empty"),
+            SessionError::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported session version {} (expected {})",
+                v, SESSION_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<io::Error> for SessionError {
+    fn from(e: io::Error) -> Self {
+        SessionError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SessionError {
+    fn from(e: serde_json::Error) -> Self {
+        SessionError::Json(e)
+    }
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::picking::VoxelFace;
+
+    use building_blocks::core::{PointN, SignedAxis3};
+
+    #[test]
+    fn frames_round_trip() {
+        let header = SessionHeader {
+            version: SESSION_VERSION,
+            map_file: "map.bbmap".to_string(),
+        };
+        let face = VoxelFace {
+            point: PointN([1, -2, 3]),
+            normal: SignedAxis3::from_vector(PointN([0, -1, 0])).unwrap(),
+        };
+        let ray = Ray3::new(Vec3::new(0.0, 1.0, 2.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut frame = SessionFrame::new(0.5, CursorRay(Some(ray)));
+        frame.events = vec![
+            SessionEvent::ToolSwitch(ToolSwitchEvents::SwitchToDragFace),
+            SessionEvent::Selection(SelectionEvents::SelectFirstCorner(face)),
+            SessionEvent::DragFace(DragFaceEvents::UpdateDragFace(PointN([4, 5, 6]))),
+            SessionEvent::Terraformer(TerraformerEvents::ChangeEditRadius(-1)),
+            SessionEvent::Undo(UndoEvents::Redo),
+        ];
+        let frames = vec![frame, SessionFrame::new(0.75, CursorRay(None))];
+
+        let mut writer = SessionWriter::new(Vec::new(), &header).unwrap();
+        for frame in frames.iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        let bytes = writer.into_inner();
+
+        let (read_header, read_frames) = read_session(bytes.as_slice()).unwrap();
+        assert_eq!(read_header, header);
+        assert_eq!(read_frames, frames);
+        assert_eq!(read_frames[0].cursor_ray(), CursorRay(Some(ray)));
+    }
+}
@@ -0,0 +1,220 @@
+use super::{
+    open_session, SessionEvent, SessionFrame, SessionHeader, SessionWriter, SESSION_VERSION,
+};
+
+use crate::{
+    ClipboardEvents, Config, CursorRay, DragFaceEvents, EditToolsPlugin, MaterialPainterEvents,
+    SelectionEvents, SlopeEvents, SmartTileEvents, StatePlugin, TerraformerEvents, TileEvents,
+    ToolSwitchEvents, UndoEvents,
+};
+
+use bevy::{app::prelude::*, core::Time, ecs::prelude::*};
+use std::fs::File;
+use std::io::BufWriter;
+
+/// Records the edit tool events and the `CursorRay` of every frame to `Config::session.record_path`, if it's set.
+pub struct SessionRecorderPlugin;
+
+impl SessionRecorderPlugin {
+    fn initialize(mut commands: Commands, config: Res<Config>, time: Res<Time>) {
+        let path = if let Some(path) = &config.session.record_path {
+            path
+        } else {
+            return;
+        };
+        let header = SessionHeader {
+            version: SESSION_VERSION,
+            map_file: config.map_file.clone(),
+        };
+        match SessionWriter::create(path, &header) {
+            Ok(writer) => {
+                println!("Recording session to {}", path);
+                commands.insert_resource(SessionRecorder {
+                    writer,
+                    start_secs: time.seconds_since_startup(),
+                });
+            }
+            Err(e) => println!("Failed to record session to {}: {}", path, e),
+        }
+    }
+}
+
+impl StatePlugin for SessionRecorderPlugin {
+    fn add_enter_systems(set: SystemSet) -> SystemSet {
+        set.with_system(Self::initialize.system())
+    }
+
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(session_recorder_system.system().after("edit_tool_input"))
+    }
+}
+
+struct SessionRecorder {
+    writer: SessionWriter<BufWriter<File>>,
+    start_secs: f64,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn session_recorder_system(
+    mut commands: Commands,
+    config: Res<Config>,
+    time: Res<Time>,
+    cursor_ray: Res<CursorRay>,
+    recorder: Option<ResMut<SessionRecorder>>,
+    mut terraformer_events: EventReader<TerraformerEvents>,
+    mut drag_face_events: EventReader<DragFaceEvents>,
+    mut selection_events: EventReader<SelectionEvents>,
+    mut clipboard_events: EventReader<ClipboardEvents>,
+    mut material_painter_events: EventReader<MaterialPainterEvents>,
+    mut tile_events: EventReader<TileEvents>,
+    mut slope_events: EventReader<SlopeEvents>,
+    mut smart_tile_events: EventReader<SmartTileEvents>,
+    mut tool_switch_events: EventReader<ToolSwitchEvents>,
+    mut undo_events: EventReader<UndoEvents>,
+) {
+    let mut recorder = if let Some(r) = recorder {
+        r
+    } else {
+        return;
+    };
+
+    let mut frame = SessionFrame::new(
+        time.seconds_since_startup() - recorder.start_secs,
+        *cursor_ray,
+    );
+    frame.events.extend(
+        tool_switch_events
+            .iter()
+            .map(|e| SessionEvent::ToolSwitch(*e))
+            .chain(undo_events.iter().map(|e| SessionEvent::Undo(*e)))
+            .chain(selection_events.iter().map(|e| SessionEvent::Selection(*e)))
+            .chain(clipboard_events.iter().map(|e| SessionEvent::Clipboard(*e)))
+            .chain(
+                material_painter_events
+                    .iter()
+                    .map(|e| SessionEvent::MaterialPainter(*e)),
+            )
+            .chain(tile_events.iter().map(|e| SessionEvent::Tile(*e)))
+            .chain(slope_events.iter().map(|e| SessionEvent::Slope(*e)))
+            .chain(
+                smart_tile_events
+                    .iter()
+                    .map(|e| SessionEvent::SmartTiles(*e)),
+            )
+            .chain(
+                terraformer_events
+                    .iter()
+                    .map(|e| SessionEvent::Terraformer(*e)),
+            )
+            .chain(drag_face_events.iter().map(|e| SessionEvent::DragFace(*e))),
+    );
+
+    if let Err(e) = recorder.writer.write_frame(&frame) {
+        println!(
+            "Stopped recording session to {}: {}",
+            config.session.record_path.as_deref().unwrap_or_default(),
+            e
+        );
+        commands.remove_resource::<SessionRecorder>();
+    }
+}
+
+/// Replays the session log at `Config::session.replay_path` by sending its events and setting the `CursorRay` one frame at a
+/// time. This takes the place of the `EditToolsPlugin`'s update systems, since the input maps would send events of their own.
+///
+/// Replays only match the recording when they start from the same map.
+pub struct SessionReplayPlugin;
+
+impl SessionReplayPlugin {
+    fn initialize(mut commands: Commands, config: Res<Config>) {
+        let path = if let Some(path) = &config.session.replay_path {
+            path
+        } else {
+            return;
+        };
+        let (header, frames) =
+            open_session(path).unwrap_or_else(|e| panic!("Failed to open session {}: {}", path, e));
+        if header.map_file != config.map_file {
+            println!(
+                "WARNING: Session {} was recorded on map {}, but replaying on {}",
+                path, header.map_file, config.map_file
+            );
+        }
+        println!("Replaying {} frames from session {}", frames.len(), path);
+
+        commands.insert_resource(SessionReplay {
+            frames: frames.into_iter(),
+            finished: false,
+        });
+    }
+}
+
+impl StatePlugin for SessionReplayPlugin {
+    fn add_enter_systems(set: SystemSet) -> SystemSet {
+        set.with_system(Self::initialize.system())
+    }
+
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        // Like the input maps, but the cursor ray also needs to be replaced before anything picks voxels with it.
+        EditToolsPlugin::add_tool_systems(set).with_system(
+            session_replay_system
+                .system()
+                .label("edit_tool_input")
+                .after("cursor_ray")
+                .before("voxel_picking"),
+        )
+    }
+}
+
+struct SessionReplay {
+    frames: std::vec::IntoIter<SessionFrame>,
+    finished: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn session_replay_system(
+    replay: Option<ResMut<SessionReplay>>,
+    mut cursor_ray: ResMut<CursorRay>,
+    mut terraformer_events: EventWriter<TerraformerEvents>,
+    mut drag_face_events: EventWriter<DragFaceEvents>,
+    mut selection_events: EventWriter<SelectionEvents>,
+    mut clipboard_events: EventWriter<ClipboardEvents>,
+    mut material_painter_events: EventWriter<MaterialPainterEvents>,
+    mut tile_events: EventWriter<TileEvents>,
+    mut slope_events: EventWriter<SlopeEvents>,
+    mut smart_tile_events: EventWriter<SmartTileEvents>,
+    mut tool_switch_events: EventWriter<ToolSwitchEvents>,
+    mut undo_events: EventWriter<UndoEvents>,
+) {
+    let mut replay = if let Some(r) = replay {
+        r
+    } else {
+        return;
+    };
+
+    let frame = if let Some(frame) = replay.frames.next() {
+        frame
+    } else {
+        if !replay.finished {
+            println!("Finished replaying session");
+            replay.finished = true;
+        }
+        return;
+    };
+
+    *cursor_ray = frame.cursor_ray();
+    for event in frame.events.into_iter() {
+        match event {
+            SessionEvent::Terraformer(e) => terraformer_events.send(e),
+            SessionEvent::DragFace(e) => drag_face_events.send(e),
+            SessionEvent::Selection(e) => selection_events.send(e),
+            SessionEvent::Clipboard(e) => clipboard_events.send(e),
+            SessionEvent::MaterialPainter(e) => material_painter_events.send(e),
+            SessionEvent::Tile(e) => tile_events.send(e),
+            SessionEvent::Slope(e) => slope_events.send(e),
+            SessionEvent::SmartTiles(e) => smart_tile_events.send(e),
+            SessionEvent::ToolSwitch(e) => tool_switch_events.send(e),
+            SessionEvent::Undo(e) => undo_events.send(e),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+mod convert;
+mod format;
+mod plugin;
+
+pub use convert::{
+    sdf_to_vox_model, vox_model_shape, vox_model_to_sdf, VoxColorMapping, VoxMapping, VoxTypeMap,
+};
+pub use format::{
+    read_vox, write_vox, VoxError, VoxFile, VoxModel, VoxVoxel, MAX_VOX_MODEL_SIZE, VOX_MAGIC,
+    VOX_VERSION,
+};
+pub use plugin::{VoxEvents, VoxPlugin};
@@ -0,0 +1,252 @@
+use super::{VoxError, VoxModel, VoxVoxel, MAX_VOX_MODEL_SIZE};
+
+use crate::{
+    ambient_sdf_array, voxel::EMPTY_VOXEL_TYPE, PaletteAsset, SdfArray, SdfVoxelPalette, VoxelType,
+};
+
+use building_blocks::{prelude::*, storage::Sd8};
+use serde::Deserialize;
+
+/// Maps MagicaVoxel color indices to voxel types by name.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct VoxMapping {
+    pub colors: Vec<VoxColorMapping>,
+    /// The voxel type for color indices that aren't in `colors`. If `None`, those voxels aren't imported.
+    pub default_voxel_type: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct VoxColorMapping {
+    pub color_index: u8,
+    pub voxel_type: String,
+}
+
+/// A `VoxMapping` resolved against a palette, for converting in both directions.
+#[derive(Clone, Debug)]
+pub struct VoxTypeMap {
+    color_types: Vec<Option<VoxelType>>,
+    type_colors: Vec<Option<u8>>,
+}
+
+impl VoxMapping {
+    pub fn resolve(&self, palette: &PaletteAsset) -> Result<VoxTypeMap, VoxError> {
+        let find_type = |name: &str| {
+            palette
+                .voxel_type_by_name(name)
+                .ok_or_else(|| VoxError::UnknownVoxelType(name.to_string()))
+        };
+
+        let default_type = self
+            .default_voxel_type
+            .as_ref()
+            .map(|name| find_type(name))
+            .transpose()?;
+        let mut color_types = vec![default_type; 256];
+        let mut type_colors = vec![None; 256];
+        for mapping in self.colors.iter() {
+            let voxel_type = find_type(&mapping.voxel_type)?;
+            color_types[mapping.color_index as usize] = Some(voxel_type);
+            // The first color listed for a type is the one it gets exported with.
+            type_colors[voxel_type.0 as usize].get_or_insert(mapping.color_index);
+        }
+
+        Ok(VoxTypeMap {
+            color_types,
+            type_colors,
+        })
+    }
+}
+
+impl VoxTypeMap {
+    pub fn voxel_type(&self, color_index: u8) -> Option<VoxelType> {
+        self.color_types[color_index as usize]
+    }
+
+    /// Voxel types without a mapped color are exported with a color index equal to their `VoxelType`.
+    pub fn color_index(&self, voxel_type: VoxelType) -> u8 {
+        self.type_colors[voxel_type.0 as usize].unwrap_or(voxel_type.0)
+    }
+}
+
+/// The shape of `model` in the editor's Y-up coordinates.
+pub fn vox_model_shape(model: &VoxModel) -> Point3i {
+    let [x, y, z] = model.size;
+
+    PointN([x as i32, z as i32, y as i32])
+}
+
+/// Converts `model` into an SDF with its minimum at `origin`. MagicaVoxel is Z-up, so its Z axis becomes Y and its Y axis
+/// becomes -Z. Solid voxels are at distance -1 and everything else is ambient, so surface nets puts the surface halfway
+/// between solid and empty voxels.
+pub fn vox_model_to_sdf(model: &VoxModel, type_map: &VoxTypeMap, origin: Point3i) -> SdfArray {
+    let shape = vox_model_shape(model);
+    let mut sdf = ambient_sdf_array(Extent3i::from_min_and_shape(origin, shape));
+    for v in model.voxels.iter() {
+        if let Some(voxel_type) = type_map.voxel_type(v.color_index) {
+            let [x, y, z] = v.position;
+            let p = origin + PointN([x as i32, z as i32, shape.z() - 1 - y as i32]);
+            let (v_type, v_dist) = sdf.get_mut(p);
+            *v_type = voxel_type;
+            *v_dist = Sd8::NEG_ONE;
+        }
+    }
+
+    sdf
+}
+
+/// Converts every solid voxel in `voxels` into a MagicaVoxel model. This is the inverse of `vox_model_to_sdf`.
+pub fn sdf_to_vox_model(
+    voxels: &SdfArray,
+    palette: &SdfVoxelPalette,
+    type_map: &VoxTypeMap,
+) -> Result<VoxModel, VoxError> {
+    let extent = *voxels.extent();
+    let shape = extent.shape;
+    let size = [shape.x() as u32, shape.z() as u32, shape.y() as u32];
+    if size.iter().any(|&s| s > MAX_VOX_MODEL_SIZE) {
+        return Err(VoxError::ModelTooLarge(size));
+    }
+
+    let mut model = VoxModel {
+        size,
+        voxels: Vec::new(),
+    };
+    voxels.for_each(&extent, |p: Point3i, (v_type, v_dist): (VoxelType, Sd8)| {
+        let is_solid = v_type != EMPTY_VOXEL_TYPE
+            && v_dist.0 < 0
+            && palette
+                .infos
+                .get(v_type.0 as usize)
+                .map_or(false, |info| !info.is_empty);
+        if !is_solid {
+            return;
+        }
+        let local = p - extent.minimum;
+        model.voxels.push(VoxVoxel {
+            position: [
+                local.x() as u8,
+                (shape.z() - 1 - local.z()) as u8,
+                local.y() as u8,
+            ],
+            color_index: type_map.color_index(v_type),
+        });
+    });
+
+    Ok(model)
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{VoxelMaterial, VoxelTypeInfo};
+
+    fn test_palette() -> PaletteAsset {
+        ron::de::from_str(
+            r#"(
+                base_color_textures: ["base_color.png"],
+                material_layers: ["grass", "rock"],
+                voxel_types: [
+                    (name: "air", is_empty: true),
+                    (name: "grass", is_empty: false, material: Some("grass")),
+                    (name: "rock", is_empty: false, material: Some("rock")),
+                ],
+            )"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn model_round_trips_through_sdf() {
+        let mapping = VoxMapping {
+            colors: vec![
+                VoxColorMapping {
+                    color_index: 7,
+                    voxel_type: "grass".to_string(),
+                },
+                VoxColorMapping {
+                    color_index: 9,
+                    voxel_type: "rock".to_string(),
+                },
+            ],
+            default_voxel_type: None,
+        };
+        let type_map = mapping.resolve(&test_palette()).unwrap();
+        let model = VoxModel {
+            size: [3, 2, 4],
+            voxels: vec![
+                VoxVoxel {
+                    position: [0, 0, 0],
+                    color_index: 7,
+                },
+                VoxVoxel {
+                    position: [2, 1, 3],
+                    color_index: 9,
+                },
+            ],
+        };
+
+        let origin = PointN([10, 20, 30]);
+        let sdf = vox_model_to_sdf(&model, &type_map, origin);
+        assert_eq!(
+            *sdf.extent(),
+            Extent3i::from_min_and_shape(origin, PointN([3, 4, 2]))
+        );
+        // Vox Y = 0 is the far end of world Z.
+        assert_eq!(sdf.get(PointN([10, 20, 31])), (VoxelType(1), Sd8::NEG_ONE));
+        assert_eq!(sdf.get(PointN([12, 23, 30])), (VoxelType(2), Sd8::NEG_ONE));
+
+        let palette = SdfVoxelPalette {
+            infos: vec![
+                VoxelTypeInfo {
+                    is_empty: true,
+                    material: VoxelMaterial::NULL,
+                },
+                VoxelTypeInfo {
+                    is_empty: false,
+                    material: VoxelMaterial(0),
+                },
+                VoxelTypeInfo {
+                    is_empty: false,
+                    material: VoxelMaterial(1),
+                },
+            ],
+        };
+        let mut exported = sdf_to_vox_model(&sdf, &palette, &type_map).unwrap();
+        exported.voxels.sort_by_key(|v| v.position);
+        assert_eq!(exported, model);
+    }
+
+    #[test]
+    fn unmapped_colors_use_the_default_type() {
+        let mapping = VoxMapping {
+            colors: Vec::new(),
+            default_voxel_type: Some("rock".to_string()),
+        };
+        let type_map = mapping.resolve(&test_palette()).unwrap();
+
+        assert_eq!(type_map.voxel_type(200), Some(VoxelType(2)));
+        assert_eq!(type_map.color_index(VoxelType(2)), 2);
+    }
+
+    #[test]
+    fn rejects_unknown_voxel_types() {
+        let mapping = VoxMapping {
+            colors: Vec::new(),
+            default_voxel_type: Some("lava".to_string()),
+        };
+
+        assert!(matches!(
+            mapping.resolve(&test_palette()),
+            Err(VoxError::UnknownVoxelType(t)) if t == "lava"
+        ));
+    }
+}
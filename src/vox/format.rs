@@ -0,0 +1,366 @@
+use std::io::{self, Read, Write};
+
+/// Every `.vox` file starts with these bytes.
+pub const VOX_MAGIC: [u8; 4] = *b"VOX ";
+
+/// The version written by `write_vox`. Readers accept any version.
+pub const VOX_VERSION: u32 = 150;
+
+/// The largest model dimension that MagicaVoxel supports.
+pub const MAX_VOX_MODEL_SIZE: u32 = 256;
+
+/// The contents of a MagicaVoxel `.vox` file that we care about. Scene graph, material and layer chunks are skipped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VoxFile {
+    pub models: Vec<VoxModel>,
+    /// `palette[i]` is the RGBA color of color index `i + 1`. `None` means the file uses MagicaVoxel's default palette.
+    pub palette: Option<Vec<[u8; 4]>>,
+}
+
+/// A single model in Z-up coordinates, as MagicaVoxel stores it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<VoxVoxel>,
+}
+
+/// A non-empty voxel. Color indices are in `1..=255`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct VoxVoxel {
+    pub position: [u8; 3],
+    pub color_index: u8,
+}
+
+#[derive(Debug)]
+pub enum VoxError {
+    Io(io::Error),
+    BadMagic,
+    MissingMainChunk,
+    /// An XYZI chunk without a preceding SIZE chunk.
+    VoxelsWithoutSize,
+    ModelTooLarge([u32; 3]),
+    VoxelOutOfBounds {
+        model: usize,
+        position: [u8; 3],
+    },
+    BadChunkSize {
+        id: [u8; 4],
+        size: u32,
+    },
+    UnknownVoxelType(String),
+    MissingModel(usize),
+}
+
+impl std::fmt::Display for VoxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoxError::Io(e) => write!(f, "{}", e),
+            VoxError::BadMagic => write!(f, "Not a MagicaVoxel file"),
+            VoxError::MissingMainChunk => write!(f, "Missing MAIN chunk"),
+            VoxError::VoxelsWithoutSize => write!(f, "XYZI chunk without a SIZE chunk"),
+            VoxError::ModelTooLarge(size) => write!(
+                f,
+                "Model size {:?} exceeds the maximum of {} per axis",
+                size, MAX_VOX_MODEL_SIZE
+            ),
+            VoxError::VoxelOutOfBounds { model, position } => {
+                write!(f, "Voxel at {:?} is outside of model {}", position, model)
+            }
+            VoxError::BadChunkSize { id, size } => write!(
+                f,
+                "{} chunk has invalid size {}",
+                String::from_utf8_lossy(id),
+                size
+            ),
+            VoxError::UnknownVoxelType(t) => write!(f, "Unknown voxel type {}", t),
+            VoxError::MissingModel(i) => write!(f, "There is no model {}", i),
+        }
+    }
+}
+
+impl std::error::Error for VoxError {}
+
+impl From<io::Error> for VoxError {
+    fn from(e: io::Error) -> Self {
+        VoxError::Io(e)
+    }
+}
+
+pub fn read_vox(mut reader: impl Read) -> Result<VoxFile, VoxError> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != VOX_MAGIC {
+        return Err(VoxError::BadMagic);
+    }
+    let _version = read_u32(&mut reader)?;
+
+    let (id, content_size, children_size) = read_chunk_header(&mut reader)?;
+    if &id != b"MAIN" {
+        return Err(VoxError::MissingMainChunk);
+    }
+    skip(&mut reader, content_size)?;
+
+    // Every other chunk is a child of MAIN, but there's no need to track nesting since we only read leaf chunks.
+    let mut children = reader.take(children_size as u64);
+    let mut file = VoxFile::default();
+    let mut pending_size = None;
+    loop {
+        let (id, content_size, children_size) = match read_chunk_header(&mut children) {
+            Ok(header) => header,
+            Err(VoxError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        match &id {
+            b"SIZE" => {
+                if content_size != 12 {
+                    return Err(VoxError::BadChunkSize {
+                        id,
+                        size: content_size,
+                    });
+                }
+                let size = [
+                    read_u32(&mut children)?,
+                    read_u32(&mut children)?,
+                    read_u32(&mut children)?,
+                ];
+                if size.iter().any(|&s| s > MAX_VOX_MODEL_SIZE) {
+                    return Err(VoxError::ModelTooLarge(size));
+                }
+                pending_size = Some(size);
+            }
+            b"XYZI" => {
+                let size = pending_size.take().ok_or(VoxError::VoxelsWithoutSize)?;
+                let num_voxels = read_u32(&mut children)?;
+                if num_voxels.checked_mul(4).and_then(|n| n.checked_add(4)) != Some(content_size) {
+                    return Err(VoxError::BadChunkSize {
+                        id,
+                        size: content_size,
+                    });
+                }
+                // The count comes straight from the file, so don't trust it with an allocation until it fits in the model.
+                let volume: u64 = size.iter().map(|&s| s as u64).product();
+                if num_voxels as u64 > volume {
+                    return Err(VoxError::BadChunkSize {
+                        id,
+                        size: content_size,
+                    });
+                }
+                let mut voxels = Vec::with_capacity(num_voxels as usize);
+                for _ in 0..num_voxels {
+                    let mut xyzi = [0; 4];
+                    children.read_exact(&mut xyzi)?;
+                    let position = [xyzi[0], xyzi[1], xyzi[2]];
+                    if position
+                        .iter()
+                        .zip(size.iter())
+                        .any(|(&p, &s)| p as u32 >= s)
+                    {
+                        return Err(VoxError::VoxelOutOfBounds {
+                            model: file.models.len(),
+                            position,
+                        });
+                    }
+                    voxels.push(VoxVoxel {
+                        position,
+                        color_index: xyzi[3],
+                    });
+                }
+                file.models.push(VoxModel { size, voxels });
+            }
+            b"RGBA" => {
+                if content_size != 4 * 256 {
+                    return Err(VoxError::BadChunkSize {
+                        id,
+                        size: content_size,
+                    });
+                }
+                let mut palette = vec![[0; 4]; 256];
+                for color in palette.iter_mut() {
+                    children.read_exact(color)?;
+                }
+                file.palette = Some(palette);
+            }
+            _ => skip(&mut children, content_size)?,
+        }
+        // The chunks we read don't have children, but an unknown chunk might.
+        skip(&mut children, children_size)?;
+    }
+
+    Ok(file)
+}
+
+pub fn write_vox(mut writer: impl Write, file: &VoxFile) -> Result<(), VoxError> {
+    let mut children = Vec::new();
+    for model in file.models.iter() {
+        if model.size.iter().any(|&s| s > MAX_VOX_MODEL_SIZE) {
+            return Err(VoxError::ModelTooLarge(model.size));
+        }
+
+        write_chunk_header(&mut children, b"SIZE", 12, 0)?;
+        for &s in model.size.iter() {
+            write_u32(&mut children, s)?;
+        }
+
+        let num_voxels = model.voxels.len() as u32;
+        write_chunk_header(&mut children, b"XYZI", 4 + 4 * num_voxels, 0)?;
+        write_u32(&mut children, num_voxels)?;
+        for v in model.voxels.iter() {
+            children.write_all(&[v.position[0], v.position[1], v.position[2], v.color_index])?;
+        }
+    }
+    if let Some(palette) = &file.palette {
+        write_chunk_header(&mut children, b"RGBA", 4 * 256, 0)?;
+        for i in 0..256 {
+            children.write_all(palette.get(i).unwrap_or(&[0; 4]))?;
+        }
+    }
+
+    writer.write_all(&VOX_MAGIC)?;
+    write_u32(&mut writer, VOX_VERSION)?;
+    write_chunk_header(&mut writer, b"MAIN", 0, children.len() as u32)?;
+    writer.write_all(&children)?;
+
+    Ok(())
+}
+
+fn read_chunk_header(reader: &mut impl Read) -> Result<([u8; 4], u32, u32), VoxError> {
+    let mut id = [0; 4];
+    reader.read_exact(&mut id)?;
+    let content_size = read_u32(reader)?;
+    let children_size = read_u32(reader)?;
+
+    Ok((id, content_size, children_size))
+}
+
+fn write_chunk_header(
+    writer: &mut impl Write,
+    id: &[u8; 4],
+    content_size: u32,
+    children_size: u32,
+) -> io::Result<()> {
+    writer.write_all(id)?;
+    write_u32(writer, content_size)?;
+    write_u32(writer, children_size)
+}
+
+fn skip(reader: &mut impl Read, num_bytes: u32) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(num_bytes as u64), &mut io::sink())?;
+    if skipped < num_bytes as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u32(writer: &mut impl Write, x: u32) -> io::Result<()> {
+    writer.write_all(&x.to_le_bytes())
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TWO_VOXELS: &[u8] = include_bytes!("../../tests/fixtures/two_voxels.vox");
+    const SCENE_GRAPH: &[u8] = include_bytes!("../../tests/fixtures/scene_graph.vox");
+    /// A 2x3x4 model whose XYZI chunk claims almost 2^30 voxels, but only has 2.
+    const OVERSIZED_XYZI: &[u8] = include_bytes!("../../tests/fixtures/oversized_xyzi.vox");
+
+    #[test]
+    fn reads_size_voxels_and_palette() {
+        let file = read_vox(TWO_VOXELS).unwrap();
+
+        assert_eq!(
+            file.models,
+            vec![VoxModel {
+                size: [2, 3, 4],
+                voxels: vec![
+                    VoxVoxel {
+                        position: [0, 0, 0],
+                        color_index: 1,
+                    },
+                    VoxVoxel {
+                        position: [1, 2, 3],
+                        color_index: 5,
+                    },
+                ],
+            }]
+        );
+        let palette = file.palette.unwrap();
+        assert_eq!(palette[0], [255, 0, 0, 255]);
+        assert_eq!(palette[4], [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn skips_unknown_chunks_and_reads_every_model() {
+        let file = read_vox(SCENE_GRAPH).unwrap();
+
+        assert_eq!(file.palette, None);
+        assert_eq!(file.models.len(), 2);
+        assert_eq!(file.models[0].size, [1, 1, 1]);
+        assert_eq!(file.models[1].size, [3, 1, 2]);
+        assert_eq!(
+            file.models[1].voxels,
+            vec![VoxVoxel {
+                position: [2, 0, 1],
+                color_index: 9,
+            }]
+        );
+    }
+
+    #[test]
+    fn write_then_read_round_trips() {
+        let file = read_vox(TWO_VOXELS).unwrap();
+        let mut bytes = Vec::new();
+        write_vox(&mut bytes, &file).unwrap();
+
+        assert_eq!(read_vox(bytes.as_slice()).unwrap(), file);
+    }
+
+    #[test]
+    fn rejects_out_of_bounds_voxels() {
+        let mut file = read_vox(TWO_VOXELS).unwrap();
+        file.models[0].voxels[1].position = [2, 0, 0];
+        let mut bytes = Vec::new();
+        write_vox(&mut bytes, &file).unwrap();
+
+        assert!(matches!(
+            read_vox(bytes.as_slice()),
+            Err(VoxError::VoxelOutOfBounds { model: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_more_voxels_than_fit_in_the_model() {
+        assert!(matches!(
+            read_vox(OVERSIZED_XYZI),
+            Err(VoxError::BadChunkSize { id, .. }) if &id == b"XYZI"
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_voxels() {
+        // Claim 3 voxels, which fit in the model, but there are still only 2.
+        let mut bytes = OVERSIZED_XYZI.to_vec();
+        bytes[48..52].copy_from_slice(&16u32.to_le_bytes());
+        bytes[56..60].copy_from_slice(&3u32.to_le_bytes());
+
+        assert!(matches!(
+            read_vox(bytes.as_slice()),
+            Err(VoxError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+}
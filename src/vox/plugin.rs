@@ -0,0 +1,125 @@
+use super::{read_vox, sdf_to_vox_model, vox_model_to_sdf, write_vox, VoxError, VoxFile};
+
+use crate::{
+    Config, PaletteAsset, PaletteHandle, SdfVoxelMap, StatePlugin, VoxConfig, VoxelEditor,
+};
+
+use bevy::{app::prelude::*, asset::prelude::*, ecs::prelude::*, input::prelude::*};
+use building_blocks::prelude::*;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+};
+
+/// Imports and exports MagicaVoxel models as configured by `Config::vox`. Depends on the `MapIoPlugin`.
+pub struct VoxPlugin;
+
+impl VoxPlugin {
+    pub fn register_events(app: &mut AppBuilder) {
+        app.add_event::<VoxEvents>();
+    }
+}
+
+impl StatePlugin for VoxPlugin {
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(vox_default_input_map.system())
+            .with_system(vox_system.system())
+    }
+}
+
+pub enum VoxEvents {
+    Import,
+    Export,
+}
+
+pub fn vox_default_input_map(mut events: EventWriter<VoxEvents>, keyboard: Res<Input<KeyCode>>) {
+    let ctrl = keyboard.pressed(KeyCode::LControl) || keyboard.pressed(KeyCode::RControl);
+    let shift = keyboard.pressed(KeyCode::LShift) || keyboard.pressed(KeyCode::RShift);
+    if !ctrl || !keyboard.just_pressed(KeyCode::P) {
+        return;
+    }
+
+    if shift {
+        events.send(VoxEvents::Export);
+    } else {
+        events.send(VoxEvents::Import);
+    }
+}
+
+pub fn vox_system(
+    config: Res<Config>,
+    palette_handle: Res<PaletteHandle>,
+    palettes: Res<Assets<PaletteAsset>>,
+    mut voxel_editor: VoxelEditor,
+    mut events: EventReader<VoxEvents>,
+) {
+    let palette = if let Some(p) = palettes.get(&palette_handle.0) {
+        p
+    } else {
+        return;
+    };
+
+    for event in events.iter() {
+        let vox = &config.vox;
+        match event {
+            VoxEvents::Import => match import_vox(vox, palette, &mut voxel_editor) {
+                Ok(extent) => println!("Imported {} into {:?}", vox.import_path, extent),
+                Err(e) => println!("Failed to import {}: {}", vox.import_path, e),
+            },
+            VoxEvents::Export => match export_vox(vox, palette, &*voxel_editor.map) {
+                Ok(()) => println!("Exported {}", vox.export_path),
+                Err(e) => println!("Failed to export {}: {}", vox.export_path, e),
+            },
+        }
+    }
+}
+
+/// Stamps the configured model into the map. Only solid voxels are written, so the model is unioned with whatever is already
+/// there.
+fn import_vox(
+    vox: &VoxConfig,
+    palette: &PaletteAsset,
+    voxel_editor: &mut VoxelEditor,
+) -> Result<Extent3i, VoxError> {
+    let type_map = vox.mapping.resolve(palette)?;
+    let file = read_vox(BufReader::new(File::open(&vox.import_path)?))?;
+    let model = file
+        .models
+        .get(vox.model_index)
+        .ok_or(VoxError::MissingModel(vox.model_index))?;
+
+    let sdf = vox_model_to_sdf(model, &type_map, PointN(vox.origin));
+    let extent = *sdf.extent();
+    voxel_editor.edit_extent_and_touch_neighbors(extent, |p, (voxel_type, dist)| {
+        let (new_type, new_dist) = sdf.get(p);
+        if new_dist.0 < 0 {
+            *voxel_type = new_type;
+            *dist = new_dist;
+        }
+    });
+
+    Ok(extent)
+}
+
+fn export_vox(
+    vox: &VoxConfig,
+    palette: &PaletteAsset,
+    voxel_map: &SdfVoxelMap,
+) -> Result<(), VoxError> {
+    let type_map = vox.mapping.resolve(palette)?;
+    let extent = Extent3i::from_min_and_shape(PointN(vox.export_min), PointN(vox.export_shape));
+    let voxels = voxel_map.copy_extent_without_caching(extent);
+    let model = sdf_to_vox_model(&voxels, &voxel_map.palette, &type_map)?;
+
+    let mut writer = BufWriter::new(File::create(&vox.export_path)?);
+    write_vox(
+        &mut writer,
+        &VoxFile {
+            models: vec![model],
+            palette: None,
+        },
+    )?;
+    writer.flush()?;
+
+    Ok(())
+}
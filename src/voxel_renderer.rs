@@ -1,9 +1,11 @@
 pub mod render_graph;
 
+mod clipmap;
 mod entity;
 mod material;
 mod mesh_generator;
 
+pub use clipmap::*;
 pub use entity::*;
 pub use material::*;
 pub use mesh_generator::*;
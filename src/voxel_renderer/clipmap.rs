@@ -0,0 +1,123 @@
+use bevy::math::prelude::*;
+use building_blocks::{
+    prelude::*,
+    storage::{ChunkKey3, SmallKeyHashSet},
+};
+
+/// Picks the level of detail for every part of the map, starting from the chunks at `top_lod` and splitting any chunk that's
+/// within `clip_radius` chunk widths of the `observer` into its 8 children at the next finer LOD. The selected chunks cover the
+/// same space as the top level chunks without overlapping, and the LOD falls off with distance from the observer. Chunks in
+/// `always_split` are split no matter how far away they are.
+///
+/// Chunk keys at LOD `n` are in LOD `n` voxel coordinates, where a voxel covers `2^n` LOD 0 voxels along each axis.
+pub fn select_clipmap_chunks(
+    chunk_shape: Point3i,
+    clip_radius: f32,
+    observer: Vec3,
+    top_lod: u8,
+    top_level_chunk_mins: impl Iterator<Item = Point3i>,
+    always_split: &SmallKeyHashSet<ChunkKey3>,
+) -> Vec<ChunkKey3> {
+    let chunk_width = chunk_shape.x().max(chunk_shape.y()).max(chunk_shape.z()) as f32;
+
+    let mut selected = Vec::new();
+    let mut stack: Vec<ChunkKey3> = top_level_chunk_mins
+        .map(|min| ChunkKey::new(top_lod, min))
+        .collect();
+    while let Some(chunk_key) = stack.pop() {
+        let scale = (1 << chunk_key.lod) as f32;
+        let min = point_to_vec3(chunk_key.minimum) * scale;
+        let max = min + point_to_vec3(chunk_shape) * scale;
+        let distance = observer.max(min).min(max).distance(observer);
+
+        if chunk_key.lod > 0
+            && (distance < clip_radius * chunk_width * scale || always_split.contains(&chunk_key))
+        {
+            let PointN([x, y, z]) = chunk_key.minimum;
+            for offset in Point3i::CUBE_CORNER_OFFSETS.iter() {
+                let child_min = PointN([
+                    2 * x + offset.x() * chunk_shape.x(),
+                    2 * y + offset.y() * chunk_shape.y(),
+                    2 * z + offset.z() * chunk_shape.z(),
+                ]);
+                stack.push(ChunkKey::new(chunk_key.lod - 1, child_min));
+            }
+        } else {
+            selected.push(chunk_key);
+        }
+    }
+
+    selected
+}
+
+fn point_to_vec3(p: Point3i) -> Vec3 {
+    Vec3::new(p.x() as f32, p.y() as f32, p.z() as f32)
+}
+
+// ████████╗███████╗███████╗████████╗
+// ╚══██╔══╝██╔════╝██╔════╝╚══██╔══╝
+//    ██║   █████╗  ███████╗   ██║
+//    ██║   ██╔══╝  ╚════██║   ██║
+//    ██║   ███████╗███████║   ██║
+//    ╚═╝   ╚══════╝╚══════╝   ╚═╝
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn near_chunks_are_split_and_far_chunks_are_not() {
+        let shape = PointN([16; 3]);
+        let near = PointN([0, 0, 0]);
+        let far = PointN([1600, 0, 0]);
+        let selected = select_clipmap_chunks(
+            shape,
+            1.0,
+            Vec3::new(1.0, 1.0, 1.0),
+            2,
+            vec![near, far].into_iter(),
+            &SmallKeyHashSet::default(),
+        );
+
+        // The far chunk stays at LOD 2.
+        assert!(selected.contains(&ChunkKey::new(2, far)));
+        // The observer's corner is split all the way down to LOD 0.
+        assert!(selected.contains(&ChunkKey::new(0, PointN([0, 0, 0]))));
+        // Every selected chunk from the near chunk covers a disjoint part of it, and together they cover all of it.
+        let covered_volume: i32 = selected
+            .iter()
+            .filter(|k| **k != ChunkKey::new(2, far))
+            .map(|k| {
+                let scale = 1 << k.lod;
+                scale * scale * scale * shape.x() * shape.y() * shape.z()
+            })
+            .sum();
+        assert_eq!(covered_volume, 64 * 64 * 64);
+    }
+
+    #[test]
+    fn always_split_chunks_are_split_when_far_away() {
+        let shape = PointN([16; 3]);
+        let far = PointN([1600, 0, 0]);
+        let always_split = vec![
+            ChunkKey::new(2, far),
+            ChunkKey::new(1, PointN([3200, 16, 0])),
+        ]
+        .into_iter()
+        .collect();
+        let selected = select_clipmap_chunks(
+            shape,
+            1.0,
+            Vec3::ZERO,
+            2,
+            vec![far].into_iter(),
+            &always_split,
+        );
+
+        assert!(!selected.contains(&ChunkKey::new(2, far)));
+        assert!(selected.contains(&ChunkKey::new(1, PointN([3200, 0, 0]))));
+        assert!(selected.contains(&ChunkKey::new(0, PointN([6400, 32, 0]))));
+        assert!(!selected.contains(&ChunkKey::new(1, PointN([3200, 16, 0]))));
+        assert_eq!(selected.len(), 7 + 8);
+    }
+}
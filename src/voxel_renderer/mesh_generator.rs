@@ -1,13 +1,17 @@
+use super::clipmap::select_clipmap_chunks;
+
 use crate::{
-    ambient_sdf_array,
+    ambient_sdf_array, ancestor_chunk_min,
+    camera::CursorRayCameraTag,
     voxel_renderer::{ArrayMaterial, MaterialLayer, MaterialVoxel, SmoothVoxelPbrBundle},
-    DirtyChunks, SdfVoxelMap, StatePlugin, ThreadLocalResource, ThreadLocalVoxelCache, VoxelType,
+    DirtyChunks, EditTransaction, LodChunks, LodConfig, SdfArray, SdfVoxelMap, StatePlugin,
+    ThreadLocalResource, ThreadLocalVoxelCache,
 };
 
 use building_blocks::{
     mesh::{surface_nets::*, PosNormMesh},
     prelude::*,
-    storage::Local,
+    storage::{Local, SmallKeyHashSet},
 };
 
 use bevy::{
@@ -28,6 +32,13 @@ pub struct MeshMaterial(pub Handle<ArrayMaterial>);
 
 /// Generates smooth meshes for voxel chunks. When a chunk becomes dirty, its old mesh is replaced with a newly generated one.
 ///
+/// Chunks are meshed at the level of detail picked by `select_clipmap_chunks` around the camera, so far away terrain is rendered
+/// from the coarser `LodChunks`. Neighboring chunks at different levels of detail aren't stitched together, so there can be small
+/// cracks between them.
+///
+/// LOD 0 chunks are meshed with the `EditTransaction` on top of the `SdfVoxelMap`, so uncommitted edits are previewed. Chunks
+/// with a preview are always rendered at LOD 0, however far away they are.
+///
 /// **NOTE**: Expects the `MeshMaterial` resource to exist before running.
 pub struct MeshGeneratorPlugin;
 
@@ -51,23 +62,100 @@ impl StatePlugin for MeshGeneratorPlugin {
 pub struct ChunkMeshes {
     // Map from chunk key to mesh entity.
     entities: SmallKeyHashMap<ChunkKey3, Entity>,
+    // The chunks that were selected for rendering, including those without any surface.
+    selected: SmallKeyHashSet<ChunkKey3>,
 }
 
-/// Generates new meshes for all dirty chunks.
+/// Selects the level of detail for each part of the map, then generates new meshes for all newly selected or dirty chunks.
 fn mesh_generator_system(
     mut commands: Commands,
     pool: Res<ComputeTaskPool>,
     voxel_map: Res<SdfVoxelMap>,
+    lod_chunks: Res<LodChunks>,
+    lod_config: Res<LodConfig>,
     dirty_chunks: Res<DirtyChunks>,
+    transaction: Res<EditTransaction>,
     local_caches: Res<ThreadLocalVoxelCache>,
     local_mesh_buffers: ecs::system::Local<ThreadLocalMeshBuffers>,
     mesh_material: Res<MeshMaterial>,
+    cameras: Query<&GlobalTransform, With<CursorRayCameraTag>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut chunk_meshes: ResMut<ChunkMeshes>,
 ) {
+    let observer = cameras
+        .iter()
+        .next()
+        .map_or(Vec3::ZERO, |tfm| tfm.translation);
+    let top_lod = lod_config.num_lods.saturating_sub(1);
+    let chunk_shape = voxel_map.voxels.chunk_shape();
+    let mut top_level_chunk_mins: SmallKeyHashSet<Point3i> = if top_lod == 0 {
+        voxel_map
+            .chunk_keys()
+            .into_iter()
+            .map(|k| k.minimum)
+            .collect()
+    } else {
+        lod_chunks.chunk_mins_at_lod(top_lod).collect()
+    };
+    // The transaction can preview chunks that don't exist yet, and its preview is only meshed at LOD 0, so every chunk above it
+    // gets split down to LOD 0.
+    let mut preview_ancestor_keys = SmallKeyHashSet::default();
+    for chunk_min in transaction.chunk_mins() {
+        for lod in 1..=top_lod {
+            preview_ancestor_keys.insert(ChunkKey::new(
+                lod,
+                ancestor_chunk_min(chunk_min, lod, chunk_shape),
+            ));
+        }
+        top_level_chunk_mins.insert(ancestor_chunk_min(chunk_min, top_lod, chunk_shape));
+    }
+    let selected: SmallKeyHashSet<ChunkKey3> = select_clipmap_chunks(
+        chunk_shape,
+        lod_config.clip_radius,
+        observer,
+        top_lod,
+        top_level_chunk_mins.into_iter(),
+        &preview_ancestor_keys,
+    )
+    .into_iter()
+    .collect();
+
+    let (chunks_to_mesh, chunks_without_surface): (Vec<ChunkKey3>, Vec<ChunkKey3>) = selected
+        .iter()
+        .filter(|k| {
+            let is_dirty = if k.lod == 0 {
+                dirty_chunks.dirty_chunk_mins.contains(&k.minimum)
+                    || transaction.dirty_preview_chunk_mins.contains(&k.minimum)
+            } else {
+                lod_chunks.dirty_chunk_keys.contains(k)
+            };
+
+            is_dirty || !chunk_meshes.selected.contains(k)
+        })
+        .cloned()
+        .partition(|k| k.lod > 0 || chunk_may_have_surface(&*voxel_map, &*transaction, k.minimum));
+
+    let ChunkMeshes {
+        entities,
+        selected: previously_selected,
+    } = &mut *chunk_meshes;
+    for chunk_key in previously_selected.difference(&selected) {
+        if let Some(old_mesh) = entities.remove(chunk_key) {
+            commands.entity(old_mesh).despawn();
+        }
+    }
+    *previously_selected = selected;
+    for chunk_key in chunks_without_surface.iter() {
+        if let Some(old_mesh) = entities.remove(chunk_key) {
+            commands.entity(old_mesh).despawn();
+        }
+    }
+
     let new_chunk_meshes = generate_mesh_for_each_chunk(
         &*voxel_map,
-        &*dirty_chunks,
+        &*lod_chunks,
+        &*transaction,
+        chunks_to_mesh,
         &*local_caches,
         &*local_mesh_buffers,
         &*pool,
@@ -81,6 +169,7 @@ fn mesh_generator_system(
                     .spawn_bundle(create_voxel_mesh_bundle(
                         mesh,
                         material_counts,
+                        chunk_key.lod,
                         mesh_material.0.clone(),
                         &mut *meshes,
                     ))
@@ -95,31 +184,59 @@ fn mesh_generator_system(
     }
 }
 
+/// False if every chunk that the padded extent of the LOD 0 chunk at `chunk_min` overlaps is uniform on the same side of the
+/// surface, so there's nothing to mesh. This avoids reading and meshing all of the empty and solid chunks near the camera.
+fn chunk_may_have_surface(
+    voxel_map: &SdfVoxelMap,
+    transaction: &EditTransaction,
+    chunk_min: Point3i,
+) -> bool {
+    let indexer = voxel_map.voxels.indexer;
+    let padded_chunk_extent =
+        padded_surface_nets_chunk_extent(&indexer.extent_for_chunk_with_min(chunk_min));
+    let mut inside = None;
+    for neighbor_min in indexer.chunk_mins_for_extent(&padded_chunk_extent) {
+        if transaction.contains_chunk(neighbor_min) {
+            return true;
+        }
+        let neighbor_inside = match voxel_map.known_uniform_value(ChunkKey::new(0, neighbor_min)) {
+            Some((_type, dist)) => dist.0 < 0,
+            None => return true,
+        };
+        if *inside.get_or_insert(neighbor_inside) != neighbor_inside {
+            return true;
+        }
+    }
+
+    false
+}
+
 fn generate_mesh_for_each_chunk(
     voxel_map: &SdfVoxelMap,
-    dirty_chunks: &DirtyChunks,
+    lod_chunks: &LodChunks,
+    transaction: &EditTransaction,
+    chunk_keys: Vec<ChunkKey3>,
     local_caches: &ThreadLocalVoxelCache,
     local_mesh_buffers: &ThreadLocalMeshBuffers,
     pool: &ComputeTaskPool,
 ) -> Vec<(ChunkKey3, Option<(PosNormMesh, Vec<[u8; 4]>)>)> {
     pool.scope(|s| {
-        for chunk_min in dirty_chunks.dirty_chunk_mins.iter().cloned() {
-            let chunk_key = ChunkKey::new(0, chunk_min);
+        for chunk_key in chunk_keys.into_iter() {
             s.spawn(async move {
                 let cache_tls = local_caches.get();
                 let reader = voxel_map.reader(&cache_tls);
 
                 let padded_chunk_extent = padded_surface_nets_chunk_extent(
-                    &reader.indexer.extent_for_chunk_with_min(chunk_min),
+                    &reader
+                        .voxels
+                        .indexer
+                        .extent_for_chunk_with_min(chunk_key.minimum),
                 );
 
                 let mesh_tls = local_mesh_buffers.get();
                 let mut mesh_buffers = mesh_tls
                     .get_or_create_with(|| {
-                        RefCell::new(MeshBuffers {
-                            padded_chunk: ambient_sdf_array(padded_chunk_extent),
-                            surface_nets_buffer: Default::default(),
-                        })
+                        RefCell::new(MeshBuffers::new(reader.voxels.indexer.chunk_shape()))
                     })
                     .borrow_mut();
 
@@ -130,36 +247,65 @@ fn generate_mesh_for_each_chunk(
 
                 padded_chunk.set_minimum(padded_chunk_extent.minimum);
 
-                copy_extent(&padded_chunk_extent, &reader.lod_view(0), padded_chunk);
-
-                let padded_sdf_chunk = TransformMap::new(padded_chunk, |(_type, dist)| dist);
-
-                surface_nets(
-                    &padded_sdf_chunk,
-                    &padded_chunk_extent,
-                    1.0,
-                    &mut *surface_nets_buffer,
-                );
-
-                if surface_nets_buffer.mesh.indices.is_empty() {
-                    (chunk_key, None)
+                if chunk_key.lod == 0 {
+                    reader.copy_extent_into(&padded_chunk_extent, padded_chunk);
+                    transaction.copy_preview(padded_chunk);
                 } else {
-                    // Count materials adjacent to each vertex for texture blending.
-                    let info_map =
-                        TransformMap::new(padded_chunk, voxel_map.voxel_info_transform());
-                    let material_counts =
-                        count_adjacent_materials(&info_map, &surface_nets_buffer.surface_strides);
-
-                    (
-                        chunk_key,
-                        Some((surface_nets_buffer.mesh.clone(), material_counts)),
-                    )
+                    copy_extent(
+                        &padded_chunk_extent,
+                        &lod_chunks.chunks.lod_view(chunk_key.lod),
+                        padded_chunk,
+                    );
                 }
+
+                (
+                    chunk_key,
+                    mesh_padded_chunk(
+                        voxel_map,
+                        padded_chunk,
+                        lod_voxel_size(chunk_key.lod),
+                        surface_nets_buffer,
+                    ),
+                )
             })
         }
     })
 }
 
+/// The width of a voxel at `lod`, in LOD 0 voxels.
+pub fn lod_voxel_size(lod: u8) -> f32 {
+    (1 << lod) as f32
+}
+
+/// Generates a mesh for the chunk whose padded extent is covered by `padded_chunk`, along with the number of adjacent voxels of
+/// each material for every vertex. Positions are scaled by `voxel_size`. Returns `None` if there is no surface in the chunk.
+pub fn mesh_padded_chunk(
+    voxel_map: &SdfVoxelMap,
+    padded_chunk: &SdfArray,
+    voxel_size: f32,
+    surface_nets_buffer: &mut SurfaceNetsBuffer,
+) -> Option<(PosNormMesh, Vec<[u8; 4]>)> {
+    let padded_chunk_extent = *padded_chunk.extent();
+    let padded_sdf_chunk = TransformMap::new(padded_chunk, |(_type, dist)| dist);
+
+    surface_nets(
+        &padded_sdf_chunk,
+        &padded_chunk_extent,
+        voxel_size,
+        &mut *surface_nets_buffer,
+    );
+
+    if surface_nets_buffer.mesh.indices.is_empty() {
+        return None;
+    }
+
+    // Count materials adjacent to each vertex for texture blending.
+    let info_map = TransformMap::new(padded_chunk, voxel_map.voxel_info_transform());
+    let material_counts = count_adjacent_materials(&info_map, &surface_nets_buffer.surface_strides);
+
+    Some((surface_nets_buffer.mesh.clone(), material_counts))
+}
+
 /// Uses a kernel to count the adjacent materials for each surface point. This is necessary because we used dual contouring to
 /// construct the mesh, so a given vertex has 8 adjacent voxels, some of which may be empty. This also assumes that the material
 /// layer can only be one of 0..4.
@@ -194,13 +340,28 @@ where
 type ThreadLocalMeshBuffers = ThreadLocalResource<RefCell<MeshBuffers>>;
 
 pub struct MeshBuffers {
-    surface_nets_buffer: SurfaceNetsBuffer,
-    padded_chunk: Array3x2<VoxelType, Sd8>,
+    pub surface_nets_buffer: SurfaceNetsBuffer,
+    pub padded_chunk: SdfArray,
+}
+
+impl MeshBuffers {
+    pub fn new(chunk_shape: Point3i) -> Self {
+        let padded_chunk_extent = padded_surface_nets_chunk_extent(&Extent3i::from_min_and_shape(
+            PointN([0; 3]),
+            chunk_shape,
+        ));
+
+        Self {
+            padded_chunk: ambient_sdf_array(padded_chunk_extent),
+            surface_nets_buffer: Default::default(),
+        }
+    }
 }
 
 fn create_voxel_mesh_bundle(
     mesh: PosNormMesh,
     material_counts: Vec<[u8; 4]>,
+    lod: u8,
     material: Handle<ArrayMaterial>,
     meshes: &mut Assets<Mesh>,
 ) -> SmoothVoxelPbrBundle {
@@ -226,9 +387,13 @@ fn create_voxel_mesh_bundle(
     );
     render_mesh.set_indices(Some(Indices::U32(mesh.indices)));
 
+    // A voxel at a coarser LOD is sampled at the center of the LOD 0 voxels it covers, not at its minimum.
+    let lod_offset = (lod_voxel_size(lod) - 1.0) / 2.0;
+
     SmoothVoxelPbrBundle {
         mesh: meshes.add(render_mesh),
         material,
+        transform: Transform::from_translation(Vec3::splat(lod_offset)),
         ..Default::default()
     }
 }
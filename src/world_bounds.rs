@@ -0,0 +1,121 @@
+use crate::{ImmediateModeTag, SdfVoxelMap, StatePlugin};
+
+use bevy::{
+    asset::prelude::*,
+    ecs::prelude::*,
+    pbr::prelude::*,
+    render::{
+        mesh::{Indices, VertexAttributeValues},
+        pipeline::PrimitiveTopology,
+        prelude::*,
+    },
+};
+use building_blocks::core::prelude::*;
+
+/// Draws a wireframe box around the world bounds of the `SdfVoxelMap`, if it has any.
+pub struct WorldBoundsPlugin;
+
+impl WorldBoundsPlugin {
+    fn initialize(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+        let material = materials.add(StandardMaterial {
+            base_color: Color::ORANGE,
+            unlit: true,
+            ..Default::default()
+        });
+        commands.insert_resource(WorldBoundsView {
+            material,
+            mesh: None,
+        });
+    }
+}
+
+impl StatePlugin for WorldBoundsPlugin {
+    fn add_enter_systems(set: SystemSet) -> SystemSet {
+        set.with_system(Self::initialize.system())
+    }
+
+    fn add_update_systems(set: SystemSet) -> SystemSet {
+        set.with_system(world_bounds_view_system.system())
+    }
+}
+
+struct WorldBoundsView {
+    material: Handle<StandardMaterial>,
+    /// The box for the bounds that it was built from, so it's only rebuilt when the bounds change.
+    mesh: Option<(Extent3i, Handle<Mesh>)>,
+}
+
+fn world_bounds_view_system(
+    mut commands: Commands,
+    voxel_map: Res<SdfVoxelMap>,
+    mut view: ResMut<WorldBoundsView>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let bounds = if let Some(b) = voxel_map.bounds {
+        b
+    } else {
+        view.mesh = None;
+        return;
+    };
+
+    let mesh = match &view.mesh {
+        Some((built_bounds, mesh)) if *built_bounds == bounds => mesh.clone(),
+        _ => {
+            let mesh = meshes.add(wireframe_box_mesh(&bounds));
+            view.mesh = Some((bounds, mesh.clone()));
+            mesh
+        }
+    };
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh,
+            material: view.material.clone(),
+            ..Default::default()
+        })
+        .insert(ImmediateModeTag);
+}
+
+/// The 12 edges of the box that encloses every voxel of `extent`.
+fn wireframe_box_mesh(extent: &Extent3i) -> Mesh {
+    let min: [f32; 3] = Point3f::from(extent.minimum).0;
+    let max: [f32; 3] = Point3f::from(extent.least_upper_bound()).0;
+    let positions: Vec<[f32; 3]> = (0..8)
+        .map(|corner| {
+            let mut p = min;
+            for (axis, c) in p.iter_mut().enumerate() {
+                if corner & (1 << axis) != 0 {
+                    *c = max[axis];
+                }
+            }
+
+            p
+        })
+        .collect();
+    // Connect every pair of corners that differ along exactly one axis.
+    let mut indices = Vec::with_capacity(24);
+    for corner in 0..8u32 {
+        for axis in 0..3 {
+            let other = corner | (1 << axis);
+            if other != corner {
+                indices.extend_from_slice(&[corner, other]);
+            }
+        }
+    }
+
+    let num_vertices = positions.len();
+    let mut mesh = Mesh::new(PrimitiveTopology::LineList);
+    mesh.set_attribute("Vertex_Position", VertexAttributeValues::Float3(positions));
+    // HACK: We have to provide normals and UVs, even though the unlit material doesn't use them.
+    mesh.set_attribute(
+        "Vertex_Normal",
+        VertexAttributeValues::Float3(vec![[0.0, 1.0, 0.0]; num_vertices]),
+    );
+    mesh.set_attribute(
+        "Vertex_Uv",
+        VertexAttributeValues::Float2(vec![[0.0; 2]; num_vertices]),
+    );
+    mesh.set_indices(Some(Indices::U32(indices)));
+
+    mesh
+}
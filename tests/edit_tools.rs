@@ -0,0 +1,468 @@
+use building_blocks_editor::{
+    EditTransaction, HeadlessEditor, Ray3, SdfArray, SdfVoxelMap, SdfVoxelPalette, SelectionState,
+    VoxelMaterial, VoxelType, VoxelTypeInfo, DEFAULT_CHUNK_SHAPE, EMPTY_SDF_VOXEL,
+};
+
+use bevy::{input::prelude::*, math::prelude::*};
+use building_blocks::prelude::*;
+
+const SOLID_VOXEL: (VoxelType, Sd8) = (VoxelType(2), Sd8::NEG_ONE);
+
+fn test_palette() -> SdfVoxelPalette {
+    let mut infos = vec![VoxelTypeInfo {
+        is_empty: true,
+        material: VoxelMaterial::NULL,
+    }];
+    infos.extend((0..3).map(|i| VoxelTypeInfo {
+        is_empty: false,
+        material: VoxelMaterial(i),
+    }));
+
+    SdfVoxelPalette { infos }
+}
+
+fn tap_key(editor: &mut HeadlessEditor, key: KeyCode) {
+    editor.press_key(key);
+    editor.step();
+    editor.release_key(key);
+    editor.step_frames(2);
+}
+
+fn click(editor: &mut HeadlessEditor) {
+    editor.press_mouse_button(MouseButton::Left);
+    editor.step();
+    editor.release_mouse_button(MouseButton::Left);
+    editor.step_frames(2);
+}
+
+/// Points the cursor straight down at the top of the voxel column at `(x, z)`.
+fn look_down_at(editor: &mut HeadlessEditor, x: i32, z: i32) {
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(x as f32 + 0.5, 20.0, z as f32 + 0.5),
+        Vec3::new(0.0, -1.0, 0.0),
+    )));
+    editor.step_frames(2);
+}
+
+/// A solid slab whose top face is at `y = 7`, with the quad from `(2, 7, 2)` to `(5, 7, 5)` selected, and the top face of
+/// `(3, 7, 3)` being dragged up to `y = 10`.
+fn start_dragging_slab() -> HeadlessEditor {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([0; 3]), PointN([16, 8, 16])),
+        SOLID_VOXEL,
+    );
+    editor.step_frames(3);
+    start_dragging(&mut editor, (2, 2), (5, 5), (3, 3), 10);
+
+    editor
+}
+
+/// Selects the top faces in the quad between the columns at `corner1` and `corner2`, and starts dragging the quad up from the
+/// column at `grab` to `y`.
+fn start_dragging(
+    editor: &mut HeadlessEditor,
+    corner1: (i32, i32),
+    corner2: (i32, i32),
+    grab: (i32, i32),
+    y: i32,
+) {
+    look_down_at(editor, corner1.0, corner1.1);
+    click(editor);
+    look_down_at(editor, corner2.0, corner2.1);
+    click(editor);
+
+    look_down_at(editor, grab.0, grab.1);
+    editor.press_mouse_button(MouseButton::Left);
+    editor.step();
+    // The drag follows the closest point on the face's normal axis to this ray.
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(-20.0, y as f32 + 0.5, grab.1 as f32 + 0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+    )));
+    editor.step_frames(3);
+}
+
+fn transaction_is_empty(editor: &HeadlessEditor) -> bool {
+    editor
+        .world()
+        .get_resource::<EditTransaction>()
+        .unwrap()
+        .is_empty()
+}
+
+/// The voxel at `p` as it's previewed, with the uncommitted `EditTransaction` over the map.
+fn preview_voxel(editor: &HeadlessEditor, p: Point3i) -> (VoxelType, Sd8) {
+    let mut preview = SdfArray::fill(
+        Extent3i::from_min_and_shape(p, PointN([1; 3])),
+        editor.voxel(p),
+    );
+    editor
+        .world()
+        .get_resource::<EditTransaction>()
+        .unwrap()
+        .copy_preview(&mut preview);
+
+    preview.get(p)
+}
+
+#[test]
+fn terraformer_makes_solid_and_undo_removes_it() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.step();
+
+    // The terraformer edits 20 voxels in front of the camera when the cursor isn't on any voxels.
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(0.5, 0.5, -20.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    )));
+    tap_key(&mut editor, KeyCode::T);
+
+    editor.press_key(KeyCode::Z);
+    editor.step_frames(10);
+    editor.release_key(KeyCode::Z);
+    editor.step_frames(2);
+
+    let (center_type, center_dist) = editor.voxel(PointN([0; 3]));
+    assert_eq!(center_type, VoxelType(1));
+    assert!(center_dist.0 < 0);
+
+    tap_key(&mut editor, KeyCode::U);
+    assert_eq!(editor.voxel(PointN([0; 3])), EMPTY_SDF_VOXEL);
+}
+
+#[test]
+fn terraformer_cube_brush_fills_past_the_sphere() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.step();
+
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(0.5, 0.5, -20.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    )));
+    tap_key(&mut editor, KeyCode::T);
+    tap_key(&mut editor, KeyCode::Right);
+
+    editor.press_key(KeyCode::Z);
+    editor.step_frames(30);
+    editor.release_key(KeyCode::Z);
+    editor.step_frames(2);
+
+    // This corner is outside of a sphere with the default radius of 10, but inside of the cube.
+    let (corner_type, corner_dist) = editor.voxel(PointN([6, 6, 6]));
+    assert_eq!(corner_type, VoxelType(1));
+    assert!(corner_dist.0 < 0);
+    assert_eq!(editor.voxel(PointN([11, 0, 0])), EMPTY_SDF_VOXEL);
+}
+
+#[test]
+fn terraformer_is_clipped_to_world_bounds() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    // Everything at x < 1 is out of bounds.
+    let bounds = Extent3i::from_min_and_shape(PointN([1, -16, -16]), PointN([32; 3]));
+    editor
+        .world_mut()
+        .get_resource_mut::<SdfVoxelMap>()
+        .unwrap()
+        .bounds = Some(bounds);
+    editor.step();
+
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(0.5, 0.5, -20.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    )));
+    tap_key(&mut editor, KeyCode::T);
+    editor.press_key(KeyCode::Z);
+    editor.step_frames(10);
+    editor.release_key(KeyCode::Z);
+    editor.step_frames(2);
+
+    assert_eq!(editor.voxel(PointN([0; 3])), EMPTY_SDF_VOXEL);
+    assert_eq!(editor.voxel(PointN([1, 0, 0])).0, VoxelType(1));
+}
+
+#[test]
+fn drag_face_previews_until_released() {
+    let mut editor = start_dragging_slab();
+
+    assert!(!transaction_is_empty(&editor));
+    assert_eq!(editor.voxel(PointN([3, 9, 3])), EMPTY_SDF_VOXEL);
+
+    editor.release_mouse_button(MouseButton::Left);
+    editor.step_frames(3);
+
+    assert!(transaction_is_empty(&editor));
+    for y in 8..10 {
+        assert_eq!(editor.voxel(PointN([3, y, 3])), SOLID_VOXEL);
+        assert_eq!(editor.voxel(PointN([5, y, 5])), SOLID_VOXEL);
+    }
+    // Outside of the selected quad.
+    assert_eq!(editor.voxel(PointN([1, 9, 1])), EMPTY_SDF_VOXEL);
+    assert_eq!(editor.voxel(PointN([6, 9, 6])), EMPTY_SDF_VOXEL);
+
+    tap_key(&mut editor, KeyCode::U);
+    assert_eq!(editor.voxel(PointN([3, 9, 3])), EMPTY_SDF_VOXEL);
+    assert_eq!(editor.voxel(PointN([3, 7, 3])), SOLID_VOXEL);
+}
+
+#[test]
+fn escape_cancels_drag_face() {
+    let mut editor = start_dragging_slab();
+
+    tap_key(&mut editor, KeyCode::Escape);
+    editor.release_mouse_button(MouseButton::Left);
+    editor.step_frames(3);
+
+    assert!(transaction_is_empty(&editor));
+    assert_eq!(editor.voxel(PointN([3, 9, 3])), EMPTY_SDF_VOXEL);
+    assert_eq!(editor.voxel(PointN([3, 7, 3])), SOLID_VOXEL);
+}
+
+fn selected_volume(editor: &HeadlessEditor) -> Option<Extent3i> {
+    editor
+        .world()
+        .get_resource::<SelectionState>()
+        .unwrap()
+        .selected_volume()
+}
+
+#[test]
+fn shift_dragging_the_selected_quad_makes_a_box() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([0; 3]), PointN([16, 8, 16])),
+        SOLID_VOXEL,
+    );
+    editor.step_frames(3);
+
+    look_down_at(&mut editor, 2, 2);
+    click(&mut editor);
+    look_down_at(&mut editor, 5, 5);
+    click(&mut editor);
+
+    look_down_at(&mut editor, 3, 3);
+    editor.press_key(KeyCode::LShift);
+    editor.press_mouse_button(MouseButton::Left);
+    editor.step();
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(-20.0, 10.2, 3.5),
+        Vec3::new(1.0, 0.0, 0.0),
+    )));
+    editor.step_frames(3);
+    editor.release_mouse_button(MouseButton::Left);
+    editor.release_key(KeyCode::LShift);
+    editor.step_frames(2);
+
+    assert_eq!(
+        selected_volume(&editor),
+        Some(Extent3i::from_min_and_max(
+            PointN([2, 7, 2]),
+            PointN([5, 9, 5])
+        ))
+    );
+    // Sizing the selection doesn't edit any voxels.
+    assert!(transaction_is_empty(&editor));
+    assert_eq!(editor.voxel(PointN([3, 9, 3])), EMPTY_SDF_VOXEL);
+
+    // Drag the west face of the box out by 2 voxels.
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(-20.0, 8.5, 3.5),
+        Vec3::new(1.0, 0.0, 0.0),
+    )));
+    editor.step();
+    editor.press_mouse_button(MouseButton::Left);
+    editor.step();
+    editor.set_cursor_ray(Some(Ray3::new(
+        Vec3::new(0.1, 20.0, 3.5),
+        Vec3::new(0.0, -1.0, 0.0),
+    )));
+    editor.step_frames(3);
+    editor.release_mouse_button(MouseButton::Left);
+    editor.step_frames(2);
+
+    assert_eq!(
+        selected_volume(&editor),
+        Some(Extent3i::from_min_and_max(
+            PointN([0, 7, 2]),
+            PointN([5, 9, 5])
+        ))
+    );
+
+    tap_key(&mut editor, KeyCode::Escape);
+    assert_eq!(selected_volume(&editor), None);
+}
+
+#[test]
+fn copied_voxels_are_pasted_on_the_clicked_face_and_undone() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([0; 3]), PointN([16, 8, 16])),
+        SOLID_VOXEL,
+    );
+    editor.step_frames(3);
+
+    look_down_at(&mut editor, 2, 2);
+    click(&mut editor);
+    look_down_at(&mut editor, 3, 3);
+    click(&mut editor);
+
+    editor.press_key(KeyCode::LControl);
+    tap_key(&mut editor, KeyCode::C);
+    tap_key(&mut editor, KeyCode::V);
+    editor.release_key(KeyCode::LControl);
+    editor.step();
+
+    // The 2x1x2 layer under the selected quad is pasted on top of the face at (10, 7, 10).
+    look_down_at(&mut editor, 10, 10);
+    click(&mut editor);
+    for p in [PointN([9, 8, 9]), PointN([10, 8, 10])].iter() {
+        assert_eq!(editor.voxel(*p), SOLID_VOXEL);
+    }
+    assert_eq!(editor.voxel(PointN([11, 8, 11])), EMPTY_SDF_VOXEL);
+    assert_eq!(editor.voxel(PointN([10, 9, 10])), EMPTY_SDF_VOXEL);
+
+    tap_key(&mut editor, KeyCode::U);
+    assert_eq!(editor.voxel(PointN([10, 8, 10])), EMPTY_SDF_VOXEL);
+}
+
+#[test]
+fn paint_material_changes_only_surface_voxel_types() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([0; 3]), PointN([16, 8, 16])),
+        SOLID_VOXEL,
+    );
+    editor.step_frames(3);
+
+    tap_key(&mut editor, KeyCode::B);
+    tap_key(&mut editor, KeyCode::Key1);
+    look_down_at(&mut editor, 8, 8);
+    tap_key(&mut editor, KeyCode::Z);
+
+    // The top layer is painted, but keeps its distances, and the voxels below it are untouched.
+    assert_eq!(
+        editor.voxel(PointN([8, 7, 8])),
+        (VoxelType(1), SOLID_VOXEL.1)
+    );
+    assert_eq!(
+        editor.voxel(PointN([10, 7, 8])),
+        (VoxelType(1), SOLID_VOXEL.1)
+    );
+    assert_eq!(editor.voxel(PointN([8, 6, 8])), SOLID_VOXEL);
+    assert_eq!(editor.voxel(PointN([8, 8, 8])), EMPTY_SDF_VOXEL);
+
+    tap_key(&mut editor, KeyCode::U);
+    assert_eq!(editor.voxel(PointN([8, 7, 8])), SOLID_VOXEL);
+}
+
+#[test]
+fn tiling_repeats_the_clipboard_and_undoes_as_one_edit() {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([0; 3]), PointN([16, 8, 16])),
+        SOLID_VOXEL,
+    );
+    editor.step_frames(3);
+
+    // Copy a single voxel.
+    look_down_at(&mut editor, 2, 2);
+    click(&mut editor);
+    click(&mut editor);
+    editor.press_key(KeyCode::LControl);
+    tap_key(&mut editor, KeyCode::C);
+    editor.release_key(KeyCode::LControl);
+    tap_key(&mut editor, KeyCode::G);
+
+    look_down_at(&mut editor, 8, 8);
+    editor.press_mouse_button(MouseButton::Left);
+    editor.step();
+    look_down_at(&mut editor, 11, 8);
+
+    assert!(!transaction_is_empty(&editor));
+    assert_eq!(editor.voxel(PointN([11, 8, 8])), EMPTY_SDF_VOXEL);
+
+    editor.release_mouse_button(MouseButton::Left);
+    editor.step_frames(3);
+
+    assert!(transaction_is_empty(&editor));
+    for x in 8..12 {
+        assert_eq!(editor.voxel(PointN([x, 8, 8])), SOLID_VOXEL);
+    }
+    assert_eq!(editor.voxel(PointN([12, 8, 8])), EMPTY_SDF_VOXEL);
+    assert_eq!(editor.voxel(PointN([8, 8, 9])), EMPTY_SDF_VOXEL);
+
+    tap_key(&mut editor, KeyCode::U);
+    for x in 8..12 {
+        assert_eq!(editor.voxel(PointN([x, 8, 8])), EMPTY_SDF_VOXEL);
+    }
+}
+
+/// A solid slab whose top face is at `y = 7`, with a step up to `y = 11` for `x >= 8`, and a ramp from `x = 2` to `x = 8` over
+/// `2 <= z <= 4` being previewed with the Slope tool.
+fn start_slope_preview() -> HeadlessEditor {
+    let mut editor = HeadlessEditor::new(DEFAULT_CHUNK_SHAPE, test_palette());
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([0; 3]), PointN([16, 8, 16])),
+        SOLID_VOXEL,
+    );
+    // A step that's 4 voxels higher than the slab.
+    editor.fill_extent(
+        Extent3i::from_min_and_shape(PointN([8, 8, 0]), PointN([8, 4, 16])),
+        SOLID_VOXEL,
+    );
+    editor.step_frames(3);
+
+    tap_key(&mut editor, KeyCode::L);
+    for (x, z) in [(2, 2), (2, 4), (8, 2), (8, 4)].iter() {
+        look_down_at(&mut editor, *x, *z);
+        click(&mut editor);
+    }
+
+    editor
+}
+
+#[test]
+fn slope_fills_a_ramp_between_two_edges_after_confirming() {
+    let mut editor = start_slope_preview();
+
+    // Halfway up, the ramp is 2 voxels above the slab. It's only a preview until it's confirmed.
+    assert!(!transaction_is_empty(&editor));
+    assert_eq!(editor.voxel(PointN([5, 9, 3])), EMPTY_SDF_VOXEL);
+    assert!(preview_voxel(&editor, PointN([5, 9, 3])).1 .0 < 0);
+    assert!(preview_voxel(&editor, PointN([5, 10, 3])).1 .0 > 0);
+
+    tap_key(&mut editor, KeyCode::Return);
+    assert!(transaction_is_empty(&editor));
+    assert!(editor.voxel(PointN([5, 9, 3])).1 .0 < 0);
+
+    tap_key(&mut editor, KeyCode::U);
+    assert_eq!(editor.voxel(PointN([5, 9, 3])), EMPTY_SDF_VOXEL);
+}
+
+#[test]
+fn switching_tools_throws_away_the_slope_preview() {
+    let mut editor = start_slope_preview();
+
+    tap_key(&mut editor, KeyCode::D);
+    assert!(transaction_is_empty(&editor));
+
+    // Dragging a face in the same chunk only commits the drag.
+    start_dragging(&mut editor, (2, 10), (3, 12), (2, 11), 9);
+    editor.release_mouse_button(MouseButton::Left);
+    editor.step_frames(3);
+
+    assert_eq!(editor.voxel(PointN([2, 8, 11])), SOLID_VOXEL);
+    assert_eq!(editor.voxel(PointN([5, 9, 3])), EMPTY_SDF_VOXEL);
+}
+
+#[test]
+fn undo_throws_away_the_slope_preview() {
+    let mut editor = start_slope_preview();
+
+    tap_key(&mut editor, KeyCode::U);
+    assert!(transaction_is_empty(&editor));
+
+    // The Slope tool starts over, so confirming does nothing.
+    tap_key(&mut editor, KeyCode::Return);
+    assert_eq!(editor.voxel(PointN([5, 9, 3])), EMPTY_SDF_VOXEL);
+}